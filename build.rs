@@ -52,8 +52,7 @@ fn main() -> Result<()> {
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
+    let paths_to_copy = vec!["res/"];
     copy_items(&paths_to_copy, out_dir, &copy_options)?;
 
     // Collect all shaders recursively within /src/
@@ -88,14 +87,20 @@ fn main() -> Result<()> {
         let mut validator =
             naga::valid::Validator::new(ValidationFlags::all(), Capabilities::all());
 
-        let mut options = Options::from(shader.kind);
+        let options = Options::from(shader.kind);
 
-        let mut module = parser.parse(&options, &shader.src).unwrap();
-        let mut module_info = validator.validate(&module).unwrap();
+        let module = parser.parse(&options, &shader.src).unwrap();
+        let module_info = validator.validate(&module).unwrap();
 
-        let spv =
-            naga::back::spv::write_vec(&module, &module_info, &naga::back::spv::Options{flags: naga::back::spv::WriterFlags::empty(), ..Default::default()})
-                .unwrap();
+        let spv = naga::back::spv::write_vec(
+            &module,
+            &module_info,
+            &naga::back::spv::Options {
+                flags: naga::back::spv::WriterFlags::empty(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let bytes = spv
             .iter()
@@ -107,5 +112,27 @@ fn main() -> Result<()> {
         write(shader.spv_path, bytes.as_slice())?;
     }
 
+    // WGSL shaders are loaded as-is at runtime, but validate them here so
+    // mistakes show up at build time instead of when the pipeline is created.
+    for wgsl_path in glob("./shaders/**/*.wgsl")? {
+        let wgsl_path = wgsl_path?;
+        println!(
+            "cargo:rerun-if-changed={}",
+            wgsl_path.as_os_str().to_str().unwrap()
+        );
+
+        let src = read_to_string(&wgsl_path)?;
+        let module = match naga::front::wgsl::parse_str(&src) {
+            Ok(module) => module,
+            Err(e) => {
+                e.emit_to_stderr(&src);
+                bail!("Failed to parse {}", wgsl_path.display());
+            }
+        };
+        naga::valid::Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .with_context(|| format!("Failed to validate {}", wgsl_path.display()))?;
+    }
+
     Ok(())
 }
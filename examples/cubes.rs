@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use bitter_engine::{
+    bloom::BloomOptions,
+    exposure::AutoExposureOptions,
+    lighting::Light,
+    scene::{NodeContent, NodeId, Transform},
+    sky::{AtmosphereOptions, Sky},
+    ssao::SsaoOptions,
+    taa::TaaOptions,
+    AntiAliasing, App, Engine, EngineOptions, ShadingPath,
+};
+use cgmath::{EuclideanSpace, InnerSpace, Rotation3, Zero};
+
+const NUM_INSTANCES_PER_ROW: u32 = 10;
+const SPACE_BETWEEN: f32 = 3.0;
+const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
+    NUM_INSTANCES_PER_ROW as f32 * 0.5,
+    0.0,
+    NUM_INSTANCES_PER_ROW as f32 * 0.5,
+);
+
+#[derive(Default)]
+struct Cubes {
+    light_pivot: Option<NodeId>,
+}
+
+impl App for Cubes {
+    fn init(&mut self, engine: &mut Engine) -> anyhow::Result<()> {
+        let res_dir = std::path::Path::new(env!("OUT_DIR")).join("res");
+        let obj_model = engine.load_model(res_dir.join("cube.obj"))?;
+        let cube = engine.scene.add_model(obj_model);
+        engine.scene.light_gizmo = Some(cube);
+        engine.tonemap.auto_exposure = Some(AutoExposureOptions::default());
+        engine.bloom = Some(BloomOptions::default());
+        engine.anti_aliasing = AntiAliasing::Taa(TaaOptions::default());
+        engine.ssao = Some(SsaoOptions::default());
+
+        // The cubes sink into the ground a little, which SSAO darkens.
+        let ground = engine.load_model(res_dir.join("plane.obj"))?;
+        let ground = engine.scene.add_model(ground);
+        engine.scene.add_node(
+            None,
+            "ground",
+            Transform::from_translation(cgmath::Vector3::new(0.0, -1.5, 0.0)),
+            NodeContent::Model(ground),
+        );
+
+        // The light hangs off a pivot node, so rotating the pivot orbits the light.
+        let light_position = cgmath::Point3::new(2.0, 5.0, 2.0);
+        let light = engine.scene.add_light(Light::point(light_position));
+        let light_pivot = engine.scene.add_node(
+            None,
+            "light pivot",
+            Transform::default(),
+            NodeContent::Empty,
+        );
+        engine.scene.add_node(
+            Some(light_pivot),
+            "light",
+            Transform::from_translation(light_position.to_vec()),
+            NodeContent::Light(light),
+        );
+        self.light_pivot = Some(light_pivot);
+
+        engine.scene.add_light(Light {
+            color: [1.0, 0.3, 0.1],
+            intensity: 2.0,
+            range: 20.0,
+            ..Light::spot(
+                cgmath::Point3::new(-6.0, 6.0, -6.0),
+                cgmath::Vector3::new(0.0, -1.0, 0.0),
+                cgmath::Deg(20.0),
+                cgmath::Deg(35.0),
+            )
+        });
+        // The sky's sun steers this light and tints it.
+        let sun = engine.scene.add_light(Light {
+            intensity: 0.3,
+            ..Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0))
+        });
+        engine.sky = Some(Sky::Atmosphere(AtmosphereOptions {
+            sun_direction: cgmath::Vector3::new(1.0, 2.0, -1.0),
+            sun_light: Some(sun),
+            ..Default::default()
+        }));
+
+        for z in 0..NUM_INSTANCES_PER_ROW {
+            for x in 0..NUM_INSTANCES_PER_ROW {
+                let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+                let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+
+                let position = cgmath::Vector3 { x, y: 0.0, z } - INSTANCE_DISPLACEMENT;
+
+                let rotation = if position.is_zero() {
+                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+
+                engine.scene.add_node(
+                    None,
+                    "cube",
+                    Transform {
+                        translation: position,
+                        rotation,
+                        ..Default::default()
+                    },
+                    NodeContent::Model(cube),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update(&mut self, engine: &mut Engine, _dt: Duration) {
+        if let Some(light_pivot) = self.light_pivot {
+            let pivot = engine.scene.transform_mut(light_pivot);
+            pivot.rotation = cgmath::Quaternion::from_angle_y(cgmath::Deg(1.0)) * pivot.rotation;
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+    // The deferred path can't be multisampled, and leaves it to TAA.
+    let options = if std::env::args().any(|arg| arg == "--deferred") {
+        EngineOptions {
+            shading_path: ShadingPath::Deferred,
+            ..Default::default()
+        }
+    } else {
+        EngineOptions {
+            sample_count: 4,
+            ..Default::default()
+        }
+    };
+    bitter_engine::run(Cubes::default(), options);
+}
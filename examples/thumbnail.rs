@@ -0,0 +1,66 @@
+//! Renders an OBJ or glTF model without opening a window and writes the frame to a PNG.
+//!
+//! cargo run --example thumbnail -- res/cube.obj cube.png [--fallback]
+
+use anyhow::Context;
+use bitter_engine::{
+    lighting::Light,
+    scene::{NodeContent, Transform},
+    Engine, EngineOptions,
+};
+use cgmath::Rotation3;
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let model_path = args.next().context("Missing model path")?;
+    let output_path = args.next().context("Missing output path")?;
+    let force_fallback_adapter = args.any(|arg| arg == "--fallback");
+
+    let options = EngineOptions {
+        force_fallback_adapter,
+        ..Default::default()
+    };
+    let mut engine = pollster::block_on(Engine::new_headless(
+        THUMBNAIL_SIZE,
+        THUMBNAIL_SIZE,
+        &options,
+    ))?;
+
+    let root = engine.scene.add_node(
+        None,
+        "model",
+        Transform {
+            rotation: cgmath::Quaternion::from_angle_y(cgmath::Deg(30.0)),
+            ..Default::default()
+        },
+        NodeContent::Empty,
+    );
+    let is_gltf = model_path.ends_with(".gltf") || model_path.ends_with(".glb");
+    if is_gltf {
+        let gltf = engine.load_gltf(&model_path)?;
+        engine.scene.add_gltf(gltf, Some(root));
+    } else {
+        let model = engine.load_model(&model_path)?;
+        let model = engine.scene.add_model(model);
+        engine.scene.add_node(
+            Some(root),
+            "mesh",
+            Transform::default(),
+            NodeContent::Model(model),
+        );
+    }
+    engine
+        .scene
+        .add_light(Light::point(cgmath::Point3::new(2.0, 5.0, 2.0)));
+
+    // A single frame, with nothing to adapt over.
+    engine.update(std::time::Duration::ZERO);
+    engine.render()?;
+    engine.save_frame(output_path)?;
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// Builds a log-luminance histogram of the HDR frame, then adapts the exposure
+// luminance towards the histogram's average.
+
+[[block]]
+struct Params {
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    // Fractions of the histogram ignored at the dark and bright end
+    low_percentile: f32;
+    high_percentile: f32;
+    // Adaptation rates towards brighter and darker scenes, per second
+    speed_up: f32;
+    speed_down: f32;
+    delta_time: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+
+[[group(0), binding(1)]]
+var t_hdr: texture_2d<f32>;
+
+[[block]]
+struct Histogram {
+    bins: array<atomic<u32>, 256>;
+};
+[[group(0), binding(2)]]
+var<storage, read_write> histogram: Histogram;
+
+[[block]]
+struct Exposure {
+    // Adapted average luminance, or 0 before the first frame
+    luminance: f32;
+};
+[[group(0), binding(3)]]
+var<storage, read_write> exposure: Exposure;
+
+// Bin 0 holds black pixels, the rest split the luminance range evenly.
+let BIN_COUNT: u32 = 256u;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> counts: array<u32, 256>;
+
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (luminance < 0.0001) {
+        return 0u;
+    }
+    let t = clamp((log2(luminance) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(t * f32(BIN_COUNT - 2u)) + 1u;
+}
+
+[[stage(compute), workgroup_size(16, 16)]]
+fn build_histogram(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+    [[builtin(local_invocation_index)]] index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(t_hdr);
+    if (id.x < u32(size.x) && id.y < u32(size.y)) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        // Atomic results have to be bound, even when unused.
+        let previous = atomicAdd(&local_bins[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    let previous = atomicAdd(&histogram.bins[index], atomicLoad(&local_bins[index]));
+}
+
+// Runs as a single workgroup with one invocation per bin.
+[[stage(compute), workgroup_size(256)]]
+fn average_histogram([[builtin(local_invocation_index)]] index: u32) {
+    counts[index] = atomicExchange(&histogram.bins[index], 0u);
+    workgroupBarrier();
+    if (index != 0u) {
+        return;
+    }
+
+    var total: f32 = 0.0;
+    for (var bin: u32 = 1u; bin < BIN_COUNT; bin = bin + 1u) {
+        total = total + f32(counts[bin]);
+    }
+
+    // Average the bins between the two percentiles.
+    let low = total * params.low_percentile;
+    let high = total * params.high_percentile;
+    var below: f32 = 0.0;
+    var sum: f32 = 0.0;
+    var weight: f32 = 0.0;
+    for (var bin: u32 = 1u; bin < BIN_COUNT; bin = bin + 1u) {
+        let count = f32(counts[bin]);
+        let taken = clamp(below + count, low, high) - clamp(below, low, high);
+        let log_luminance = params.min_log_luminance
+            + (f32(bin - 1u) + 0.5) / f32(BIN_COUNT - 2u) * params.log_luminance_range;
+        sum = sum + taken * log_luminance;
+        weight = weight + taken;
+        below = below + count;
+    }
+    if (weight <= 0.0) {
+        return;
+    }
+
+    let target = exp2(sum / weight);
+    let current = exposure.luminance;
+    if (current <= 0.0) {
+        exposure.luminance = target;
+        return;
+    }
+    var speed: f32 = params.speed_down;
+    if (target > current) {
+        speed = params.speed_up;
+    }
+    exposure.luminance = current + (target - current) * (1.0 - exp(-params.delta_time * speed));
+}
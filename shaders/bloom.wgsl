@@ -0,0 +1,106 @@
+// Bloom: a thresholded 13-tap downsample chain, a tent-filter upsample chain
+// back up it, and an additive composite into the HDR target.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[block]]
+struct Bloom {
+    // Brightness above which pixels bloom
+    threshold: f32;
+    // Width of the soft transition around the threshold
+    knee: f32;
+    intensity: f32;
+    // Upsample tent radius, in source texels
+    radius: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> bloom: Bloom;
+
+fn sample_at(uv: vec2<f32>, texel: vec2<f32>, x: f32, y: f32) -> vec3<f32> {
+    return textureSample(t_source, s_source, uv + texel * vec2<f32>(x, y)).rgb;
+}
+
+// Jimenez's 13-tap downsample from "Next Generation Post Processing in Call
+// of Duty: Advanced Warfare": five overlapping 2x2 boxes, weighted to hide
+// the aliasing a plain box filter flickers with.
+fn downsample_13(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let a = sample_at(uv, texel, -2.0, -2.0);
+    let b = sample_at(uv, texel, 0.0, -2.0);
+    let c = sample_at(uv, texel, 2.0, -2.0);
+    let d = sample_at(uv, texel, -2.0, 0.0);
+    let e = sample_at(uv, texel, 0.0, 0.0);
+    let f = sample_at(uv, texel, 2.0, 0.0);
+    let g = sample_at(uv, texel, -2.0, 2.0);
+    let h = sample_at(uv, texel, 0.0, 2.0);
+    let i = sample_at(uv, texel, 2.0, 2.0);
+    let j = sample_at(uv, texel, -1.0, -1.0);
+    let k = sample_at(uv, texel, 1.0, -1.0);
+    let l = sample_at(uv, texel, -1.0, 1.0);
+    let m = sample_at(uv, texel, 1.0, 1.0);
+
+    return (j + k + l + m) * 0.125
+        + (a + b + d + e) * 0.03125
+        + (b + c + e + f) * 0.03125
+        + (d + e + g + h) * 0.03125
+        + (e + f + h + i) * 0.03125;
+}
+
+// Scales `color` down by how far its brightest channel is below the
+// threshold, with a quadratic knee instead of a hard cut.
+fn prefilter_color(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft: f32 = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 0.00001);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+// Downsamples the HDR target into the first mip, keeping what blooms.
+[[stage(fragment)]]
+fn prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(prefilter_color(downsample_13(in.tex_coords)), 1.0);
+}
+
+[[stage(fragment)]]
+fn downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(downsample_13(in.tex_coords), 1.0);
+}
+
+// 3x3 tent filter, blended additively onto the next larger mip.
+[[stage(fragment)]]
+fn upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = bloom.radius / vec2<f32>(textureDimensions(t_source));
+    let uv = in.tex_coords;
+    var color: vec3<f32> = sample_at(uv, texel, 0.0, 0.0) * 4.0;
+    color = color + (sample_at(uv, texel, 0.0, -1.0) + sample_at(uv, texel, -1.0, 0.0)
+        + sample_at(uv, texel, 1.0, 0.0) + sample_at(uv, texel, 0.0, 1.0)) * 2.0;
+    color = color + sample_at(uv, texel, -1.0, -1.0) + sample_at(uv, texel, 1.0, -1.0)
+        + sample_at(uv, texel, -1.0, 1.0) + sample_at(uv, texel, 1.0, 1.0);
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+// Adds the first mip, which has gathered the whole chain, to the HDR target.
+[[stage(fragment)]]
+fn composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_source, s_source, in.tex_coords).rgb;
+    return vec4<f32>(color * bloom.intensity, 1.0);
+}
@@ -0,0 +1,108 @@
+// Assigns the lights to the clusters of the view frustum they reach.
+
+[[block]]
+struct Clusters {
+    inverse_projection: mat4x4<f32>;
+    view: mat4x4<f32>;
+    screen_size: vec2<f32>;
+    znear: f32;
+    zfar: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> clusters: Clusters;
+
+let LIGHT_DIRECTIONAL: u32 = 2u;
+
+struct Light {
+    proj: mat4x4<f32>;
+    // xyz: position, w: range
+    position: vec4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+    kind: u32;
+    cos_inner: f32;
+    cos_outer: f32;
+    shadow_index: i32;
+};
+
+[[block]]
+struct Lights {
+    count: u32;
+    data: array<Light>;
+};
+[[group(0), binding(1)]]
+var<storage, read> lights: Lights;
+
+// Matches CLUSTER_GRID and MAX_LIGHTS_PER_CLUSTER in cluster.rs
+let CLUSTER_GRID_X: u32 = 16u;
+let CLUSTER_GRID_Y: u32 = 9u;
+let CLUSTER_GRID_Z: u32 = 24u;
+let MAX_LIGHTS_PER_CLUSTER: u32 = 128u;
+
+struct Cluster {
+    count: u32;
+    lights: array<u32, 128>;
+};
+
+[[block]]
+struct ClusterLists {
+    data: array<Cluster>;
+};
+[[group(0), binding(2)]]
+var<storage, read_write> cluster_lists: ClusterLists;
+
+// View space point at a view depth of one behind `ndc`.
+fn view_ray(ndc: vec2<f32>) -> vec3<f32> {
+    let point = clusters.inverse_projection * vec4<f32>(ndc, 0.0, 1.0);
+    let view = point.xyz / point.w;
+    return view / -view.z;
+}
+
+fn slice_depth(slice: u32) -> f32 {
+    return clusters.znear * pow(clusters.zfar / clusters.znear, f32(slice) / f32(CLUSTER_GRID_Z));
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn assign_lights([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= CLUSTER_GRID_X * CLUSTER_GRID_Y * CLUSTER_GRID_Z) {
+        return;
+    }
+    let x = index % CLUSTER_GRID_X;
+    let y = (index / CLUSTER_GRID_X) % CLUSTER_GRID_Y;
+    let z = index / (CLUSTER_GRID_X * CLUSTER_GRID_Y);
+
+    // Tiles count down from the top of the screen, like fragment coordinates.
+    let grid = vec2<f32>(f32(CLUSTER_GRID_X), f32(CLUSTER_GRID_Y));
+    let ndc_low = vec2<f32>(f32(x) / grid.x * 2.0 - 1.0, 1.0 - f32(y + 1u) / grid.y * 2.0);
+    let ndc_high = vec2<f32>(f32(x + 1u) / grid.x * 2.0 - 1.0, 1.0 - f32(y) / grid.y * 2.0);
+    let ray_low = view_ray(ndc_low);
+    let ray_high = view_ray(ndc_high);
+    let near = slice_depth(z);
+    let far = slice_depth(z + 1u);
+
+    // The box around the cluster's corners.
+    let box_low = min(min(ray_low * near, ray_low * far), min(ray_high * near, ray_high * far));
+    let box_high = max(max(ray_low * near, ray_low * far), max(ray_high * near, ray_high * far));
+
+    var count: u32 = 0u;
+    for (var i: u32 = 0u; i < lights.count; i = i + 1u) {
+        if (count >= MAX_LIGHTS_PER_CLUSTER) {
+            break;
+        }
+        let light = lights.data[i];
+        var reaches: bool = true;
+        if (light.kind != LIGHT_DIRECTIONAL) {
+            // Spot lights are culled by the sphere around their whole range.
+            let center = (clusters.view * vec4<f32>(light.position.xyz, 1.0)).xyz;
+            let closest = clamp(center, box_low, box_high);
+            let offset = center - closest;
+            reaches = dot(offset, offset) <= light.position.w * light.position.w;
+        }
+        if (reaches) {
+            cluster_lists.data[index].lights[count] = i;
+            count = count + 1u;
+        }
+    }
+    cluster_lists.data[index].count = count;
+}
@@ -0,0 +1,33 @@
+// Draws a depth texture as a linearized grayscale image.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_depth: texture_depth_2d;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let z_near = 0.1;
+    let z_far = 100.0;
+
+    let size = textureDimensions(t_depth);
+    let coords = min(vec2<i32>(in.tex_coords * vec2<f32>(size)), size - vec2<i32>(1));
+    let depth = textureLoad(t_depth, coords, 0);
+
+    let r = z_near / (z_far - depth * (z_far - z_near));
+
+    return vec4<f32>(vec3<f32>(r), 1.0);
+}
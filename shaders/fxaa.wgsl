@@ -0,0 +1,85 @@
+// FXAA, after Timothy Lottes' original PC version: finds the local edge
+// direction from the luma of the four diagonal neighbors, then blurs along
+// it, falling back to a shorter blur where the longer one oversteps the
+// local luma range.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[block]]
+struct Fxaa {
+    // Set when the source decodes sRGB on sampling, so luma has to be
+    // brought back to perceptual space
+    linear_source: u32;
+};
+[[group(0), binding(2)]]
+var<uniform> fxaa: Fxaa;
+
+let REDUCE_MIN: f32 = 0.0078125;
+let REDUCE_MUL: f32 = 0.125;
+// Longest blur along an edge, in texels
+let SPAN_MAX: f32 = 8.0;
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_source, s_source, uv, 0.0).rgb;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    let value = dot(color, vec3<f32>(0.299, 0.587, 0.114));
+    if (fxaa.linear_source != 0u) {
+        return sqrt(value);
+    }
+    return value;
+}
+
+[[stage(fragment)]]
+fn fxaa_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let uv = in.tex_coords;
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+
+    let color_m = sample_source(uv);
+    let luma_nw = luma(sample_source(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_source(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_source(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_source(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(color_m);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir: vec2<f32> = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let inverse_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * inverse_dir_min, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let color_a = 0.5 * (
+        sample_source(uv + dir * (1.0 / 3.0 - 0.5))
+        + sample_source(uv + dir * (2.0 / 3.0 - 0.5)));
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample_source(uv + dir * -0.5)
+        + sample_source(uv + dir * 0.5));
+    let luma_b = luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(color_a, 1.0);
+    }
+    return vec4<f32>(color_b, 1.0);
+}
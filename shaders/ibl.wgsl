@@ -0,0 +1,243 @@
+// Bakes an equirectangular environment into the cube maps image based
+// lighting samples, and the BRDF lookup table of the split-sum
+// approximation. Every entry point writes one texel of `output`, with the
+// cube face or array layer in z.
+
+[[group(0), binding(0)]]
+var t_equirect: texture_2d<f32>;
+[[group(0), binding(1)]]
+var output: texture_storage_2d_array<rgba16float, write>;
+[[group(0), binding(2)]]
+var t_source: texture_2d_array<f32>;
+[[group(0), binding(3)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(4)]]
+var s_environment: sampler;
+
+[[block]]
+struct Prefilter {
+    roughness: f32;
+    // Width of the environment's first mip
+    source_size: f32;
+};
+[[group(0), binding(5)]]
+var<uniform> prefilter: Prefilter;
+
+let PI: f32 = 3.14159265359;
+// Angle between the irradiance samples, in radians.
+let IRRADIANCE_SAMPLE_DELTA: f32 = 0.05;
+let PREFILTER_SAMPLES: u32 = 256u;
+let BRDF_SAMPLES: u32 = 512u;
+
+// Direction through texture coordinates `uv` of cube face `face`, in the
+// order +X, -X, +Y, -Y, +Z, -Z.
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let p = uv * 2.0 - 1.0;
+    var direction: vec3<f32>;
+    if (face == 0u) {
+        direction = vec3<f32>(1.0, -p.y, -p.x);
+    } elseif (face == 1u) {
+        direction = vec3<f32>(-1.0, -p.y, p.x);
+    } elseif (face == 2u) {
+        direction = vec3<f32>(p.x, 1.0, p.y);
+    } elseif (face == 3u) {
+        direction = vec3<f32>(p.x, -1.0, -p.y);
+    } elseif (face == 4u) {
+        direction = vec3<f32>(p.x, -p.y, 1.0);
+    } else {
+        direction = vec3<f32>(-p.x, -p.y, -1.0);
+    }
+    return normalize(direction);
+}
+
+// Texture coordinates at the center of the output texel `id`.
+fn texel_uv(id: vec3<u32>) -> vec2<f32> {
+    return (vec2<f32>(id.xy) + 0.5) / vec2<f32>(textureDimensions(output));
+}
+
+fn in_output(id: vec3<u32>) -> bool {
+    let size = textureDimensions(output);
+    return id.x < u32(size.x) && id.y < u32(size.y);
+}
+
+fn equirect_texel(coords: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    // Wrap around horizontally, clamp at the poles.
+    let x = (coords.x % size.x + size.x) % size.x;
+    let y = clamp(coords.y, 0, size.y - 1);
+    return textureLoad(t_equirect, vec2<i32>(x, y), 0).rgb;
+}
+
+// The equirectangular image is 32-bit float, which can't be filtered, so it
+// is interpolated by hand.
+fn sample_equirect(direction: vec3<f32>) -> vec3<f32> {
+    let size = textureDimensions(t_equirect);
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    let position = uv * vec2<f32>(size) - 0.5;
+    let base = floor(position);
+    let t = position - base;
+    let coords = vec2<i32>(base);
+    let top = mix(equirect_texel(coords, size), equirect_texel(coords + vec2<i32>(1, 0), size), vec3<f32>(t.x));
+    let bottom = mix(equirect_texel(coords + vec2<i32>(0, 1), size), equirect_texel(coords + vec2<i32>(1, 1), size), vec3<f32>(t.x));
+    return mix(top, bottom, vec3<f32>(t.y));
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn equirect_to_cube([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (!in_output(id)) {
+        return;
+    }
+    let direction = cube_direction(id.z, texel_uv(id));
+    textureStore(output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(sample_equirect(direction), 1.0));
+}
+
+// Averages 2x2 texels of the next larger mip.
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn downsample([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (!in_output(id)) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy) * 2;
+    let layer = i32(id.z);
+    let color = textureLoad(t_source, coords, layer, 0)
+        + textureLoad(t_source, coords + vec2<i32>(1, 0), layer, 0)
+        + textureLoad(t_source, coords + vec2<i32>(0, 1), layer, 0)
+        + textureLoad(t_source, coords + vec2<i32>(1, 1), layer, 0);
+    textureStore(output, vec2<i32>(id.xy), layer, color * 0.25);
+}
+
+// Any two axes perpendicular to `normal`.
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var up: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(normal.y) > 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+// Cosine weighted integral of the environment over the hemisphere around
+// each direction, for diffuse lighting.
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn irradiance([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (!in_output(id)) {
+        return;
+    }
+    let frame = tangent_frame(cube_direction(id.z, texel_uv(id)));
+    // Read from the mip whose texels are about as far apart as the samples.
+    let size = f32(textureDimensions(t_environment).x);
+    let lod = max(log2(size * IRRADIANCE_SAMPLE_DELTA / (0.5 * PI)), 0.0);
+
+    var sum: vec3<f32> = vec3<f32>(0.0);
+    var count: f32 = 0.0;
+    for (var phi: f32 = 0.0; phi < 2.0 * PI; phi = phi + IRRADIANCE_SAMPLE_DELTA) {
+        for (var theta: f32 = 0.0; theta < 0.5 * PI; theta = theta + IRRADIANCE_SAMPLE_DELTA) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(t_environment, s_environment, frame * local, lod).rgb;
+            sum = sum + color * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+    textureStore(output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(PI * sum / count, 1.0));
+}
+
+// Low discrepancy point `i` of `count`.
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) / 4294967296.0);
+}
+
+// Half vector around `normal` distributed like the GGX lobe of `roughness`.
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    return normalize(tangent_frame(normal) * local);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Convolves the environment with the GGX lobe of `prefilter.roughness`,
+// assuming the view direction equals the normal. Each sample reads from the
+// mip that covers about as much of the sphere as the sample does, which
+// keeps bright spots from turning into fireflies.
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn prefilter_specular([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (!in_output(id)) {
+        return;
+    }
+    let normal = cube_direction(id.z, texel_uv(id));
+    let roughness = prefilter.roughness;
+    let texel_solid_angle = 4.0 * PI / (6.0 * prefilter.source_size * prefilter.source_size);
+
+    var sum: vec3<f32> = vec3<f32>(0.0);
+    var weight: f32 = 0.0;
+    for (var i: u32 = 0u; i < PREFILTER_SAMPLES; i = i + 1u) {
+        let half_dir = importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), normal, roughness);
+        let light_dir = normalize(2.0 * dot(normal, half_dir) * half_dir - normal);
+        let n_dot_l = dot(normal, light_dir);
+        if (n_dot_l > 0.0) {
+            let n_dot_h = max(dot(normal, half_dir), 0.0);
+            // With the view along the normal, the pdf reduces to D / 4.
+            let pdf = distribution_ggx(n_dot_h, roughness) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(PREFILTER_SAMPLES) * pdf);
+            var lod: f32 = 0.0;
+            if (roughness > 0.0) {
+                lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+            }
+            sum = sum + textureSampleLevel(t_environment, s_environment, light_dir, lod).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    textureStore(output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(sum / max(weight, 0.0001), 1.0));
+}
+
+// Smith geometry term with the Schlick-GGX approximation for image based
+// lighting, which remaps roughness differently than for direct light.
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
+
+// Scale and bias to the Fresnel reflectance at normal incidence, indexed by
+// the cosine of the view angle in x and the roughness in y.
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn brdf_lut([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (!in_output(id)) {
+        return;
+    }
+    let uv = texel_uv(id);
+    let n_dot_v = uv.x;
+    let roughness = uv.y;
+    let view_dir = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale: f32 = 0.0;
+    var bias: f32 = 0.0;
+    for (var i: u32 = 0u; i < BRDF_SAMPLES; i = i + 1u) {
+        let half_dir = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), normal, roughness);
+        let light_dir = normalize(2.0 * dot(view_dir, half_dir) * half_dir - view_dir);
+        let n_dot_l = max(light_dir.z, 0.0);
+        if (n_dot_l > 0.0) {
+            let n_dot_h = max(half_dir.z, 0.0);
+            let v_dot_h = max(dot(view_dir, half_dir), 0.0);
+            let visibility = geometry_smith_ibl(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale = scale + (1.0 - fresnel) * visibility;
+            bias = bias + fresnel * visibility;
+        }
+    }
+    let lut = vec2<f32>(scale, bias) / f32(BRDF_SAMPLES);
+    textureStore(output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(lut, 0.0, 1.0));
+}
@@ -4,17 +4,35 @@
 struct Camera {
     view_pos: vec4<f32>;
     view_proj: mat4x4<f32>;
+    // view_proj without the TAA jitter
+    unjittered_view_proj: mat4x4<f32>;
+    // The previous frame's unjittered_view_proj
+    previous_view_proj: mat4x4<f32>;
+    // Inverse of view_proj, to reconstruct positions from depth
+    inverse_view_proj: mat4x4<f32>;
 };
 [[group(0), binding(0)]]
 var<uniform> camera: Camera;
 
-[[block]]
 struct Light {
-    position: vec3<f32>;
-    color: vec3<f32>;
+    proj: mat4x4<f32>;
+    position: vec4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+    kind: u32;
+    cos_inner: f32;
+    cos_outer: f32;
+    // Cube in the point shadow array, or -1
+    shadow_index: i32;
+};
+
+[[block]]
+struct Lights {
+    count: u32;
+    data: array<Light>;
 };
-[[group(1), binding(0)]]
-var<uniform> light: Light;
+[[group(1), binding(1)]]
+var<storage, read> lights: Lights;
 
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
@@ -23,22 +41,45 @@ struct VertexInput {
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] color: vec3<f32>;
+    // Unjittered clip positions this frame and the last, for motion vectors
+    [[location(1)]] current_position: vec4<f32>;
+    [[location(2)]] previous_position: vec4<f32>;
 };
 
+// One instance is drawn per light.
 [[stage(vertex)]]
 fn main(
     model: VertexInput,
+    [[builtin(instance_index)]] light_index: u32,
 ) -> VertexOutput {
+    // Let's keep our light smaller than our other objects
     let scale = 0.25;
+    let light = lights.data[light_index];
     var out: VertexOutput;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
-    out.color = light.color;
+    let world_position = vec4<f32>(model.position * scale + light.position.xyz, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    // Only the camera's motion is tracked; lights have no previous position.
+    out.current_position = camera.unjittered_view_proj * world_position;
+    out.previous_position = camera.previous_view_proj * world_position;
+    // Emit at the light's intensity, so bright lights bloom.
+    out.color = light.color.rgb * light.color.a;
+    // Directional lights have no position, so push them outside the clip volume.
+    if (light.kind == 2u) {
+        out.clip_position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+    }
     return out;
 }
 
 // Fragment shader
 
+struct FragmentOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] velocity: vec2<f32>;
+};
+
 [[stage(fragment)]]
-fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+fn main(in: VertexOutput) -> FragmentOutput {
+    let ndc_motion = in.current_position.xy / in.current_position.w
+        - in.previous_position.xy / in.previous_position.w;
+    return FragmentOutput(vec4<f32>(in.color, 1.0), ndc_motion * vec2<f32>(0.5, -0.5));
+}
@@ -0,0 +1,81 @@
+// Renders the distance to a point light into one face of its shadow cube.
+
+[[block]]
+struct Face {
+    view_proj: mat4x4<f32>;
+    // xyz: light position, w: range
+    light: vec4<f32>;
+    // xy: exponents for the moments
+    warp: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> face: Face;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = face.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+// Linear distance, so the lit pass can compare without knowing the face.
+fn light_depth(world_position: vec3<f32>) -> f32 {
+    return length(world_position - face.light.xyz) / face.light.w;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[builtin(frag_depth)]] f32 {
+    return light_depth(in.world_position);
+}
+
+// Depth and its square, or with nonzero exponents the exponentially warped
+// depth and its square for both signs. Mirrors `ShadowFilter::moments`.
+fn warp_moments(depth: f32, exponents: vec2<f32>) -> vec4<f32> {
+    if (exponents.x <= 0.0 && exponents.y <= 0.0) {
+        return vec4<f32>(depth, depth * depth, 0.0, 0.0);
+    }
+    let d = depth * 2.0 - 1.0;
+    let positive = exp(exponents.x * d);
+    let negative = -exp(-exponents.y * d);
+    return vec4<f32>(positive, positive * positive, negative, negative * negative);
+}
+
+struct MomentsOutput {
+    [[builtin(frag_depth)]] depth: f32;
+    [[location(0)]] moments: vec4<f32>;
+};
+
+// Only used by the variance filters, which sample moments instead of depth.
+[[stage(fragment)]]
+fn moments(in: VertexOutput) -> MomentsOutput {
+    var out: MomentsOutput;
+    out.depth = light_depth(in.world_position);
+    out.moments = warp_moments(out.depth, face.warp.xy);
+    return out;
+}
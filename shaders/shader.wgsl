@@ -4,22 +4,107 @@
 struct Camera {
     view_pos: vec4<f32>;
     view_proj: mat4x4<f32>;
+    // view_proj without the TAA jitter
+    unjittered_view_proj: mat4x4<f32>;
+    // The previous frame's unjittered_view_proj
+    previous_view_proj: mat4x4<f32>;
+    // Inverse of view_proj, to reconstruct positions from depth
+    inverse_view_proj: mat4x4<f32>;
 };
 [[group(1), binding(0)]]
 var<uniform> camera: Camera;
 
+struct ShadowFilter {
+    // One of the FILTER_* modes
+    mode: u32;
+    // Kernel width or number of Poisson samples
+    samples: u32;
+    bias: f32;
+    // Poisson disk radius or light size, in texels
+    radius: f32;
+    // Warp exponents, both zero for plain variance shadow maps
+    exponents: vec2<f32>;
+    min_variance: f32;
+    light_bleed: f32;
+};
+
 [[block]]
-struct Light {
-    position: vec3<f32>;
-    color: vec3<f32>;
+struct ShadowLight {
+    cascades: array<mat4x4<f32>, 4>;
+    // View depth at which each cascade ends
+    splits: vec4<f32>;
+    cascade_count: u32;
+    // Fraction of a cascade over which it blends into the next
+    blend: f32;
+    // Index of the light the cascades belong to
+    caster: u32;
+    filter: ShadowFilter;
 };
 [[group(2), binding(0)]]
-var<uniform> light: Light;
+var<uniform> shadow_light: ShadowLight;
+
+let LIGHT_POINT: u32 = 0u;
+let LIGHT_SPOT: u32 = 1u;
+let LIGHT_DIRECTIONAL: u32 = 2u;
+
+struct Light {
+    proj: mat4x4<f32>;
+    // xyz: position, w: range
+    position: vec4<f32>;
+    // xyz: direction the light points in
+    direction: vec4<f32>;
+    // rgb: color, a: intensity
+    color: vec4<f32>;
+    kind: u32;
+    cos_inner: f32;
+    cos_outer: f32;
+    // Cube in the point shadow array, or -1
+    shadow_index: i32;
+};
+
+[[block]]
+struct Lights {
+    count: u32;
+    data: array<Light>;
+};
+[[group(2), binding(1)]]
+var<storage, read> lights: Lights;
+
+[[block]]
+struct Clusters {
+    inverse_projection: mat4x4<f32>;
+    view: mat4x4<f32>;
+    screen_size: vec2<f32>;
+    znear: f32;
+    zfar: f32;
+};
+[[group(2), binding(2)]]
+var<uniform> clusters: Clusters;
+
+// Matches CLUSTER_GRID in cluster.rs
+let CLUSTER_GRID_X: u32 = 16u;
+let CLUSTER_GRID_Y: u32 = 9u;
+let CLUSTER_GRID_Z: u32 = 24u;
+
+// Indices of the lights reaching a cluster of the view frustum
+struct Cluster {
+    count: u32;
+    lights: array<u32, 128>;
+};
+
+[[block]]
+struct ClusterLists {
+    data: array<Cluster>;
+};
+[[group(2), binding(3)]]
+var<storage, read> cluster_lists: ClusterLists;
 
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] tex_coords: vec2<f32>;
     [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tangent: vec3<f32>;
+    [[location(4)]] bitangent: vec3<f32>;
 };
 struct InstanceInput {
     [[location(5)]] model_matrix_0: vec4<f32>;
@@ -29,6 +114,10 @@ struct InstanceInput {
     [[location(9)]] normal_matrix_0: vec3<f32>;
     [[location(10)]] normal_matrix_1: vec3<f32>;
     [[location(11)]] normal_matrix_2: vec3<f32>;
+    [[location(12)]] previous_model_matrix_0: vec4<f32>;
+    [[location(13)]] previous_model_matrix_1: vec4<f32>;
+    [[location(14)]] previous_model_matrix_2: vec4<f32>;
+    [[location(15)]] previous_model_matrix_3: vec4<f32>;
 };
 
 struct VertexOutput {
@@ -36,6 +125,11 @@ struct VertexOutput {
     [[location(0)]] tex_coords: vec2<f32>;
     [[location(1)]] world_normal: vec3<f32>;
     [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] world_tangent: vec3<f32>;
+    [[location(4)]] world_bitangent: vec3<f32>;
+    // Unjittered clip positions this frame and the last, for motion vectors
+    [[location(5)]] current_position: vec4<f32>;
+    [[location(6)]] previous_position: vec4<f32>;
 };
 
 [[stage(vertex)]]
@@ -57,38 +151,702 @@ fn main(
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
     out.world_normal = normal_matrix * model.normal;
+    // Tangents lie in the surface, so they follow the model matrix itself.
+    let model_3x3 = mat3x3<f32>(model_matrix.x.xyz, model_matrix.y.xyz, model_matrix.z.xyz);
+    out.world_tangent = model_3x3 * model.tangent;
+    out.world_bitangent = model_3x3 * model.bitangent;
     var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_proj * world_position;
+
+    let previous_model_matrix = mat4x4<f32>(
+        instance.previous_model_matrix_0,
+        instance.previous_model_matrix_1,
+        instance.previous_model_matrix_2,
+        instance.previous_model_matrix_3,
+    );
+    out.current_position = camera.unjittered_view_proj * world_position;
+    out.previous_position = camera.previous_view_proj * previous_model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
 
 // Fragment shader
 
 [[group(0), binding(0)]]
-var t_diffuse: texture_2d<f32>;
+var t_base_color: texture_2d<f32>;
 [[group(0), binding(1)]]
-var s_diffuse: sampler;
+var s_base_color: sampler;
+[[group(0), binding(2)]]
+var t_metallic_roughness: texture_2d<f32>;
+[[group(0), binding(3)]]
+var s_metallic_roughness: sampler;
+[[group(0), binding(4)]]
+var t_normal: texture_2d<f32>;
+[[group(0), binding(5)]]
+var s_normal: sampler;
+[[group(0), binding(6)]]
+var t_occlusion: texture_2d<f32>;
+[[group(0), binding(7)]]
+var s_occlusion: sampler;
+[[group(0), binding(8)]]
+var t_emissive: texture_2d<f32>;
+[[group(0), binding(9)]]
+var s_emissive: sampler;
 
-[[stage(fragment)]]
-fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    
-    // We don't need (or want) much ambient light, so 0.1 is fine
-    let ambient_strength = 0.1;
-    let ambient_color = light.color * ambient_strength;
-
-    let light_dir = normalize(light.position - in.world_position);
-    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+[[block]]
+struct Material {
+    base_color: vec4<f32>;
+    // rgb: emissive, a: alpha cutoff
+    emissive: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    occlusion_strength: f32;
+    normal_scale: f32;
+};
+[[group(0), binding(10)]]
+var<uniform> material: Material;
+
+[[group(3), binding(0)]]
+var t_shadow: texture_depth_2d_array;
+[[group(3), binding(1)]]
+var s_shadow: sampler_comparison;
+[[group(3), binding(2)]]
+var t_point_shadow: texture_depth_cube_array;
+[[group(3), binding(3)]]
+var t_shadow_moments: texture_2d_array<f32>;
+[[group(3), binding(4)]]
+var t_point_shadow_moments: texture_cube_array<f32>;
+[[group(3), binding(5)]]
+var s_shadow_moments: sampler;
+[[group(3), binding(6)]]
+var s_shadow_depth: sampler;
+
+[[group(3), binding(7)]]
+var t_irradiance: texture_cube<f32>;
+[[group(3), binding(8)]]
+var t_prefiltered: texture_cube<f32>;
+[[group(3), binding(9)]]
+var t_brdf_lut: texture_2d<f32>;
+[[group(3), binding(10)]]
+var s_environment: sampler;
+
+[[block]]
+struct Environment {
+    intensity: f32;
+    // Zero until an environment map is loaded
+    enabled: u32;
+    // Mip of t_prefiltered for roughness 1
+    max_lod: f32;
+};
+[[group(3), binding(11)]]
+var<uniform> environment: Environment;
+
+// Fraction of ambient light reaching each pixel, from SSAO. A single white
+// texel while SSAO is off.
+[[group(3), binding(12)]]
+var t_ambient_occlusion: texture_2d<f32>;
+
+let PI: f32 = 3.14159265359;
+// Stand-in for image based lighting without an environment map.
+let AMBIENT: f32 = 0.03;
+// In units of the light's range.
+let POINT_SHADOW_BIAS: f32 = 0.005;
+
+let FILTER_HARDWARE: u32 = 0u;
+let FILTER_PCF: u32 = 1u;
+let FILTER_POISSON: u32 = 2u;
+let FILTER_PCSS: u32 = 3u;
+let FILTER_VARIANCE: u32 = 4u;
+
+var<private> poisson_disk: array<vec2<f32>, 16> = array<vec2<f32>, 16>(
+    vec2<f32>(-0.94201624, -0.39906216),
+    vec2<f32>(0.94558609, -0.76890725),
+    vec2<f32>(-0.09418410, -0.92938870),
+    vec2<f32>(0.34495938, 0.29387760),
+    vec2<f32>(-0.91588581, 0.45771432),
+    vec2<f32>(-0.81544232, -0.87912464),
+    vec2<f32>(-0.38277543, 0.27676845),
+    vec2<f32>(0.97484398, 0.75648379),
+    vec2<f32>(0.44323325, -0.97511554),
+    vec2<f32>(0.53742981, -0.47373420),
+    vec2<f32>(-0.26496911, -0.41893023),
+    vec2<f32>(0.79197514, 0.19090188),
+    vec2<f32>(-0.24188840, 0.99706507),
+    vec2<f32>(-0.81409955, 0.91437590),
+    vec2<f32>(0.19984126, 0.78641367),
+    vec2<f32>(0.14383161, -0.14100790),
+);
+
+// Where to look up a shadow: a layer of the cascade array, or a cube of the
+// point shadow array.
+struct ShadowCoords {
+    // xy: texture coordinates, or xyz: direction from the light
+    position: vec3<f32>;
+    // Moves `position` by one texel along each axis of the map
+    texel: mat2x3<f32>;
+    layer: i32;
+    depth: f32;
+    cube: bool;
+};
+
+// Compares against the map `offset` texels away.
+fn compare_at(coords: ShadowCoords, offset: vec2<f32>, depth: f32) -> f32 {
+    let position = coords.position + coords.texel * offset;
+    if (coords.cube) {
+        return textureSampleCompareLevel(t_point_shadow, s_shadow, position, coords.layer, depth);
+    }
+    return textureSampleCompareLevel(t_shadow, s_shadow, position.xy, coords.layer, depth);
+}
+
+fn depth_at(coords: ShadowCoords, offset: vec2<f32>) -> f32 {
+    let position = coords.position + coords.texel * offset;
+    if (coords.cube) {
+        return textureSampleLevel(t_point_shadow, s_shadow_depth, position, coords.layer, 0.0);
+    }
+    return textureSampleLevel(t_shadow, s_shadow_depth, position.xy, coords.layer, 0.0);
+}
+
+fn moments_at(coords: ShadowCoords, offset: vec2<f32>) -> vec4<f32> {
+    let position = coords.position + coords.texel * offset;
+    if (coords.cube) {
+        return textureSampleLevel(t_point_shadow_moments, s_shadow_moments, position, coords.layer, 0.0);
+    }
+    return textureSampleLevel(t_shadow_moments, s_shadow_moments, position.xy, coords.layer, 0.0);
+}
+
+// Averages `samples` comparisons from the Poisson disk, `radius` texels wide.
+fn poisson_pcf(coords: ShadowCoords, depth: f32, radius: f32, rotation: mat2x2<f32>) -> f32 {
+    let samples = shadow_light.filter.samples;
+    var sum: f32 = 0.0;
+    for (var i: u32 = 0u; i < samples; i = i + 1u) {
+        sum = sum + compare_at(coords, rotation * poisson_disk[i] * radius, depth);
+    }
+    return sum / f32(samples);
+}
+
+// Mirrors `ShadowFilter::moments`.
+fn warp_moments(depth: f32, exponents: vec2<f32>) -> vec4<f32> {
+    if (exponents.x <= 0.0 && exponents.y <= 0.0) {
+        return vec4<f32>(depth, depth * depth, 0.0, 0.0);
+    }
+    let d = depth * 2.0 - 1.0;
+    let positive = exp(exponents.x * d);
+    let negative = -exp(-exponents.y * d);
+    return vec4<f32>(positive, positive * positive, negative, negative * negative);
+}
+
+// Upper bound on the lit fraction from the mean and variance of the occluder
+// depth, with the bottom `light_bleed` of it cut off.
+fn chebyshev(moments: vec2<f32>, depth: f32) -> f32 {
+    if (depth <= moments.x) {
+        return 1.0;
+    }
+    let filter = shadow_light.filter;
+    let variance = max(moments.y - moments.x * moments.x, filter.min_variance);
+    let d = depth - moments.x;
+    let p_max = variance / (variance + d * d);
+    return clamp((p_max - filter.light_bleed) / (1.0 - filter.light_bleed), 0.0, 1.0);
+}
+
+fn variance_shadow(moments: vec4<f32>, depth: f32) -> f32 {
+    let exponents = shadow_light.filter.exponents;
+    if (exponents.x <= 0.0 && exponents.y <= 0.0) {
+        return chebyshev(moments.xy, depth);
+    }
+    let warped = warp_moments(depth, exponents);
+    return min(chebyshev(moments.xy, warped.x), chebyshev(moments.zw, warped.z));
+}
+
+// Filters the lookup at `coords` with the selected mode. `rotation` turns the
+// Poisson disk per pixel.
+fn filter_shadow(coords: ShadowCoords, rotation: mat2x2<f32>) -> f32 {
+    let filter = shadow_light.filter;
+    let depth = coords.depth - filter.bias;
+
+    if (filter.mode == FILTER_PCF || filter.mode == FILTER_VARIANCE) {
+        let half_width = f32(filter.samples - 1u) * 0.5;
+        var sum: f32 = 0.0;
+        var moments: vec4<f32> = vec4<f32>(0.0);
+        for (var y: u32 = 0u; y < filter.samples; y = y + 1u) {
+            for (var x: u32 = 0u; x < filter.samples; x = x + 1u) {
+                let offset = vec2<f32>(f32(x), f32(y)) - half_width;
+                if (filter.mode == FILTER_PCF) {
+                    sum = sum + compare_at(coords, offset, depth);
+                } else {
+                    moments = moments + moments_at(coords, offset);
+                }
+            }
+        }
+        let count = f32(filter.samples * filter.samples);
+        if (filter.mode == FILTER_PCF) {
+            return sum / count;
+        }
+        return variance_shadow(moments / count, coords.depth);
+    }
+
+    if (filter.mode == FILTER_POISSON) {
+        return poisson_pcf(coords, depth, filter.radius, rotation);
+    }
+
+    if (filter.mode == FILTER_PCSS) {
+        // Average the depth of the occluders within the light's size.
+        var blockers: f32 = 0.0;
+        var count: f32 = 0.0;
+        for (var i: u32 = 0u; i < filter.samples; i = i + 1u) {
+            let blocker = depth_at(coords, rotation * poisson_disk[i] * filter.radius);
+            if (blocker < depth) {
+                blockers = blockers + blocker;
+                count = count + 1.0;
+            }
+        }
+        if (count == 0.0) {
+            return 1.0;
+        }
+        let blocker = blockers / count;
+        // The penumbra widens with the distance between blocker and receiver.
+        let penumbra = (depth - blocker) / max(blocker, 0.0001) * filter.radius;
+        return poisson_pcf(coords, depth, clamp(penumbra, 1.0, filter.radius), rotation);
+    }
+
+    return compare_at(coords, vec2<f32>(0.0), depth);
+}
+
+// Rotates the Poisson disk by interleaved gradient noise of the pixel.
+fn disk_rotation(frag_coord: vec2<f32>) -> mat2x2<f32> {
+    let noise = fract(52.9829189 * fract(dot(frag_coord, vec2<f32>(0.06711056, 0.00583715))));
+    let angle = noise * 2.0 * PI;
+    let c = cos(angle);
+    let s = sin(angle);
+    return mat2x2<f32>(vec2<f32>(c, s), vec2<f32>(-s, c));
+}
+
+fn shadow_calc(world_position: vec3<f32>, cascade: u32, rotation: mat2x2<f32>) -> f32 {
+    let homogeneous_coords = shadow_light.cascades[cascade] * vec4<f32>(world_position, 1.0);
+    if (homogeneous_coords.w <= 0.0) {
+        return 1.0;
+    }
+
+    let flip_correction = vec2<f32>(0.5, -0.5);
+    let proj_correction = 1.0 / homogeneous_coords.w;
+    let light_local = homogeneous_coords.xy * flip_correction * proj_correction + vec2<f32>(0.5, 0.5);
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var coords: ShadowCoords;
+    coords.position = vec3<f32>(light_local, 0.0);
+    coords.texel = mat2x3<f32>(vec3<f32>(texel.x, 0.0, 0.0), vec3<f32>(0.0, texel.y, 0.0));
+    coords.layer = i32(cascade);
+    coords.depth = homogeneous_coords.z * proj_correction;
+    coords.cube = false;
+    return filter_shadow(coords, rotation);
+}
+
+// Picks the cascade covering `view_depth`, blending into the next cascade
+// near its far end. The last cascade fades out instead.
+fn cascaded_shadow(world_position: vec3<f32>, view_depth: f32, rotation: mat2x2<f32>) -> f32 {
+    var start: f32 = 0.0;
+    for (var i: u32 = 0u; i < shadow_light.cascade_count; i = i + 1u) {
+        let split = shadow_light.splits[i];
+        if (view_depth < split) {
+            let shadow = shadow_calc(world_position, i, rotation);
+            let blend_start = split - (split - start) * shadow_light.blend;
+            if (view_depth <= blend_start) {
+                return shadow;
+            }
+
+            let t = (view_depth - blend_start) / (split - blend_start);
+            var next: f32 = 1.0;
+            if (i + 1u < shadow_light.cascade_count) {
+                next = shadow_calc(world_position, i + 1u, rotation);
+            }
+            return mix(shadow, next, t);
+        }
+        start = split;
+    }
+    return 1.0;
+}
+
+// Compares the distance to a point light against its shadow cube, which
+// stores distances divided by the light's range.
+fn point_shadow(light: Light, world_position: vec3<f32>, rotation: mat2x2<f32>) -> f32 {
+    let to_fragment = world_position - light.position.xyz;
+    let direction = normalize(to_fragment);
+    // Any two axes perpendicular to the lookup direction.
+    var up: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(direction.y) > 0.9) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, direction));
+    let bitangent = cross(direction, tangent);
+    // A cube face spans two units at unit distance from the center.
+    let texel = 2.0 / f32(textureDimensions(t_point_shadow).x);
+
+    var coords: ShadowCoords;
+    coords.position = direction;
+    coords.texel = mat2x3<f32>(tangent * texel, bitangent * texel);
+    coords.layer = light.shadow_index;
+    coords.depth = length(to_fragment) / light.position.w - POINT_SHADOW_BIAS;
+    coords.cube = true;
+    return filter_shadow(coords, rotation);
+}
+
+// Distance falloff that reaches zero at the light's range.
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let falloff = 1.0 / (1.0 + 0.007 * distance + 0.002 * (distance * distance));
+    let window = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    return falloff * window * window;
+}
+
+// Trowbridge-Reitz GGX normal distribution.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith geometry term with the Schlick-GGX approximation for direct light.
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Fresnel for light from every direction at once, which rough surfaces
+// reflect less of at grazing angles.
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let f90 = max(vec3<f32>(1.0 - roughness), f0);
+    return f0 + (f90 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Applies the tangent-space normal map to the interpolated surface frame.
+fn perturb_normal(in: VertexOutput) -> vec3<f32> {
+    let n = normalize(in.world_normal);
+    // Re-orthogonalize after interpolation.
+    let t = normalize(in.world_tangent - n * dot(n, in.world_tangent));
+    var b: vec3<f32> = cross(n, t);
+    if (dot(b, in.world_bitangent) < 0.0) {
+        b = -b;
+    }
+
+    var tangent_normal: vec3<f32> = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+    tangent_normal = vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z);
+    return normalize(mat3x3<f32>(t, b, n) * tangent_normal);
+}
+
+// What the lights need to know about a point on a surface.
+struct Surface {
+    base_color: vec4<f32>;
+    normal: vec3<f32>;
+    metallic: f32;
+    roughness: f32;
+    occlusion: f32;
+    emissive: vec3<f32>;
+};
+
+fn material_surface(in: VertexOutput) -> Surface {
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    var out: Surface;
+    out.base_color = textureSample(t_base_color, s_base_color, in.tex_coords) * material.base_color;
+    out.normal = perturb_normal(in);
+    out.metallic = clamp(material.metallic * metallic_roughness.b, 0.0, 1.0);
+    out.roughness = clamp(material.roughness * metallic_roughness.g, 0.045, 1.0);
+    out.occlusion = 1.0 + material.occlusion_strength * (textureSample(t_occlusion, s_occlusion, in.tex_coords).r - 1.0);
+    out.emissive = textureSample(t_emissive, s_emissive, in.tex_coords).rgb * material.emissive.rgb;
+    return out;
+}
+
+fn screen_occlusion(frag_coord: vec2<f32>) -> f32 {
+    let last = textureDimensions(t_ambient_occlusion) - 1;
+    return textureLoad(t_ambient_occlusion, min(vec2<i32>(frag_coord), last), 0).r;
+}
+
+// Light that doesn't come from the light list: the environment map, with
+// the split-sum approximation for its specular part, and emission.
+fn ambient_light(surface: Surface, world_position: vec3<f32>, frag_coord: vec2<f32>) -> vec3<f32> {
+    let occlusion = surface.occlusion * screen_occlusion(frag_coord);
+    if (environment.enabled == 0u) {
+        return AMBIENT * surface.base_color.rgb * occlusion + surface.emissive;
+    }
+
+    let normal = surface.normal;
+    let view_dir = normalize(camera.view_pos.xyz - world_position);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), surface.base_color.rgb, vec3<f32>(surface.metallic));
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, surface.roughness);
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, normal, 0.0).rgb;
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - surface.metallic) * irradiance * surface.base_color.rgb;
+
+    let reflection = reflect(-view_dir, normal);
+    let lod = surface.roughness * environment.max_lod;
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflection, lod).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, surface.roughness), 0.0).rg;
+    let specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+    return (diffuse + specular) * occlusion * environment.intensity + surface.emissive;
+}
+
+// Reflected light from light `index` of the list, shadows included.
+fn direct_light(index: u32, surface: Surface, world_position: vec3<f32>, frag_coord: vec2<f32>) -> vec3<f32> {
+    let light = lights.data[index];
+
+    var light_dir: vec3<f32>;
+    var strength: f32 = light.color.a;
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        light_dir = -normalize(light.direction.xyz);
+    } else {
+        let to_light = light.position.xyz - world_position;
+        light_dir = normalize(to_light);
+        strength = strength * attenuation(length(to_light), light.position.w);
+        if (light.kind == LIGHT_SPOT) {
+            let cos_angle = dot(-light_dir, normalize(light.direction.xyz));
+            let t = clamp((cos_angle - light.cos_outer) / max(light.cos_inner - light.cos_outer, 0.0001), 0.0, 1.0);
+            strength = strength * t * t * (3.0 - 2.0 * t);
+        }
+    }
+
+    let normal = surface.normal;
+    let n_dot_l = dot(normal, light_dir);
+    if (n_dot_l <= 0.0 || strength <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let rotation = disk_rotation(frag_coord);
+    if (light.kind == LIGHT_POINT) {
+        if (light.shadow_index >= 0) {
+            strength = strength * point_shadow(light, world_position, rotation);
+        }
+    } elseif (index == shadow_light.caster) {
+        // Clip w of a perspective projection is the view depth.
+        let view_depth = (camera.view_proj * vec4<f32>(world_position, 1.0)).w;
+        strength = strength * cascaded_shadow(world_position, view_depth, rotation);
+    }
+
+    let view_dir = normalize(camera.view_pos.xyz - world_position);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
     let half_dir = normalize(view_dir + light_dir);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+    let f0 = mix(vec3<f32>(0.04), surface.base_color.rgb, vec3<f32>(surface.metallic));
+
+    let fresnel = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+    let specular = distribution_ggx(n_dot_h, surface.roughness)
+        * geometry_smith(n_dot_v, n_dot_l, surface.roughness)
+        * fresnel
+        / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - surface.metallic) * surface.base_color.rgb / PI;
+
+    return (diffuse + specular) * light.color.rgb * strength * n_dot_l;
+}
+
+// Index of the cluster containing the fragment at `frag_coord`, `view_depth`
+// away from the camera. Mirrors `cluster::depth_slice`.
+fn cluster_index(frag_coord: vec2<f32>, view_depth: f32) -> u32 {
+    let grid = vec2<f32>(f32(CLUSTER_GRID_X), f32(CLUSTER_GRID_Y));
+    let tile = min(vec2<u32>(frag_coord / clusters.screen_size * grid), vec2<u32>(CLUSTER_GRID_X - 1u, CLUSTER_GRID_Y - 1u));
+    let t = log(view_depth / clusters.znear) / log(clusters.zfar / clusters.znear);
+    let slice = min(u32(max(t * f32(CLUSTER_GRID_Z), 0.0)), CLUSTER_GRID_Z - 1u);
+    return tile.x + CLUSTER_GRID_X * (tile.y + CLUSTER_GRID_Y * slice);
+}
+
+// Lights the fragment with the lights of its cluster.
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let surface = material_surface(in);
+    let view_depth = (camera.view_proj * vec4<f32>(in.world_position, 1.0)).w;
+    let cluster = cluster_index(in.clip_position.xy, view_depth);
+
+    var color: vec3<f32> = ambient_light(surface, in.world_position, in.clip_position.xy);
+    let count = cluster_lists.data[cluster].count;
+    for (var i: u32 = 0u; i < count; i = i + 1u) {
+        let light = cluster_lists.data[cluster].lights[i];
+        color = color + direct_light(light, surface, in.world_position, in.clip_position.xy);
+    }
+    return vec4<f32>(color, surface.base_color.a);
+}
+
+// Screen motion since the previous frame, in texture coordinates.
+fn velocity(current: vec4<f32>, previous: vec4<f32>) -> vec2<f32> {
+    let ndc_motion = current.xy / current.w - previous.xy / previous.w;
+    // Texture coordinates run down, normalized device coordinates up.
+    return ndc_motion * vec2<f32>(0.5, -0.5);
+}
+
+struct FragmentOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] velocity: vec2<f32>;
+};
 
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> FragmentOutput {
+    let color = shade(in);
+    // Alpha test; opaque materials have a cutoff of zero.
+    if (color.a < material.emissive.a) {
+        discard;
+    }
+    return FragmentOutput(color, velocity(in.current_position, in.previous_position));
+}
+
+// Cutouts drawn with alpha to coverage. Alpha is sharpened around the cutoff
+// so the edge fades over about a pixel, instead of dithering the whole
+// gradient of the texture.
+[[stage(fragment)]]
+fn coverage(in: VertexOutput) -> FragmentOutput {
+    let color = shade(in);
+    let alpha = (color.a - material.emissive.a) / max(fwidth(color.a), 0.0001) + 0.5;
+    return FragmentOutput(
+        vec4<f32>(color.rgb, clamp(alpha, 0.0, 1.0)),
+        velocity(in.current_position, in.previous_position),
+    );
+}
 
-    let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
-    let specular_color = specular_strength * light.color;
+// Transparency
 
-    let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+// Sorted transparent surfaces, blended back to front over what's behind them
+// by the pipeline. The velocity target is masked off, so motion vectors stay
+// those of the opaque surface behind.
+[[stage(fragment)]]
+fn transparent(in: VertexOutput) -> FragmentOutput {
+    return FragmentOutput(shade(in), velocity(in.current_position, in.previous_position));
+}
 
-    return vec4<f32>(result, object_color.a);
-}
\ No newline at end of file
+// Weighted blended order independent transparency (McGuire and Bavoil 2013):
+// premultiplied color and alpha are summed with a weight favoring near,
+// opaque fragments, while the revealage multiplies up how much of the
+// background shows through.
+struct AccumulateOutput {
+    [[location(0)]] accumulation: vec4<f32>;
+    [[location(1)]] revealage: f32;
+};
+
+[[stage(fragment)]]
+fn accumulate(in: VertexOutput) -> AccumulateOutput {
+    let color = shade(in);
+    // Equation 10 of the paper, on the depth buffer's value.
+    let distance = 1.0 - in.clip_position.z;
+    let weight = clamp(color.a * max(0.01, 3000.0 * distance * distance * distance), 0.01, 3000.0);
+    return AccumulateOutput(vec4<f32>(color.rgb * color.a, color.a) * weight, color.a);
+}
+
+// Deferred shading
+
+// The G-buffer pass writes the light that doesn't depend on the light list
+// straight into the HDR target, and the surface into the other targets.
+struct GBufferOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] velocity: vec2<f32>;
+    // rgb: base color, a: metallic
+    [[location(2)]] albedo: vec4<f32>;
+    // xyz: world normal, w: roughness
+    [[location(3)]] normal: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn gbuffer(in: VertexOutput) -> GBufferOutput {
+    let surface = material_surface(in);
+    if (surface.base_color.a < material.emissive.a) {
+        discard;
+    }
+    return GBufferOutput(
+        vec4<f32>(ambient_light(surface, in.world_position, in.clip_position.xy), surface.base_color.a),
+        velocity(in.current_position, in.previous_position),
+        vec4<f32>(surface.base_color.rgb, surface.metallic),
+        vec4<f32>(surface.normal, surface.roughness),
+    );
+}
+
+// The lighting pass replaces the material group with the G-buffer, numbered
+// after the material bindings so both can live in this module.
+[[group(0), binding(11)]]
+var t_gbuffer_depth: texture_depth_2d;
+[[group(0), binding(12)]]
+var t_gbuffer_albedo: texture_2d<f32>;
+[[group(0), binding(13)]]
+var t_gbuffer_normal: texture_2d<f32>;
+
+struct LightVolumeOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0), interpolate(flat)]] light_index: u32;
+};
+
+var<private> quad_corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+);
+
+// Covers the part of the screen a light can reach with a quad: the
+// projection of the box around its range, or the whole screen for
+// directional lights and lights the camera is close to.
+[[stage(vertex)]]
+fn light_volume(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[builtin(instance_index)]] light_index: u32,
+) -> LightVolumeOutput {
+    let light = lights.data[light_index];
+    var low: vec2<f32> = vec2<f32>(-1.0);
+    var high: vec2<f32> = vec2<f32>(1.0);
+
+    if (light.kind != LIGHT_DIRECTIONAL) {
+        var bounds_low: vec2<f32> = vec2<f32>(1000000.0);
+        var bounds_high: vec2<f32> = vec2<f32>(-1000000.0);
+        var behind: bool = false;
+        for (var i: u32 = 0u; i < 8u; i = i + 1u) {
+            let side = vec3<f32>(f32(i & 1u), f32((i >> 1u) & 1u), f32((i >> 2u) & 1u)) * 2.0 - 1.0;
+            let corner = camera.view_proj * vec4<f32>(light.position.xyz + side * light.position.w, 1.0);
+            // A box corner behind the camera can project anywhere.
+            if (corner.w < 0.0001) {
+                behind = true;
+            }
+            let ndc = corner.xy / corner.w;
+            bounds_low = min(bounds_low, ndc);
+            bounds_high = max(bounds_high, ndc);
+        }
+        if (!behind) {
+            low = clamp(bounds_low, vec2<f32>(-1.0), vec2<f32>(1.0));
+            high = clamp(bounds_high, vec2<f32>(-1.0), vec2<f32>(1.0));
+        }
+    }
+
+    var out: LightVolumeOutput;
+    out.clip_position = vec4<f32>(mix(low, high, quad_corners[vertex_index % 6u]), 0.0, 1.0);
+    out.light_index = light_index;
+    return out;
+}
+
+// Adds one light to the HDR target for every G-buffer pixel under its quad.
+[[stage(fragment)]]
+fn deferred_light(in: LightVolumeOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_gbuffer_depth, coords, 0);
+    // Nothing was drawn here.
+    if (depth >= 1.0) {
+        discard;
+    }
+    let albedo = textureLoad(t_gbuffer_albedo, coords, 0);
+    let normal = textureLoad(t_gbuffer_normal, coords, 0);
+
+    let uv = in.clip_position.xy / vec2<f32>(textureDimensions(t_gbuffer_depth));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+    let world_position = world.xyz / world.w;
+
+    var surface: Surface;
+    surface.base_color = vec4<f32>(albedo.rgb, 1.0);
+    surface.normal = normalize(normal.xyz);
+    surface.metallic = albedo.a;
+    surface.roughness = normal.w;
+    // Occlusion and emission only go into the G-buffer pass' ambient term.
+    surface.occlusion = 1.0;
+    surface.emissive = vec3<f32>(0.0);
+
+    let color = direct_light(in.light_index, surface, world_position, in.clip_position.xy);
+    return vec4<f32>(color, 0.0);
+}
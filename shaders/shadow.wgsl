@@ -0,0 +1,52 @@
+// Renders scene depth from the shadow-casting light into one cascade.
+
+[[block]]
+struct Cascade {
+    view_proj: mat4x4<f32>;
+    // xy: exponents for the moments
+    warp: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> cascade: Cascade;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> [[builtin(position)]] vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return cascade.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+}
+
+// Depth and its square, or with nonzero exponents the exponentially warped
+// depth and its square for both signs. Mirrors `ShadowFilter::moments`.
+fn warp_moments(depth: f32, exponents: vec2<f32>) -> vec4<f32> {
+    if (exponents.x <= 0.0 && exponents.y <= 0.0) {
+        return vec4<f32>(depth, depth * depth, 0.0, 0.0);
+    }
+    let d = depth * 2.0 - 1.0;
+    let positive = exp(exponents.x * d);
+    let negative = -exp(-exponents.y * d);
+    return vec4<f32>(positive, positive * positive, negative, negative * negative);
+}
+
+// Only used by the variance filters, which sample moments instead of depth.
+[[stage(fragment)]]
+fn moments([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return warp_moments(position.z, cascade.warp.xy);
+}
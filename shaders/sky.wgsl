@@ -0,0 +1,186 @@
+// Fills the pixels the scene's geometry left empty with the sky, at the far
+// plane behind everything else.
+
+[[block]]
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+    // view_proj without the TAA jitter
+    unjittered_view_proj: mat4x4<f32>;
+    // The previous frame's unjittered_view_proj
+    previous_view_proj: mat4x4<f32>;
+    // Inverse of view_proj, to reconstruct positions from depth
+    inverse_view_proj: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Atmosphere {
+    // xyz: direction towards the sun, w: sun intensity
+    sun: vec4<f32>;
+    // rgb: Rayleigh scattering at sea level, a: its scale height
+    rayleigh: vec4<f32>;
+    mie_scattering: f32;
+    mie_scale_height: f32;
+    // Mie phase function asymmetry
+    mie_anisotropy: f32;
+    planet_radius: f32;
+    atmosphere_radius: f32;
+    // Height of the viewer above the ground
+    altitude: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> atmosphere: Atmosphere;
+[[group(0), binding(1)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(2)]]
+var s_environment: sampler;
+
+[[block]]
+struct Environment {
+    intensity: f32;
+    // Zero until an environment map is loaded
+    enabled: u32;
+    // Mip of t_prefiltered for roughness 1
+    max_lod: f32;
+};
+[[group(0), binding(3)]]
+var<uniform> environment: Environment;
+
+let PI: f32 = 3.14159265359;
+let PRIMARY_STEPS: u32 = 16u;
+let LIGHT_STEPS: u32 = 8u;
+// Mie scattering removes a bit more light than it scatters.
+let MIE_EXTINCTION: f32 = 1.1;
+// Cosine of the sun's angular radius.
+let SUN_DISK_COS: f32 = 0.99998;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen at the far plane.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(out.ndc, 1.0, 1.0);
+    return out;
+}
+
+struct FragmentOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] velocity: vec2<f32>;
+};
+
+// World space direction through the pixel at `ndc`.
+fn view_direction(ndc: vec2<f32>) -> vec3<f32> {
+    let near = camera.inverse_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = camera.inverse_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+// The sky is infinitely far away, so it only moves when the camera turns.
+fn sky_output(color: vec3<f32>, direction: vec3<f32>) -> FragmentOutput {
+    let current = camera.unjittered_view_proj * vec4<f32>(direction, 0.0);
+    let previous = camera.previous_view_proj * vec4<f32>(direction, 0.0);
+    let ndc_motion = current.xy / current.w - previous.xy / previous.w;
+    // Texture coordinates run down, normalized device coordinates up.
+    return FragmentOutput(vec4<f32>(color, 1.0), ndc_motion * vec2<f32>(0.5, -0.5));
+}
+
+[[stage(fragment)]]
+fn environment_sky(in: VertexOutput) -> FragmentOutput {
+    let direction = view_direction(in.ndc);
+    let color = textureSampleLevel(t_environment, s_environment, direction, 0.0).rgb;
+    return sky_output(color * environment.intensity, direction);
+}
+
+// Distances along the ray from `origin` in `direction` to where it enters
+// and leaves a sphere of `radius` around the planet's center. The entry is
+// past the exit when the ray misses.
+fn ray_sphere(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b = dot(origin, direction);
+    let distance = length(origin);
+    // Factored to keep precision at planetary scales.
+    let c = (distance - radius) * (distance + radius);
+    let discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return vec2<f32>(1.0, -1.0);
+    }
+    let root = sqrt(discriminant);
+    return vec2<f32>(-b - root, -b + root);
+}
+
+// Rayleigh and Mie densities integrated from `origin` to the edge of the
+// atmosphere towards the sun, or a negative value if the planet is in the
+// way.
+fn sun_optical_depth(origin: vec3<f32>, sun: vec3<f32>) -> vec2<f32> {
+    let step = ray_sphere(origin, sun, atmosphere.atmosphere_radius).y / f32(LIGHT_STEPS);
+    var depth: vec2<f32> = vec2<f32>(0.0);
+    for (var i: u32 = 0u; i < LIGHT_STEPS; i = i + 1u) {
+        let height = length(origin + sun * (f32(i) + 0.5) * step) - atmosphere.planet_radius;
+        if (height < 0.0) {
+            return vec2<f32>(-1.0);
+        }
+        depth = depth + vec2<f32>(exp(-height / atmosphere.rayleigh.a), exp(-height / atmosphere.mie_scale_height)) * step;
+    }
+    return depth;
+}
+
+fn extinction(optical_depth: vec2<f32>) -> vec3<f32> {
+    return exp(-(atmosphere.rayleigh.rgb * optical_depth.x + vec3<f32>(atmosphere.mie_scattering * MIE_EXTINCTION * optical_depth.y)));
+}
+
+// Single scattering of sunlight along the view ray, marched through the
+// atmosphere until it leaves it or hits the ground.
+[[stage(fragment)]]
+fn atmosphere_sky(in: VertexOutput) -> FragmentOutput {
+    let direction = view_direction(in.ndc);
+    let sun = normalize(atmosphere.sun.xyz);
+    let origin = vec3<f32>(0.0, atmosphere.planet_radius + atmosphere.altitude, 0.0);
+
+    let outer = ray_sphere(origin, direction, atmosphere.atmosphere_radius);
+    let ground = ray_sphere(origin, direction, atmosphere.planet_radius);
+    let hits_ground = ground.x <= ground.y && ground.x > 0.0;
+    var end: f32 = outer.y;
+    if (hits_ground) {
+        end = ground.x;
+    }
+    let start = max(outer.x, 0.0);
+    let step = (end - start) / f32(PRIMARY_STEPS);
+
+    var optical_depth: vec2<f32> = vec2<f32>(0.0);
+    var rayleigh: vec3<f32> = vec3<f32>(0.0);
+    var mie: vec3<f32> = vec3<f32>(0.0);
+    for (var i: u32 = 0u; i < PRIMARY_STEPS; i = i + 1u) {
+        let position = origin + direction * (start + (f32(i) + 0.5) * step);
+        let height = length(position) - atmosphere.planet_radius;
+        let density = vec2<f32>(exp(-height / atmosphere.rayleigh.a), exp(-height / atmosphere.mie_scale_height)) * step;
+        optical_depth = optical_depth + density;
+
+        let sun_depth = sun_optical_depth(position, sun);
+        if (sun_depth.x >= 0.0) {
+            let attenuation = extinction(optical_depth + sun_depth);
+            rayleigh = rayleigh + attenuation * density.x;
+            mie = mie + attenuation * density.y;
+        }
+    }
+
+    let mu = dot(direction, sun);
+    let g = atmosphere.mie_anisotropy;
+    let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    let mie_phase = 3.0 / (8.0 * PI) * ((1.0 - g * g) * (1.0 + mu * mu))
+        / ((2.0 + g * g) * pow(1.0 + g * g - 2.0 * g * mu, 1.5));
+    var color: vec3<f32> = rayleigh * atmosphere.rayleigh.rgb * rayleigh_phase
+        + mie * atmosphere.mie_scattering * mie_phase;
+
+    // The sun itself, dimmed by the air in front of it.
+    if (!hits_ground && mu > SUN_DISK_COS) {
+        color = color + extinction(optical_depth);
+    }
+    return sky_output(color * atmosphere.sun.w, direction);
+}
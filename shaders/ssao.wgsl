@@ -0,0 +1,188 @@
+// Screen space ambient occlusion from the camera pass' depth buffer.
+//
+// The depth buffer is first turned into linear view depth, from which view
+// space positions and normals are reconstructed. Occlusion counts the
+// samples of a hemisphere kernel around each position that end up behind
+// the depth buffer, and is then blurred without bleeding across edges. The
+// view depth and occlusion may be smaller than the depth buffer and the
+// final target, so pixels are matched up by texture coordinates.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_depth: texture_depth_2d;
+[[group(0), binding(1)]]
+var t_depth_multisampled: texture_depth_multisampled_2d;
+[[group(0), binding(2)]]
+var t_view_depth: texture_2d<f32>;
+[[group(0), binding(3)]]
+var t_noise: texture_2d<f32>;
+[[group(0), binding(4)]]
+var t_occlusion: texture_2d<f32>;
+
+[[block]]
+struct Ssao {
+    // The camera's projection, jittered like the depth buffer
+    projection: mat4x4<f32>;
+    inverse_projection: mat4x4<f32>;
+    // Offsets in the unit hemisphere around +z, denser towards the center
+    kernel: array<vec4<f32>, 64>;
+    radius: f32;
+    intensity: f32;
+    bias: f32;
+    sample_count: u32;
+};
+[[group(0), binding(5)]]
+var<uniform> ssao: Ssao;
+
+// How quickly blur weights fall off with relative depth difference.
+let BLUR_SHARPNESS: f32 = 400.0;
+let BLUR_RADIUS: i32 = 4;
+
+fn view_depth(ndc: vec2<f32>, depth: f32) -> f32 {
+    // Nothing was drawn here.
+    if (depth >= 1.0) {
+        return 0.0;
+    }
+    let view = ssao.inverse_projection * vec4<f32>(ndc, depth, 1.0);
+    return -view.z / view.w;
+}
+
+fn to_ndc(tex_coords: vec2<f32>) -> vec2<f32> {
+    return tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+}
+
+// Distance from the camera along its view direction, 0 for the background.
+[[stage(fragment)]]
+fn linearize(in: VertexOutput) -> [[location(0)]] f32 {
+    let coords = vec2<i32>(in.tex_coords * vec2<f32>(textureDimensions(t_depth)));
+    let depth = textureLoad(t_depth, coords, 0);
+    return view_depth(to_ndc(in.tex_coords), depth);
+}
+
+// Like `linearize`, for a multisampled depth buffer, from its first sample.
+[[stage(fragment)]]
+fn linearize_multisampled(in: VertexOutput) -> [[location(0)]] f32 {
+    let coords = vec2<i32>(in.tex_coords * vec2<f32>(textureDimensions(t_depth_multisampled)));
+    let depth = textureLoad(t_depth_multisampled, coords, 0);
+    return view_depth(to_ndc(in.tex_coords), depth);
+}
+
+fn load_view_depth(coords: vec2<i32>) -> f32 {
+    let size = textureDimensions(t_view_depth);
+    return textureLoad(t_view_depth, clamp(coords, vec2<i32>(0), size - 1), 0).r;
+}
+
+// View space position of the pixel at `coords`.
+fn view_position(coords: vec2<i32>) -> vec3<f32> {
+    let tex_coords = (vec2<f32>(coords) + 0.5) / vec2<f32>(textureDimensions(t_view_depth));
+    let far = ssao.inverse_projection * vec4<f32>(to_ndc(tex_coords), 1.0, 1.0);
+    let ray = far.xyz / far.w;
+    return ray * (load_view_depth(coords) / -ray.z);
+}
+
+// Facing normal from the neighbors on the same surface: on each axis, the
+// one closer in depth.
+fn view_normal(coords: vec2<i32>, position: vec3<f32>) -> vec3<f32> {
+    let left = view_position(coords - vec2<i32>(1, 0));
+    let right = view_position(coords + vec2<i32>(1, 0));
+    let up = view_position(coords - vec2<i32>(0, 1));
+    let down = view_position(coords + vec2<i32>(0, 1));
+
+    var dx: vec3<f32> = right - position;
+    if (abs(position.z - left.z) < abs(right.z - position.z)) {
+        dx = position - left;
+    }
+    var dy: vec3<f32> = position - up;
+    if (abs(down.z - position.z) < abs(position.z - up.z)) {
+        dy = down - position;
+    }
+    let normal = normalize(cross(dy, dx));
+    if (dot(normal, position) > 0.0) {
+        return -normal;
+    }
+    return normal;
+}
+
+// Fraction of the hemisphere above each pixel left open, raised to the
+// configured intensity.
+[[stage(fragment)]]
+fn occlusion(in: VertexOutput) -> [[location(0)]] f32 {
+    let coords = vec2<i32>(in.clip_position.xy);
+    if (load_view_depth(coords) <= 0.0) {
+        return 1.0;
+    }
+    let position = view_position(coords);
+    let normal = view_normal(coords, position);
+
+    // A random rotation around the normal per pixel of a small tile, which
+    // the blur then averages away.
+    let noise_size = textureDimensions(t_noise);
+    let random = textureLoad(t_noise, coords % noise_size, 0).xyz;
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occluded: f32 = 0.0;
+    let count = min(ssao.sample_count, 64u);
+    for (var i: u32 = 0u; i < count; i = i + 1u) {
+        let sample = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = ssao.projection * vec4<f32>(sample, 1.0);
+        let tex_coords = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        let scene_depth = load_view_depth(vec2<i32>(tex_coords * vec2<f32>(textureDimensions(t_view_depth))));
+        let sample_depth = -sample.z;
+        if (scene_depth > 0.0 && scene_depth <= sample_depth - ssao.bias) {
+            // Geometry far in front of the sample doesn't occlude it.
+            let range = smoothStep(0.0, 1.0, ssao.radius / abs(position.z + scene_depth));
+            occluded = occluded + range;
+        }
+    }
+    let open = 1.0 - occluded / f32(max(count, 1u));
+    return pow(open, ssao.intensity);
+}
+
+// Gaussian blur along `direction` that leaves out pixels at a different
+// depth, so occlusion doesn't spread across silhouettes. Works at the size of
+// the view depth whatever the size of the target.
+fn blur(tex_coords: vec2<f32>, direction: vec2<i32>) -> f32 {
+    let coords = vec2<i32>(tex_coords * vec2<f32>(textureDimensions(t_view_depth)));
+    let center_depth = load_view_depth(coords);
+    if (center_depth <= 0.0) {
+        return 1.0;
+    }
+    let size = textureDimensions(t_occlusion);
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    for (var i: i32 = -BLUR_RADIUS; i <= BLUR_RADIUS; i = i + 1) {
+        let tap = clamp(coords + direction * i, vec2<i32>(0), size - 1);
+        let difference = (load_view_depth(tap) - center_depth) / center_depth;
+        let offset = f32(i) / f32(BLUR_RADIUS);
+        let weight = exp(-2.0 * offset * offset - BLUR_SHARPNESS * difference * difference);
+        sum = sum + textureLoad(t_occlusion, tap, 0).r * weight;
+        total = total + weight;
+    }
+    return sum / total;
+}
+
+[[stage(fragment)]]
+fn blur_horizontal(in: VertexOutput) -> [[location(0)]] f32 {
+    return blur(in.tex_coords, vec2<i32>(1, 0));
+}
+
+[[stage(fragment)]]
+fn blur_vertical(in: VertexOutput) -> [[location(0)]] f32 {
+    return blur(in.tex_coords, vec2<i32>(0, 1));
+}
@@ -0,0 +1,79 @@
+// Temporal anti-aliasing: blends each jittered frame into a history that is
+// reprojected along the motion vectors. The history is clamped to the color
+// range around the pixel in the current frame, so stale history from
+// disoccluded or changing surfaces can't ghost.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_current: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_history: texture_2d<f32>;
+[[group(0), binding(2)]]
+var t_velocity: texture_2d<f32>;
+[[group(0), binding(3)]]
+var s_linear: sampler;
+
+[[block]]
+struct Taa {
+    // Weight of the current frame in the blend
+    blend: f32;
+    // Zero when the history holds nothing usable
+    history_valid: u32;
+};
+[[group(0), binding(4)]]
+var<uniform> taa: Taa;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Karis' luminance weight, which keeps single bright samples from
+// flickering through the blend.
+fn blend_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + luminance(color));
+}
+
+[[stage(fragment)]]
+fn resolve(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let uv = in.tex_coords;
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_current));
+    let current = textureSampleLevel(t_current, s_linear, uv, 0.0).rgb;
+    if (taa.history_valid == 0u) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    let history_uv = uv - textureSampleLevel(t_velocity, s_linear, uv, 0.0).xy;
+    if (history_uv.x < 0.0 || history_uv.y < 0.0 || history_uv.x > 1.0 || history_uv.y > 1.0) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    var minimum: vec3<f32> = current;
+    var maximum: vec3<f32> = current;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let neighbor = textureSampleLevel(t_current, s_linear, uv + texel * vec2<f32>(f32(x), f32(y)), 0.0).rgb;
+            minimum = min(minimum, neighbor);
+            maximum = max(maximum, neighbor);
+        }
+    }
+    let history = clamp(textureSampleLevel(t_history, s_linear, history_uv, 0.0).rgb, minimum, maximum);
+
+    let current_weight = taa.blend * blend_weight(current);
+    let history_weight = (1.0 - taa.blend) * blend_weight(history);
+    let color = (current * current_weight + history * history_weight) / max(current_weight + history_weight, 0.0001);
+    return vec4<f32>(color, 1.0);
+}
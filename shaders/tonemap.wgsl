@@ -0,0 +1,128 @@
+// Tonemaps the HDR scene color into the output target.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_hdr: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_hdr: sampler;
+
+[[block]]
+struct Tonemap {
+    operator: u32;
+    // Linear scale applied before the operator
+    exposure: f32;
+    encode_srgb: u32;
+    auto_exposure: u32;
+};
+[[group(0), binding(2)]]
+var<uniform> tonemap: Tonemap;
+
+[[block]]
+struct Exposure {
+    // Adapted average luminance, or 0 before the first frame
+    luminance: f32;
+};
+[[group(0), binding(3)]]
+var<uniform> exposure: Exposure;
+
+// Luminance the adapted average is mapped to.
+let MIDDLE_GRAY: f32 = 0.18;
+
+let OPERATOR_REINHARD: u32 = 0u;
+let OPERATOR_ACES: u32 = 1u;
+let OPERATOR_AGX: u32 = 2u;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0) + color);
+}
+
+// Stephen Hill's fit of the ACES RRT and ODT, from linear sRGB to linear sRGB.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial fit of the AgX base contrast curve.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+// Minimal AgX with the default look, from linear sRGB to linear sRGB.
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v: vec3<f32> = inset * max(color, vec3<f32>(0.0000000001));
+    v = clamp(log2(v), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+    v = outset * agx_contrast(v);
+    // The curve outputs display encoded values.
+    return pow(max(v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var scale: f32 = tonemap.exposure;
+    if (tonemap.auto_exposure != 0u && exposure.luminance > 0.0) {
+        scale = scale * MIDDLE_GRAY / exposure.luminance;
+    }
+    let hdr = textureSample(t_hdr, s_hdr, in.tex_coords).rgb * scale;
+
+    var color: vec3<f32>;
+    if (tonemap.operator == OPERATOR_REINHARD) {
+        color = reinhard(hdr);
+    } elseif (tonemap.operator == OPERATOR_AGX) {
+        color = agx(hdr);
+    } else {
+        color = aces(hdr);
+    }
+
+    if (tonemap.encode_srgb != 0u) {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
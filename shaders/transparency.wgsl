@@ -0,0 +1,35 @@
+// Resolves weighted blended order independent transparency: the weighted
+// average of the transparent fragments' colors covers the opaque scene as
+// much as the product of their alphas hides it.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+// A single triangle that covers the whole screen.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_accumulation: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_revealage: texture_2d<f32>;
+
+// Blended over the scene with the source alpha.
+[[stage(fragment)]]
+fn composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let revealage = textureLoad(t_revealage, coords, 0).r;
+    // Nothing transparent here.
+    if (revealage >= 1.0) {
+        discard;
+    }
+    let accumulation = textureLoad(t_accumulation, coords, 0);
+    let color = accumulation.rgb / max(accumulation.a, 0.00001);
+    return vec4<f32>(color, 1.0 - revealage);
+}
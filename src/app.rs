@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use crate::engine::{Engine, EngineOptions};
+
+/// Hooks a game or tool implements to drive the engine.
+pub trait App {
+    /// Called once after the engine has been created, before the first frame.
+    fn init(&mut self, engine: &mut Engine) -> anyhow::Result<()>;
+
+    /// Called every frame before the engine uploads its uniforms.
+    fn update(&mut self, _engine: &mut Engine, _dt: Duration) {}
+
+    /// Called every frame to draw. The default renders the engine's scene.
+    fn render(&mut self, engine: &mut Engine) -> Result<(), wgpu::SurfaceError> {
+        engine.render()
+    }
+
+    /// Returns true if the event was consumed and should not reach the engine.
+    fn input(&mut self, _engine: &mut Engine, _event: &WindowEvent) -> bool {
+        false
+    }
+}
+
+/// Opens a window and runs `app` until the window is closed.
+pub fn run<A: App + 'static>(mut app: A, options: EngineOptions) -> ! {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+
+    // Since main can't be async, we're going to need to block
+    let mut engine = pollster::block_on(Engine::new(&window, &options));
+    app.init(&mut engine).unwrap();
+
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == window.id()
+                && !app.input(&mut engine, event)
+                && !engine.input(event) =>
+            {
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(physical_size) => {
+                        engine.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        // new_inner_size is &&mut so w have to dereference it twice
+                        engine.resize(**new_inner_size);
+                    }
+                    _ => {}
+                }
+            }
+            Event::RedrawRequested(_) => {
+                let now = Instant::now();
+                let dt = now - last_frame;
+                last_frame = now;
+
+                app.update(&mut engine, dt);
+                engine.update(dt);
+                match app.render(&mut engine) {
+                    Ok(_) => {}
+                    // Recreate the swap_chain if lost
+                    Err(wgpu::SurfaceError::Lost) => engine.resize(engine.size()),
+                    // The system is out of memory, we should probably quit
+                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    // All other errors (Outdated, Timeout) should be resolved by the next frame
+                    Err(e) => eprintln!("{:?}", e),
+                }
+            }
+            Event::MainEventsCleared => {
+                // RedrawRequested will only trigger once, unless we manually
+                // request it.
+                window.request_redraw();
+            }
+            _ => {}
+        }
+    })
+}
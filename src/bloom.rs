@@ -0,0 +1,362 @@
+//! Bloom on the HDR target.
+//!
+//! The bright parts of the frame are downsampled into a chain of targets at
+//! half, a quarter and so on of the window's resolution with a 13-tap filter, then upsampled back up the chain
+//! with a tent filter, each level adding onto the next larger one. The first
+//! mip ends up holding the sum of every level, and is added to the HDR
+//! target before tonemapping.
+
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use crate::{
+    pipeline::{PipelineBuilder, PipelineCache},
+    rendertarget::{RenderTargets, TargetDesc, TargetId, TargetSize},
+    texture,
+};
+
+/// Most mips in the chain. Fewer are used when the target is too small.
+pub const MAX_BLOOM_MIPS: u32 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomOptions {
+    /// Brightness above which pixels bloom.
+    pub threshold: f32,
+    /// Width of the soft transition around `threshold`.
+    pub knee: f32,
+    /// Scale of the bloom added to the HDR target.
+    pub intensity: f32,
+    /// Radius of the upsample filter in texels. Larger values spread the
+    /// glow further at the cost of blockiness.
+    pub radius: f32,
+}
+
+impl Default for BloomOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            radius: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+impl From<&BloomOptions> for BloomUniform {
+    fn from(options: &BloomOptions) -> Self {
+        Self {
+            threshold: options.threshold.max(0.0),
+            knee: options.knee.max(0.0),
+            intensity: options.intensity.max(0.0),
+            radius: options.radius.max(0.0),
+        }
+    }
+}
+
+/// Number of mips in the chain for a target of `width` by `height`, halving
+/// until the smaller side would drop below one texel.
+pub fn bloom_mip_count(width: u32, height: u32) -> u32 {
+    let smallest = (width.min(height) / 2).max(1);
+    (32 - smallest.leading_zeros()).min(MAX_BLOOM_MIPS)
+}
+
+/// The mip chain and pipelines for bloom.
+pub struct BloomPass {
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    prefilter_pipeline: Arc<wgpu::RenderPipeline>,
+    downsample_pipeline: Arc<wgpu::RenderPipeline>,
+    upsample_pipeline: Arc<wgpu::RenderPipeline>,
+    composite_pipeline: Arc<wgpu::RenderPipeline>,
+    /// Blooms in place.
+    hdr: TargetId,
+    /// Every mip there can be, of which the first [`BloomPass::mip_count`]
+    /// are used.
+    mips: Vec<TargetId>,
+    /// Reads the HDR target.
+    prefilter_bind_group: wgpu::BindGroup,
+    /// Reads the mip with the same index, for the mips in use.
+    mip_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl BloomPass {
+    /// Blooms `hdr`, and adds the mips to `targets`.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        targets: &mut RenderTargets,
+        hdr: TargetId,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom.sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[BloomUniform::from(&BloomOptions::default())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("bloom.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/bloom.wgsl")));
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let mut pipeline = |entry_point, blend| {
+            pipelines.get(
+                device,
+                &PipelineBuilder::new(entry_point, &pipeline_layout, &shader)
+                    .fragment(entry_point)
+                    .color_target(texture::Texture::HDR_FORMAT, Some(blend))
+                    .cull_mode(None),
+            )
+        };
+        let prefilter_pipeline = pipeline("prefilter", wgpu::BlendState::REPLACE);
+        let downsample_pipeline = pipeline("downsample", wgpu::BlendState::REPLACE);
+        let upsample_pipeline = pipeline("upsample", additive);
+        let composite_pipeline = pipeline("composite", additive);
+
+        let mips = (0..MAX_BLOOM_MIPS)
+            .map(|mip| {
+                targets.add(
+                    device,
+                    TargetDesc {
+                        label: "bloom_mip",
+                        size: TargetSize::Window {
+                            scale: 0.5f32.powi(mip as i32 + 1),
+                        },
+                        format: texture::Texture::HDR_FORMAT,
+                        sample_count: 1,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        let (prefilter_bind_group, mip_bind_groups) = Self::create_bind_groups(
+            device,
+            targets,
+            hdr,
+            &mips,
+            &layout,
+            &sampler,
+            &uniform_buffer,
+        );
+
+        Self {
+            layout,
+            sampler,
+            uniform_buffer,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            hdr,
+            mips,
+            prefilter_bind_group,
+            mip_bind_groups,
+        }
+    }
+
+    /// Bind groups reading the HDR target and each of the mips in use at
+    /// its current size.
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        targets: &RenderTargets,
+        hdr: TargetId,
+        mips: &[TargetId],
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> (wgpu::BindGroup, Vec<wgpu::BindGroup>) {
+        let bind_group = |source: TargetId| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom.bind_group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&targets.get(source).view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let (width, height) = targets.size(hdr);
+        let mip_count = bloom_mip_count(width, height) as usize;
+        let prefilter_bind_group = bind_group(hdr);
+        let mip_bind_groups = mips[..mip_count].iter().copied().map(bind_group).collect();
+
+        (prefilter_bind_group, mip_bind_groups)
+    }
+
+    /// Points the bind groups at the recreated targets, e.g. after a resize.
+    pub fn set_input(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        let (prefilter_bind_group, mip_bind_groups) = Self::create_bind_groups(
+            device,
+            targets,
+            self.hdr,
+            &self.mips,
+            &self.layout,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+        self.prefilter_bind_group = prefilter_bind_group;
+        self.mip_bind_groups = mip_bind_groups;
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, options: &BloomOptions) {
+        let uniform = BloomUniform::from(options);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Number of mips in use at the current size.
+    pub fn mip_count(&self) -> u32 {
+        self.mip_bind_groups.len() as u32
+    }
+
+    /// Blooms the HDR target in place.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, targets: &RenderTargets) {
+        let mip_count = self.mip_bind_groups.len();
+        let mip_view = |mip: usize| &targets.get(self.mips[mip]).view;
+
+        self.draw(
+            encoder,
+            "bloom.prefilter",
+            mip_view(0),
+            true,
+            &self.prefilter_pipeline,
+            &self.prefilter_bind_group,
+        );
+        for mip in 1..mip_count {
+            self.draw(
+                encoder,
+                "bloom.downsample",
+                mip_view(mip),
+                true,
+                &self.downsample_pipeline,
+                &self.mip_bind_groups[mip - 1],
+            );
+        }
+        for mip in (1..mip_count).rev() {
+            self.draw(
+                encoder,
+                "bloom.upsample",
+                mip_view(mip - 1),
+                false,
+                &self.upsample_pipeline,
+                &self.mip_bind_groups[mip],
+            );
+        }
+        self.draw(
+            encoder,
+            "bloom.composite",
+            &targets.get(self.hdr).view,
+            false,
+            &self.composite_pipeline,
+            &self.mip_bind_groups[0],
+        );
+    }
+
+    /// Draws a fullscreen triangle into `view`, clearing it first if `clear`
+    /// is set and blending onto it otherwise.
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        view: &wgpu::TextureView,
+        clear: bool,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -14,13 +14,74 @@ pub struct Camera {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    /// Sub-pixel offset of the projection in normalized device coordinates,
+    /// set by the engine every frame while TAA is on.
+    pub jitter: cgmath::Vector2<f32>,
 }
 
 impl Camera {
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        proj * view
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// Distance of `point` from the eye along the view direction.
+    pub fn view_depth(&self, point: cgmath::Point3<f32>) -> f32 {
+        use cgmath::InnerSpace;
+
+        (self.target - self.eye).normalize().dot(point - self.eye)
+    }
+
+    /// World space corners of the frustum between the view depths `near` and
+    /// `far`: the four near corners followed by the four far ones.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [cgmath::Point3<f32>; 8] {
+        use cgmath::InnerSpace;
+
+        let forward = (self.target - self.eye).normalize();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward);
+        let tan_half_fovy = (self.fovy / 2.0).to_radians().tan();
+
+        let corners = |depth: f32| {
+            let half_height = depth * tan_half_fovy;
+            let half_width = half_height * self.aspect;
+            let center = self.eye + forward * depth;
+            [
+                center - right * half_width - up * half_height,
+                center + right * half_width - up * half_height,
+                center + right * half_width + up * half_height,
+                center - right * half_width + up * half_height,
+            ]
+        };
+        let [n0, n1, n2, n3] = corners(near);
+        let [f0, f1, f2, f3] = corners(far);
+        [n0, n1, n2, n3, f0, f1, f2, f3]
+    }
+
+    /// Projection times view, shifted by `jitter` after the perspective
+    /// divide.
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.jitter_matrix() * self.build_unjittered_view_projection_matrix()
+    }
+
+    /// Projection times view without `jitter`, for motion vectors.
+    pub fn build_unjittered_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.perspective_matrix() * self.view_matrix()
+    }
+
+    /// The projection alone, shifted by `jitter` like
+    /// [`Camera::build_view_projection_matrix`].
+    pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.jitter_matrix() * self.perspective_matrix()
+    }
+
+    fn perspective_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    fn jitter_matrix(&self) -> cgmath::Matrix4<f32> {
+        // Clip space is scaled by w, so translating it before the divide
+        // moves every point by exactly `jitter` afterwards.
+        cgmath::Matrix4::from_translation(self.jitter.extend(0.0))
     }
 }
 
@@ -29,6 +90,18 @@ impl Camera {
 pub struct CameraUniform {
     view_pos: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    /// `view_proj` without the TAA jitter.
+    unjittered_view_proj: [[f32; 4]; 4],
+    /// The previous frame's `unjittered_view_proj`.
+    previous_view_proj: [[f32; 4]; 4],
+    /// Inverse of `view_proj`, to reconstruct positions from depth.
+    inverse_view_proj: [[f32; 4]; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraUniform {
@@ -37,11 +110,31 @@ impl CameraUniform {
         Self {
             view_pos: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            unjittered_view_proj: cgmath::Matrix4::identity().into(),
+            previous_view_proj: cgmath::Matrix4::identity().into(),
+            inverse_view_proj: cgmath::Matrix4::identity().into(),
         }
     }
 
+    /// Moves on to a new frame seen from `camera`, keeping the last one's
+    /// projection for motion vectors.
     pub fn update_view_proj(&mut self, camera: &Camera) {
+        use cgmath::SquareMatrix;
+        self.previous_view_proj = self.unjittered_view_proj;
         self.view_pos = camera.eye.to_homogeneous().into();
-        self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
+        let view_proj = OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        self.inverse_view_proj = view_proj
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
+            .into();
+        self.unjittered_view_proj =
+            (OPENGL_TO_WGPU_MATRIX * camera.build_unjittered_view_projection_matrix()).into();
+    }
+
+    /// Forgets the previous frame, so nothing appears to move from it, e.g.
+    /// after the camera cut to somewhere else.
+    pub fn reset_motion(&mut self) {
+        self.previous_view_proj = self.unjittered_view_proj;
     }
 }
@@ -0,0 +1,213 @@
+//! Clustered light culling for the forward path.
+//!
+//! The view frustum is split into a grid of clusters: tiles across the
+//! screen, each cut into slices along the view depth that grow exponentially
+//! with distance. A compute pass tests the bounding sphere of every point and
+//! spot light against the box around every cluster and writes a list of the
+//! lights reaching it. The camera pass' fragment shader then only walks the
+//! list of the cluster it falls into, instead of every light in the scene.
+
+use crate::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+
+/// Clusters across the screen, down the screen, and along the view depth.
+/// Matches `CLUSTER_GRID_*` in `cluster.wgsl` and `shader.wgsl`.
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Lights a cluster can hold. Any more that reach it are left out.
+/// Matches the length of `Cluster::lights` in the shaders.
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+/// Invocations per workgroup of the culling pass, one per cluster.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Total number of clusters.
+pub fn cluster_count() -> u32 {
+    CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]
+}
+
+/// View depth at which depth slice `slice` starts. Slices split the range
+/// between `znear` and `zfar` evenly in log space, so each is about as deep
+/// as it is wide on screen.
+pub fn slice_depth(slice: u32, znear: f32, zfar: f32) -> f32 {
+    znear * (zfar / znear).powf(slice as f32 / CLUSTER_GRID[2] as f32)
+}
+
+/// Depth slice containing `view_depth`, clamped to the grid.
+pub fn depth_slice(view_depth: f32, znear: f32, zfar: f32) -> u32 {
+    let t = (view_depth / znear).ln() / (zfar / znear).ln();
+    ((t * CLUSTER_GRID[2] as f32).max(0.0) as u32).min(CLUSTER_GRID[2] - 1)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterUniform {
+    inverse_projection: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    screen_size: [f32; 2],
+    znear: f32,
+    zfar: f32,
+}
+
+impl ClusterUniform {
+    fn new(camera: &Camera, width: u32, height: u32) -> Self {
+        use cgmath::SquareMatrix;
+
+        let projection = OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(
+                cgmath::Deg(camera.fovy),
+                camera.aspect,
+                camera.znear,
+                camera.zfar,
+            );
+        Self {
+            inverse_projection: projection
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity)
+                .into(),
+            view: camera.view_matrix().into(),
+            screen_size: [width.max(1) as f32, height.max(1) as f32],
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+}
+
+/// The cluster light lists and the compute pass that fills them.
+pub struct ClusterPass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// The grid's camera and screen size, also read by the camera pass.
+    pub uniform_buffer: wgpu::Buffer,
+    /// A light count and [`MAX_LIGHTS_PER_CLUSTER`] light indices for every
+    /// cluster, the tiles of the nearest slice first.
+    pub lists_buffer: wgpu::Buffer,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl ClusterPass {
+    /// Culls the lights in `lights_buffer`, laid out as the engine's light
+    /// storage buffer.
+    pub fn new(device: &wgpu::Device, lights_buffer: &wgpu::Buffer) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cluster Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+            ],
+        });
+
+        // Written by `update` before the first frame.
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster.uniform_buffer"),
+            size: std::mem::size_of::<ClusterUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let lists_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster.lists_buffer"),
+            size: (cluster_count() * (1 + MAX_LIGHTS_PER_CLUSTER) * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &uniform_buffer,
+            lights_buffer,
+            &lists_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cluster.pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("../shaders/cluster.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cluster.pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "assign_lights",
+        });
+
+        Self {
+            layout,
+            bind_group,
+            uniform_buffer,
+            lists_buffer,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        lights_buffer: &wgpu::Buffer,
+        lists_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cluster.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lists_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Culls a new light storage buffer, e.g. after it has grown.
+    pub fn set_lights(&mut self, device: &wgpu::Device, lights_buffer: &wgpu::Buffer) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.uniform_buffer,
+            lights_buffer,
+            &self.lists_buffer,
+        );
+    }
+
+    /// Fits the grid to `camera` and a `width` by `height` target.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, width: u32, height: u32) {
+        let uniform = ClusterUniform::new(camera, width, height);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("cluster.compute_pass"),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch(cluster_count().div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
@@ -0,0 +1,226 @@
+//! Deferred shading.
+//!
+//! Instead of lighting every fragment as it is drawn, the camera pass writes
+//! the surfaces into a G-buffer: depth, albedo and metallic, and normal and
+//! roughness. Light that doesn't come from the light list goes straight into
+//! the HDR target. The lighting pass then draws a screen-space quad over the
+//! reach of every light, adding its light to the pixels under it, so each
+//! light only costs as much as the area it covers.
+
+use std::sync::Arc;
+
+use crate::{
+    pipeline::{PipelineBuilder, PipelineCache},
+    rendertarget::{RenderTargets, TargetDesc, TargetId, TargetSize},
+    texture,
+};
+
+/// Base color in rgb and metallic in alpha.
+pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// World space normal in rgb and roughness in alpha.
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The surface attributes the camera pass writes next to the HDR and
+/// velocity targets. Depth comes from the camera pass' depth buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GBuffer {
+    pub albedo: TargetId,
+    pub normal: TargetId,
+}
+
+impl GBuffer {
+    /// Formats of the targets, in the order the G-buffer shader writes them
+    /// after color and velocity.
+    pub const FORMATS: [wgpu::TextureFormat; 2] = [ALBEDO_FORMAT, NORMAL_FORMAT];
+
+    /// Adds the G-buffer's targets to `targets`.
+    pub fn new(device: &wgpu::Device, targets: &mut RenderTargets) -> Self {
+        let mut target = |label, format| {
+            targets.add(
+                device,
+                TargetDesc {
+                    label,
+                    size: TargetSize::FULL,
+                    format,
+                    sample_count: 1,
+                },
+            )
+        };
+        Self {
+            albedo: target("gbuffer_albedo", ALBEDO_FORMAT),
+            normal: target("gbuffer_normal", NORMAL_FORMAT),
+        }
+    }
+
+    /// Attachments that clear the G-buffer for the camera pass.
+    pub fn color_attachments<'a>(
+        &self,
+        targets: &'a RenderTargets,
+    ) -> [wgpu::RenderPassColorAttachment<'a>; 2] {
+        let attachment = |view| wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        };
+        [
+            attachment(&targets.get(self.albedo).view),
+            attachment(&targets.get(self.normal).view),
+        ]
+    }
+}
+
+/// The G-buffer and the pass that lights it.
+pub struct DeferredPass {
+    pub gbuffer: GBuffer,
+    /// The camera pass' depth buffer.
+    depth: TargetId,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl DeferredPass {
+    /// `shader` is the camera pass' shader, whose `light_volume` and
+    /// `deferred_light` entry points share its lighting code. `layouts` are
+    /// the camera, light and shadow layouts of the camera pass' groups 1 to
+    /// 3, and `depth` is its depth buffer. The G-buffer is added to `targets`.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        targets: &mut RenderTargets,
+        shader: &Arc<wgpu::ShaderModule>,
+        layouts: [&wgpu::BindGroupLayout; 3],
+        depth: TargetId,
+    ) -> Self {
+        let [camera_layout, light_layout, shadow_layout] = layouts;
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        // Numbered after the material bindings, see `shader.wgsl`.
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-buffer Layout"),
+            entries: &[
+                texture_entry(11, wgpu::TextureSampleType::Depth),
+                texture_entry(12, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(13, wgpu::TextureSampleType::Float { filterable: false }),
+            ],
+        });
+
+        let gbuffer = GBuffer::new(device, targets);
+        let bind_group = Self::create_bind_group(device, &layout, targets, &gbuffer, depth);
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("deferred.pipeline_layout"),
+                bind_group_layouts: &[&layout, camera_layout, light_layout, shadow_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let render_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("deferred.render_pipeline", &pipeline_layout, shader)
+                .vertex_entry("light_volume")
+                .fragment("deferred_light")
+                // Lights add up; the camera pass' alpha is kept.
+                .color_target(
+                    texture::Texture::HDR_FORMAT,
+                    Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                )
+                .cull_mode(None),
+        );
+
+        Self {
+            gbuffer,
+            depth,
+            layout,
+            bind_group,
+            render_pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        targets: &RenderTargets,
+        gbuffer: &GBuffer,
+        depth: TargetId,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("deferred.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: wgpu::BindingResource::TextureView(&targets.get(depth).view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: wgpu::BindingResource::TextureView(&targets.get(gbuffer.albedo).view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: wgpu::BindingResource::TextureView(&targets.get(gbuffer.normal).view),
+                },
+            ],
+        })
+    }
+
+    /// Points the bind group at the recreated targets, e.g. after a resize.
+    pub fn set_input(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.bind_group =
+            Self::create_bind_group(device, &self.layout, targets, &self.gbuffer, self.depth);
+    }
+
+    /// Adds the first `light_count` lights to the HDR target.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr_view: &wgpu::TextureView,
+        camera: &wgpu::BindGroup,
+        lights: &wgpu::BindGroup,
+        shadows: &wgpu::BindGroup,
+        light_count: u32,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("deferred.render_pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: hdr_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera, &[]);
+        render_pass.set_bind_group(2, lights, &[]);
+        render_pass.set_bind_group(3, shadows, &[]);
+        // One quad per light.
+        render_pass.draw(0..6, 0..light_count);
+    }
+}
@@ -1,173 +1,84 @@
-use wgpu::util::DeviceExt;
+use std::sync::Arc;
 
-use crate::{
-    model::{ModelVertex, Vertex},
-    texture,
-};
-
-const DEPTH_VERTICES: &[ModelVertex] = &[
-    ModelVertex {
-        position: [0.0, 0.0, 0.0],
-        tex_coords: [0.0, 1.0],
-        normal: [0.0, 0.0, 0.0],
-    },
-    ModelVertex {
-        position: [1.0, 0.0, 0.0],
-        tex_coords: [1.0, 1.0],
-        normal: [0.0, 0.0, 0.0],
-    },
-    ModelVertex {
-        position: [1.0, 1.0, 0.0],
-        tex_coords: [1.0, 0.0],
-        normal: [0.0, 0.0, 0.0],
-    },
-    ModelVertex {
-        position: [0.0, 1.0, 0.0],
-        tex_coords: [0.0, 0.0],
-        normal: [0.0, 0.0, 0.0],
-    },
-];
-
-const DEPTH_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+use crate::pipeline::{PipelineBuilder, PipelineCache};
 
+/// Draws a depth texture, e.g. the shadow map, to a color target for debugging.
 pub struct DepthPass {
-    pub texture: crate::texture::Texture,
-    layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    render_pipeline: wgpu::RenderPipeline,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
 }
 
 impl DepthPass {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> DepthPass {
-        let texture =
-            crate::texture::Texture::create_depth_texture(&device, &config, "depth_texture");
-
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+    ) -> DepthPass {
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Depth Pass Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Depth,
-                    },
-                    count: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler {
-                        comparison: true,
-                        filtering: true,
-                    },
-                    count: None,
-                },
-            ],
+                count: None,
+            }],
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("depth_pass.bind_group"),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth_view),
+            }],
             layout: &layout,
         });
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("depth_pass.vertex_buffer"),
-            contents: bytemuck::cast_slice(&DEPTH_VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("depth_pass.index_buffer"),
-            contents: bytemuck::cast_slice(&DEPTH_INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("depth_pass.pipeline_layout"),
-            bind_group_layouts: &[&layout],
-            push_constant_ranges: &[],
-        });
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("depth_pass.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
 
-        let vert_shader = device.create_shader_module(&wgpu::include_spirv!("depth.vert.spv"));
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/depth.wgsl")));
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("depth_pass.render_pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert_shader,
-                entry_point: "main",
-                buffers: &[ModelVertex::desc()],
-            },
-            fragment: None,
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState {
-                    constant: 2, // corresponds to bilinear filtering
-                    slope_scale: 2.0,
-                    clamp: 0.0,
-                },
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let render_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("depth_pass.render_pipeline", &pipeline_layout, &shader)
+                .fragment("main")
+                .color_target(config.format, Some(wgpu::BlendState::REPLACE))
+                .cull_mode(None),
+        );
 
         Self {
-            texture,
-            layout,
-            bind_group: bind_group,
-            vertex_buffer,
-            index_buffer,
+            bind_group,
             render_pipeline,
         }
     }
 
-    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("depth_pass.render_pass"),
-            color_attachments: &[],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: true,
-                }),
-                stencil_ops: None,
-            }),
+                },
+            }],
+            depth_stencil_attachment: None,
         });
 
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..DEPTH_INDICES.len() as u32, 0, 0..1);
+        render_pass.draw(0..3, 0..1);
     }
 }
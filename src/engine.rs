@@ -0,0 +1,2090 @@
+use std::{cell::RefCell, iter, path::Path, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use wgpu::util::DeviceExt;
+use winit::{event::WindowEvent, window::Window};
+
+use crate::{
+    bloom::{BloomOptions, BloomPass},
+    camera::{Camera, CameraUniform},
+    cameracontroller::CameraController,
+    cluster::ClusterPass,
+    deferred::{DeferredPass, GBuffer},
+    depthpass::DepthPass,
+    exposure::AutoExposurePass,
+    fxaa::FxaaPass,
+    ibl::{Environment, IblPass},
+    instance::InstanceRaw,
+    lighting::{self, DrawLight},
+    model::{self, DrawModel, Model, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+    rendergraph::{RenderGraph, ResourceId, TextureDesc, TransientTextures},
+    renderpass,
+    rendertarget::{RenderTargets, TargetDesc, TargetId, TargetSize},
+    scene::Scene,
+    shadow::{self, PointShadowMaps, ShadowMaps, ShadowOptions},
+    sky::{Sky, SkyPass, SunTint},
+    ssao::{SsaoOptions, SsaoPass},
+    taa::{TaaOptions, TaaPass},
+    texture,
+    tonemap::{TonemapOptions, TonemapPass},
+    transparency::{self, AccumulationTargets, Transparency, TransparencyPass, TransparentDraw},
+};
+
+/// Options used when picking the adapter and device the engine renders with.
+pub struct EngineOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Only accept a software (CPU) adapter, e.g. for CI machines without a GPU.
+    pub force_fallback_adapter: bool,
+    pub shadows: ShadowOptions,
+    /// Samples per pixel for the camera pass, one of
+    /// [`SUPPORTED_SAMPLE_COUNTS`]. Checked by [`EngineOptions::validate`]
+    /// when the engine is created.
+    pub sample_count: u32,
+    pub shading_path: ShadingPath,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU,
+            // unless overridden through the WGPU_BACKEND environment variable
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::PRIMARY),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            shadows: ShadowOptions::default(),
+            sample_count: 1,
+            shading_path: ShadingPath::Forward,
+        }
+    }
+}
+
+/// MSAA sample counts the camera targets can use. wgpu can't query
+/// per-format multisampling support yet, and 1 and 4 are the only counts
+/// every adapter is guaranteed to handle.
+pub const SUPPORTED_SAMPLE_COUNTS: &[u32] = &[1, 4];
+
+impl EngineOptions {
+    /// Checks the options go together, before any adapter is requested.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            SUPPORTED_SAMPLE_COUNTS.contains(&self.sample_count),
+            "{}x MSAA is not supported, use one of {:?}: wgpu can't tell yet \
+             which other sample counts an adapter handles",
+            self.sample_count,
+            SUPPORTED_SAMPLE_COUNTS
+        );
+        anyhow::ensure!(
+            self.shading_path == ShadingPath::Forward || self.sample_count == 1,
+            "The deferred shading path can't be multisampled, use FXAA or TAA instead"
+        );
+        Ok(())
+    }
+}
+
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    options: &EngineOptions,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> Option<wgpu::Adapter> {
+    if options.force_fallback_adapter {
+        return instance
+            .enumerate_adapters(options.backends)
+            .find(|adapter| {
+                adapter.get_info().device_type == wgpu::DeviceType::Cpu
+                    && compatible_surface
+                        .is_none_or(|surface| adapter.is_surface_supported(surface))
+            });
+    }
+
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: options.power_preference,
+            compatible_surface,
+        })
+        .await
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None, // Trace path
+        )
+        .await
+}
+
+/// What the camera pass writes to the output target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugView {
+    /// The lit scene, tonemapped.
+    Lit,
+    /// The first layer of the shadow map, linearized to grayscale.
+    ShadowMap,
+}
+
+/// How the camera pass lights the scene, picked when the engine is created.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadingPath {
+    /// Every fragment is lit while it is drawn, by the lights a compute
+    /// pass found to reach its [cluster](crate::cluster) of the view.
+    Forward,
+    /// Surfaces are written to a G-buffer, then each light only shades the
+    /// pixels it can reach. Scales to hundreds of lights, but can't be
+    /// combined with MSAA.
+    Deferred,
+}
+
+/// Post-process anti-aliasing, on top of any MSAA.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AntiAliasing {
+    None,
+    /// Smooths edges in the tonemapped frame. Cheap, but blurs some detail
+    /// and can't do anything about sub-pixel shimmer.
+    Fxaa,
+    /// Jitters the projection and accumulates frames over time.
+    Taa(TaaOptions),
+}
+
+/// Number of lights the light storage buffer has room for before it is grown.
+const INITIAL_LIGHT_CAPACITY: usize = 16;
+
+/// Per-model instance data generated from the scene graph.
+struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    /// How many instances fit in the buffer.
+    capacity: u32,
+    count: u32,
+    /// World transform of each instance, to sort transparent draws with.
+    transforms: Vec<cgmath::Matrix4<f32>>,
+}
+
+/// One layer of a shadow map to render into. The moments are only written
+/// by the variance filters.
+struct ShadowTarget<'a> {
+    depth: &'a wgpu::TextureView,
+    moments: &'a wgpu::TextureView,
+}
+
+/// The resources the frame's render graph passes between the stages
+/// [`Engine::render_to`] builds it in, as the passes so far left them.
+struct FrameResources {
+    shadow_maps: ResourceId,
+    point_shadow_maps: ResourceId,
+    hdr: ResourceId,
+    velocity: ResourceId,
+}
+
+pub struct Engine {
+    surface: Option<wgpu::Surface>,
+    offscreen_target: Option<TargetId>,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    size: winit::dpi::PhysicalSize<u32>,
+    /// The offscreen, depth, HDR, velocity, multisampled and LDR targets,
+    /// recreated when the window changes size.
+    targets: RenderTargets,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    light_render_pipeline: Arc<wgpu::RenderPipeline>,
+    pub camera: Camera,
+    camera_uniform: CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    pub camera_controller: CameraController,
+    pub scene: Scene,
+    instance_buffers: Vec<InstanceBuffer>,
+    lights_buffer: wgpu::Buffer,
+    light_capacity: usize,
+    light_bind_group_layout: wgpu::BindGroupLayout,
+    light_bind_group: wgpu::BindGroup,
+    /// Sorts the lights into clusters of the view frustum for the forward
+    /// path.
+    cluster_pass: ClusterPass,
+
+    shadow_pass: renderpass::Pass,
+    shadow_maps: ShadowMaps,
+    point_shadow_pass: renderpass::Pass,
+    point_shadow_maps: PointShadowMaps,
+    shadow_bind_group_layout: wgpu::BindGroupLayout,
+    /// The shadow maps and the environment's lighting, the camera pass'
+    /// group 3.
+    shadow_bind_group: wgpu::BindGroup,
+    ibl_pass: IblPass,
+    /// Lights the scene in place of a constant ambient term, if loaded.
+    environment: Option<Environment>,
+    /// Scales the light from [`Engine::load_environment`]'s environment.
+    pub environment_intensity: f32,
+    sky_pass: SkyPass,
+    /// Drawn behind the scene, which is otherwise cleared to a flat color.
+    pub sky: Option<Sky>,
+    sun_tint: Option<SunTint>,
+    camera_pass: renderpass::Pass,
+    /// Draws [`AlphaMode::Mask`](model::AlphaMode::Mask) materials, with
+    /// alpha to coverage when multisampling.
+    cutout_pass: renderpass::Pass,
+    /// Draws [`AlphaMode::Blend`](model::AlphaMode::Blend) materials after
+    /// the opaque scene.
+    transparency_pass: TransparencyPass,
+    /// How overlapping transparent surfaces are blended.
+    pub transparency: Transparency,
+    /// Lights the G-buffer the camera pass writes on the deferred path.
+    deferred_pass: Option<DeferredPass>,
+    /// Fills the camera pass' depth buffer ahead of it for SSAO.
+    depth_prepass: renderpass::Pass,
+    ssao_pass: SsaoPass,
+    /// Bound in place of the SSAO result while SSAO is off.
+    no_occlusion: texture::Texture,
+    /// Whether the shadow bind group holds the SSAO result.
+    ssao_bound: bool,
+    /// Darkens the ambient light in creases and corners, if set.
+    pub ssao: Option<SsaoOptions>,
+    camera_depth: TargetId,
+    sample_count: u32,
+    /// The camera pass renders into these and resolves into `hdr_target`
+    /// and `velocity_target` when multisampling.
+    msaa_targets: Option<[TargetId; 2]>,
+    /// Screen motion since the previous frame, for TAA.
+    velocity_target: TargetId,
+    /// The camera pass lights the scene into this before tonemapping.
+    hdr_target: TargetId,
+    bloom_pass: BloomPass,
+    /// Bloom added to the HDR scene color before tonemapping, if any.
+    pub bloom: Option<BloomOptions>,
+    auto_exposure_pass: AutoExposurePass,
+    tonemap_pass: TonemapPass,
+    /// Applied to the HDR scene color every frame.
+    pub tonemap: TonemapOptions,
+    taa_pass: TaaPass,
+    /// The tonemapped frame, when FXAA needs it before it goes out.
+    ldr_target: TargetId,
+    fxaa_pass: FxaaPass,
+    pub anti_aliasing: AntiAliasing,
+    /// Set by [`Engine::camera_cut`] until the next update.
+    camera_cut: bool,
+    /// Whether the instance buffers hold motion from the last update, which
+    /// has to be cleared again once nothing moves.
+    instances_moved: bool,
+    shadow_debug_pass: DepthPass,
+    pub debug_view: DebugView,
+    /// Backs the render graph's transient targets from frame to frame.
+    transient_targets: RefCell<TransientTextures>,
+}
+
+impl Engine {
+    /// Color format of the offscreen target used by headless engines.
+    pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub async fn new(window: &Window, options: &EngineOptions) -> Self {
+        let size = window.inner_size();
+        options.validate().unwrap();
+
+        // The instance is a handle to our GPU
+        let instance = wgpu::Instance::new(options.backends);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = request_adapter(&instance, options, Some(&surface))
+            .await
+            .unwrap();
+        let (device, queue) = request_device(&adapter).await.unwrap();
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface.get_preferred_format(&adapter).unwrap(),
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        surface.configure(&device, &config);
+
+        Self::create(device, queue, config, Some(surface), options)
+    }
+
+    /// Creates an engine without a window that renders into an offscreen
+    /// texture, which can be read back with [`Engine::read_frame`].
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        options: &EngineOptions,
+    ) -> anyhow::Result<Self> {
+        options.validate()?;
+        let instance = wgpu::Instance::new(options.backends);
+        let adapter = request_adapter(&instance, options, None)
+            .await
+            .context("No suitable adapter found")?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::HEADLESS_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+
+        Ok(Self::create(device, queue, config, None, options))
+    }
+
+    fn create(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface>,
+        options: &EngineOptions,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
+        let mut targets = RenderTargets::new(config.width, config.height);
+        // Every render pipeline is built through this, so passes that end up
+        // with the same state share one pipeline.
+        let mut pipelines = PipelineCache::default();
+        let window_target = |label, format, sample_count| TargetDesc {
+            label,
+            size: TargetSize::FULL,
+            format,
+            sample_count,
+        };
+        let offscreen_target = match surface {
+            Some(_) => None,
+            None => Some(targets.add(&device, window_target("offscreen_target", config.format, 1))),
+        };
+
+        let texture_bind_group_layout = model::Material::bind_group_layout(&device);
+
+        let camera = Camera {
+            eye: (0.0, 5.0, -10.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: config.width as f32 / config.height as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            jitter: cgmath::Vector2::new(0.0, 0.0),
+        };
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+        camera_uniform.reset_motion();
+
+        let sample_count = options.sample_count;
+        // The camera pass writes color and motion vectors.
+        let color_formats = [
+            texture::Texture::HDR_FORMAT,
+            texture::Texture::VELOCITY_FORMAT,
+        ];
+
+        let cascade_bind_group_layout = ShadowMaps::cascade_bind_group_layout(&device);
+        let shadow_maps = ShadowMaps::new(&device, &cascade_bind_group_layout, options.shadows);
+        let point_face_bind_group_layout = PointShadowMaps::face_bind_group_layout(&device);
+        let point_shadow_maps =
+            PointShadowMaps::new(&device, &point_face_bind_group_layout, options.shadows);
+        // The variance filters render depth moments next to the depth.
+        let uses_moments = options.shadows.filter.uses_moments();
+
+        let lights_buffer = Self::create_lights_buffer(&device, INITIAL_LIGHT_CAPACITY);
+        let cluster_pass = ClusterPass::new(&device, &lights_buffer);
+
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // The light clusters.
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("light_bind_group_layout"),
+            });
+
+        let light_bind_group = Self::create_light_bind_group(
+            &device,
+            &light_bind_group_layout,
+            &shadow_maps.uniform_buffer,
+            &lights_buffer,
+            &cluster_pass,
+        );
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let shadow_pass = {
+            let vert_shader = Arc::new(
+                device.create_shader_module(&wgpu::include_wgsl!("../shaders/shadow.wgsl")),
+            );
+
+            let pipeline_layout = Arc::new(device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Shadow Pipeline Layout"),
+                    bind_group_layouts: &[&cascade_bind_group_layout],
+                    push_constant_ranges: &[],
+                },
+            ));
+
+            println!("creating shadow pipeline");
+            let mut builder =
+                PipelineBuilder::new("shadow pipeline", &pipeline_layout, &vert_shader)
+                    .vertex_buffers(&[model::ModelVertex::desc(), InstanceRaw::desc()])
+                    .clamp_depth(device.features().contains(wgpu::Features::DEPTH_CLAMPING))
+                    .depth(
+                        texture::Texture::DEPTH_FORMAT,
+                        true,
+                        wgpu::CompareFunction::LessEqual,
+                    );
+            builder = if uses_moments {
+                // Moments take their depth before the bias.
+                builder
+                    .fragment("moments")
+                    .color_target(shadow::MOMENTS_FORMAT, None)
+            } else {
+                builder.depth_bias(wgpu::DepthBiasState {
+                    constant: 2, // corresponds to bilinear filtering
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                })
+            };
+
+            renderpass::Pass {
+                pipeline: pipelines.get(&device, &builder),
+            }
+        };
+
+        let point_shadow_pass = {
+            let shader = Arc::new(
+                device.create_shader_module(&wgpu::include_wgsl!("../shaders/point_shadow.wgsl")),
+            );
+
+            let pipeline_layout = Arc::new(device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Point Shadow Pipeline Layout"),
+                    bind_group_layouts: &[&point_face_bind_group_layout],
+                    push_constant_ranges: &[],
+                },
+            ));
+
+            let mut builder =
+                PipelineBuilder::new("point shadow pipeline", &pipeline_layout, &shader)
+                    .vertex_buffers(&[model::ModelVertex::desc(), InstanceRaw::desc()])
+                    // The cube face projections are mirrored.
+                    .front_face(wgpu::FrontFace::Cw)
+                    .depth(
+                        texture::Texture::DEPTH_FORMAT,
+                        true,
+                        wgpu::CompareFunction::LessEqual,
+                    );
+            builder = if uses_moments {
+                builder
+                    .fragment("moments")
+                    .color_target(shadow::MOMENTS_FORMAT, None)
+            } else {
+                builder.fragment("main")
+            };
+
+            renderpass::Pass {
+                pipeline: pipelines.get(&device, &builder),
+            }
+        };
+
+        let camera_depth = targets.add(
+            &device,
+            window_target("camera_depth", texture::Texture::DEPTH_FORMAT, sample_count),
+        );
+
+        let ibl_pass = IblPass::new(&device, &queue);
+        let ssao_pass = SsaoPass::new(
+            &device,
+            &queue,
+            &mut pipelines,
+            &mut targets,
+            camera_depth,
+            sample_count,
+        );
+        let no_occlusion = texture::Texture::from_color(
+            &device,
+            &queue,
+            [255; 4],
+            wgpu::TextureFormat::Rgba8Unorm,
+            "no_occlusion",
+        )
+        .unwrap();
+        let sky_pass = SkyPass::new(
+            &device,
+            &mut pipelines,
+            &camera_bind_group_layout,
+            sample_count,
+            &ibl_pass.placeholder,
+            &ibl_pass.uniform_buffer,
+        );
+        let (
+            camera_pass,
+            cutout_pass,
+            transparency_pass,
+            deferred_pass,
+            depth_prepass,
+            shadow_bind_group_layout,
+            shadow_bind_group,
+        ) = {
+            let shadow_bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Depth,
+                                view_dimension: wgpu::TextureViewDimension::D2Array,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler {
+                                comparison: true,
+                                filtering: true,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Depth,
+                                view_dimension: wgpu::TextureViewDimension::CubeArray,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2Array,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::CubeArray,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler {
+                                comparison: false,
+                                filtering: true,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler {
+                                comparison: false,
+                                filtering: false,
+                            },
+                            count: None,
+                        },
+                        // The environment's image based lighting.
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 7,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::Cube,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 8,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::Cube,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 9,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 10,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler {
+                                comparison: false,
+                                filtering: true,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 11,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 12,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                    ],
+                    label: Some("shadow_bind_group_layout"),
+                });
+
+            let shadow_bind_group = Self::create_shadow_bind_group(
+                &device,
+                &shadow_bind_group_layout,
+                &shadow_maps,
+                &point_shadow_maps,
+                &ibl_pass,
+                &ibl_pass.placeholder,
+                &no_occlusion.view,
+            );
+
+            let pipeline_layout = Arc::new(device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("main"),
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &camera_bind_group_layout,
+                        &light_bind_group_layout,
+                        &shadow_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                },
+            ));
+
+            let shader = Arc::new(
+                device.create_shader_module(&wgpu::include_wgsl!("../shaders/shader.wgsl")),
+            );
+
+            // The camera and cutout passes end up with the same pipeline on
+            // the deferred path, and on the forward one without alpha to
+            // coverage.
+            let mesh = PipelineBuilder::new("camera pipeline", &pipeline_layout, &shader)
+                .vertex_buffers(&[model::ModelVertex::desc(), InstanceRaw::desc()])
+                .sample_count(sample_count);
+
+            // The camera pass' vertex stage alone, so the depth matches
+            // exactly when the camera pass draws over it.
+            let depth_prepass = renderpass::Pass {
+                pipeline: pipelines.get(
+                    &device,
+                    &mesh.clone().label("depth prepass pipeline").depth(
+                        texture::Texture::DEPTH_FORMAT,
+                        true,
+                        wgpu::CompareFunction::Less,
+                    ),
+                ),
+            };
+
+            // Both paths shade transparent surfaces forward, after the
+            // opaque ones are lit.
+            let transparency_pass = TransparencyPass::new(&device, &mut pipelines, &mesh);
+
+            // Equal passes too, for geometry already in the depth prepass.
+            let opaque = mesh
+                .depth(
+                    texture::Texture::DEPTH_FORMAT,
+                    true,
+                    wgpu::CompareFunction::LessEqual,
+                )
+                .color_target(color_formats[0], Some(wgpu::BlendState::REPLACE))
+                .color_target(color_formats[1], Some(wgpu::BlendState::REPLACE));
+
+            if options.shading_path == ShadingPath::Deferred {
+                // Every material writes the G-buffer, with the alpha test
+                // for cutouts, and is lit afterwards.
+                let [albedo_format, normal_format] = GBuffer::FORMATS;
+                let gbuffer = opaque
+                    .label("G-buffer pipeline")
+                    .fragment("gbuffer")
+                    .color_target(albedo_format, Some(wgpu::BlendState::REPLACE))
+                    .color_target(normal_format, Some(wgpu::BlendState::REPLACE));
+
+                let deferred_pass = DeferredPass::new(
+                    &device,
+                    &mut pipelines,
+                    &mut targets,
+                    &shader,
+                    [
+                        &camera_bind_group_layout,
+                        &light_bind_group_layout,
+                        &shadow_bind_group_layout,
+                    ],
+                    camera_depth,
+                );
+                (
+                    renderpass::Pass {
+                        pipeline: pipelines.get(&device, &gbuffer),
+                    },
+                    renderpass::Pass {
+                        pipeline: pipelines.get(&device, &gbuffer),
+                    },
+                    transparency_pass,
+                    Some(deferred_pass),
+                    depth_prepass,
+                    shadow_bind_group_layout,
+                    shadow_bind_group,
+                )
+            } else {
+                println!("creating camera pipeline");
+                let pipeline = pipelines.get(&device, &opaque.clone().fragment("main"));
+
+                // Without samples to cover, cutouts fall back to the alpha test.
+                let alpha_to_coverage = sample_count > 1;
+                let cutout = opaque.label("cutout pipeline");
+                let cutout = if alpha_to_coverage {
+                    cutout.fragment("coverage").alpha_to_coverage(true)
+                } else {
+                    cutout.fragment("main")
+                };
+
+                (
+                    renderpass::Pass { pipeline },
+                    renderpass::Pass {
+                        pipeline: pipelines.get(&device, &cutout),
+                    },
+                    transparency_pass,
+                    None,
+                    depth_prepass,
+                    shadow_bind_group_layout,
+                    shadow_bind_group,
+                )
+            }
+        };
+
+        let light_render_pipeline = {
+            let layout = Arc::new(
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Light Pipeline Layout"),
+                    bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            );
+
+            let shader = Arc::new(
+                device.create_shader_module(&wgpu::include_wgsl!("../shaders/light.wgsl")),
+            );
+
+            println!("creating light pipeline");
+            let builder = PipelineBuilder::new("light pipeline", &layout, &shader)
+                .vertex_buffers(&[model::ModelVertex::desc()])
+                .fragment("main")
+                .color_target(color_formats[0], Some(wgpu::BlendState::REPLACE))
+                .color_target(color_formats[1], Some(wgpu::BlendState::REPLACE))
+                .depth(
+                    texture::Texture::DEPTH_FORMAT,
+                    true,
+                    wgpu::CompareFunction::LessEqual,
+                )
+                .sample_count(sample_count);
+            pipelines.get(&device, &builder)
+        };
+
+        let camera_controller = CameraController::new(0.2);
+
+        let shadow_debug_pass = DepthPass::new(
+            &device,
+            &mut pipelines,
+            &config,
+            &shadow_maps.layer_views[0],
+        );
+
+        let hdr_target = targets.add(
+            &device,
+            window_target("hdr_target", texture::Texture::HDR_FORMAT, 1),
+        );
+        let velocity_target = targets.add(
+            &device,
+            window_target("velocity_target", texture::Texture::VELOCITY_FORMAT, 1),
+        );
+        let msaa_targets = (sample_count > 1).then(|| {
+            [
+                targets.add(
+                    &device,
+                    window_target("msaa_target", texture::Texture::HDR_FORMAT, sample_count),
+                ),
+                targets.add(
+                    &device,
+                    window_target(
+                        "msaa_velocity_target",
+                        texture::Texture::VELOCITY_FORMAT,
+                        sample_count,
+                    ),
+                ),
+            ]
+        });
+        let ldr_target = targets.add(&device, window_target("ldr_target", config.format, 1));
+        let bloom_pass = BloomPass::new(&device, &mut pipelines, &mut targets, hdr_target);
+        let taa_pass = TaaPass::new(
+            &device,
+            &mut pipelines,
+            &mut targets,
+            hdr_target,
+            velocity_target,
+        );
+        let tonemap = TonemapOptions::default();
+        let auto_exposure_pass = AutoExposurePass::new(&device, &targets, hdr_target);
+        let tonemap_pass = TonemapPass::new(
+            &device,
+            &mut pipelines,
+            &config,
+            &targets.get(hdr_target).view,
+            &auto_exposure_pass.luminance_buffer,
+            &tonemap,
+        );
+        let fxaa_pass = FxaaPass::new(
+            &device,
+            &mut pipelines,
+            &config,
+            &targets.get(ldr_target).view,
+        );
+
+        Self {
+            surface,
+            offscreen_target,
+            device,
+            queue,
+            config,
+            size,
+            targets,
+            texture_bind_group_layout,
+
+            camera,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            camera_controller,
+            scene: Scene::new(),
+            instance_buffers: Vec::new(),
+            lights_buffer,
+            light_capacity: INITIAL_LIGHT_CAPACITY,
+            light_bind_group_layout,
+            light_bind_group,
+            cluster_pass,
+
+            shadow_pass,
+            shadow_maps,
+            point_shadow_pass,
+            point_shadow_maps,
+            shadow_bind_group_layout,
+            shadow_bind_group,
+            ibl_pass,
+            environment: None,
+            environment_intensity: 1.0,
+            sky_pass,
+            sky: None,
+            sun_tint: None,
+            camera_pass,
+            cutout_pass,
+            transparency_pass,
+            transparency: Transparency::Sorted,
+            deferred_pass,
+            depth_prepass,
+            ssao_pass,
+            no_occlusion,
+            ssao_bound: false,
+            ssao: None,
+            camera_depth,
+            sample_count,
+            msaa_targets,
+            velocity_target,
+            hdr_target,
+            bloom_pass,
+            bloom: None,
+            auto_exposure_pass,
+            tonemap_pass,
+            tonemap,
+            taa_pass,
+            ldr_target,
+            fxaa_pass,
+            anti_aliasing: AntiAliasing::None,
+            camera_cut: false,
+            instances_moved: false,
+            shadow_debug_pass,
+            debug_view: DebugView::Lit,
+            transient_targets: RefCell::default(),
+            light_render_pipeline,
+        }
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Resizes the surface and everything sized to it to `new_size` in
+    /// physical pixels, as after a window resize or a change of scale
+    /// factor.
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        // Minimized windows have no area to render to.
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.camera.aspect = self.config.width as f32 / self.config.height as f32;
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+        if self
+            .targets
+            .resize(&self.device, new_size.width, new_size.height)
+        {
+            self.rebuild_size_dependents();
+        }
+    }
+
+    /// Points the passes' bind groups at the recreated render targets,
+    /// their own included.
+    fn rebuild_size_dependents(&mut self) {
+        let device = &self.device;
+        let targets = &self.targets;
+        self.bloom_pass.set_input(device, targets);
+        self.auto_exposure_pass.set_input(device, targets);
+        self.tonemap_pass.set_input(
+            device,
+            &targets.get(self.hdr_target).view,
+            &self.auto_exposure_pass.luminance_buffer,
+        );
+        self.taa_pass.set_input(device, targets);
+        self.fxaa_pass
+            .set_input(device, &targets.get(self.ldr_target).view);
+        if let Some(deferred_pass) = &mut self.deferred_pass {
+            deferred_pass.set_input(device, targets);
+        }
+        self.ssao_pass.set_input(device, targets);
+        // Holds the SSAO result, which was just recreated.
+        self.rebuild_shadow_bind_group();
+    }
+
+    /// Which of the shading paths the engine was created with.
+    pub fn shading_path(&self) -> ShadingPath {
+        match self.deferred_pass {
+            Some(_) => ShadingPath::Deferred,
+            None => ShadingPath::Forward,
+        }
+    }
+
+    /// Tells the engine the camera jumped somewhere else this frame, so
+    /// nothing should be blended or reprojected from the previous one.
+    pub fn camera_cut(&mut self) {
+        self.camera_cut = true;
+    }
+
+    /// Loads an OBJ model using the engine's material layout.
+    pub fn load_model<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<Model> {
+        Model::load(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            path,
+        )
+    }
+
+    /// Loads a glTF or GLB file using the engine's material layout. Add the
+    /// result to the scene with [`Scene::add_gltf`](crate::scene::Scene::add_gltf).
+    pub fn load_gltf<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> anyhow::Result<model::GltfScene> {
+        Model::load_gltf(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            path,
+        )
+    }
+
+    /// Lights the scene with an equirectangular Radiance HDR image of its
+    /// surroundings, replacing any environment loaded before.
+    pub fn load_environment<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let environment = self.ibl_pass.load(&self.device, &self.queue, path)?;
+        self.set_environment(Some(environment));
+        Ok(())
+    }
+
+    /// Goes back to the constant ambient term.
+    pub fn clear_environment(&mut self) {
+        self.set_environment(None);
+    }
+
+    fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment;
+        let environment = self
+            .environment
+            .as_ref()
+            .unwrap_or(&self.ibl_pass.placeholder);
+        self.sky_pass
+            .set_environment(&self.device, environment, &self.ibl_pass.uniform_buffer);
+        self.rebuild_shadow_bind_group();
+    }
+
+    /// Rebinds the environment and ambient occlusion after either changed.
+    fn rebuild_shadow_bind_group(&mut self) {
+        self.ssao_bound = self.ssao.is_some();
+        self.shadow_bind_group = Self::create_shadow_bind_group(
+            &self.device,
+            &self.shadow_bind_group_layout,
+            &self.shadow_maps,
+            &self.point_shadow_maps,
+            &self.ibl_pass,
+            self.environment
+                .as_ref()
+                .unwrap_or(&self.ibl_pass.placeholder),
+            if self.ssao_bound {
+                &self.targets.get(self.ssao_pass.occlusion).view
+            } else {
+                &self.no_occlusion.view
+            },
+        );
+    }
+
+    fn create_lights_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lights Buffer"),
+            size: (std::mem::size_of::<lighting::LightsHeader>()
+                + capacity * std::mem::size_of::<lighting::LightRaw>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_shadow_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        shadow_maps: &ShadowMaps,
+        point_shadow_maps: &PointShadowMaps,
+        ibl_pass: &IblPass,
+        environment: &Environment,
+        ambient_occlusion: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&shadow_maps.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&shadow_maps.texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&point_shadow_maps.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&shadow_maps.moments.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&point_shadow_maps.moments.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&shadow_maps.moments.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&shadow_maps.depth_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefiltered.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::TextureView(&ibl_pass.brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::Sampler(&environment.prefiltered.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: ibl_pass.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: wgpu::BindingResource::TextureView(ambient_occlusion),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        })
+    }
+
+    fn create_light_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        shadow_buffer: &wgpu::Buffer,
+        lights_buffer: &wgpu::Buffer,
+        cluster_pass: &ClusterPass,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: shadow_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cluster_pass.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: cluster_pass.lists_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("light_bind_group"),
+        })
+    }
+
+    /// Uploads every light in the scene, growing the storage buffer if needed.
+    fn update_lights(&mut self) {
+        let lights = &self.scene.lights;
+        if lights.len() > self.light_capacity {
+            self.light_capacity = lights.len().next_power_of_two();
+            self.lights_buffer = Self::create_lights_buffer(&self.device, self.light_capacity);
+            self.cluster_pass
+                .set_lights(&self.device, &self.lights_buffer);
+            self.light_bind_group = Self::create_light_bind_group(
+                &self.device,
+                &self.light_bind_group_layout,
+                &self.shadow_maps.uniform_buffer,
+                &self.lights_buffer,
+                &self.cluster_pass,
+            );
+        }
+
+        let header = lighting::LightsHeader::new(lights.len() as u32);
+        let mut data = lights
+            .iter()
+            .map(lighting::Light::to_raw)
+            .collect::<Vec<_>>();
+        for (cube, light) in shadow::point_shadow_casters(lights).into_iter().enumerate() {
+            data[light].shadow_index = cube as i32;
+        }
+        self.queue
+            .write_buffer(&self.lights_buffer, 0, bytemuck::cast_slice(&[header]));
+        if !data.is_empty() {
+            self.queue.write_buffer(
+                &self.lights_buffer,
+                std::mem::size_of::<lighting::LightsHeader>() as wgpu::BufferAddress,
+                bytemuck::cast_slice(&data),
+            );
+        }
+    }
+
+    /// Rebuilds the instance buffers from the world matrices of the scene graph.
+    /// Writes every model's instances into its buffer, which is only
+    /// reallocated when they've outgrown it.
+    fn update_instance_buffers(&mut self) {
+        let device = &self.device;
+        let queue = &self.queue;
+        let scene = &self.scene;
+        let model_count = scene.models().count();
+        self.instance_buffers.truncate(model_count);
+        for (index, (id, _)) in scene.models().enumerate() {
+            let instance_data = scene.instances(id);
+            let count = instance_data.len() as u32;
+            let outgrown = self
+                .instance_buffers
+                .get(index)
+                .is_none_or(|instances| instances.capacity < count);
+            if outgrown {
+                let capacity = count.max(1).next_power_of_two();
+                let instances = InstanceBuffer {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Instance Buffer"),
+                        size: capacity as wgpu::BufferAddress
+                            * std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    capacity,
+                    count: 0,
+                    transforms: Vec::new(),
+                };
+                if index < self.instance_buffers.len() {
+                    self.instance_buffers[index] = instances;
+                } else {
+                    self.instance_buffers.push(instances);
+                }
+            }
+            let instances = &mut self.instance_buffers[index];
+            queue.write_buffer(&instances.buffer, 0, bytemuck::cast_slice(&instance_data));
+            instances.count = count;
+            instances.transforms.clear();
+            instances
+                .transforms
+                .extend(instance_data.iter().map(InstanceRaw::model_matrix));
+        }
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        self.camera_controller.process_events(event)
+    }
+
+    /// Steps the engine `dt` past the previous frame, which is what
+    /// exposure adapts over.
+    pub fn update(&mut self, dt: Duration) {
+        self.camera_controller.update_camera(&mut self.camera);
+        let camera_cut = std::mem::take(&mut self.camera_cut);
+        self.camera.jitter = match self.anti_aliasing {
+            AntiAliasing::Taa(options) => {
+                if camera_cut {
+                    self.taa_pass.invalidate();
+                }
+                self.taa_pass.update(&self.queue, &options);
+                self.taa_pass.jitter()
+            }
+            _ => {
+                // Start from a clean history once TAA is turned on.
+                self.taa_pass.invalidate();
+                cgmath::Vector2::new(0.0, 0.0)
+            }
+        };
+        self.camera_uniform.update_view_proj(&self.camera);
+        if camera_cut {
+            self.camera_uniform.reset_motion();
+        }
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+        self.cluster_pass.update(
+            &self.queue,
+            &self.camera,
+            self.config.width,
+            self.config.height,
+        );
+
+        let transforms_changed = self.scene.update_world_transforms();
+        if transforms_changed
+            || self.instances_moved
+            || self.instance_buffers.len() != self.scene.models().count()
+        {
+            self.update_instance_buffers();
+        }
+        self.instances_moved = transforms_changed;
+
+        let atmosphere = match &self.sky {
+            Some(Sky::Atmosphere(atmosphere)) => Some(atmosphere),
+            _ => None,
+        };
+        if let Some(atmosphere) = atmosphere {
+            self.sky_pass.update(&self.queue, atmosphere);
+        }
+        self.sun_tint = SunTint::apply(self.sun_tint, atmosphere, &mut self.scene);
+        self.update_lights();
+        let lights = &self.scene.lights;
+        self.shadow_maps.update(&self.queue, &self.camera, lights);
+        self.point_shadow_maps.update(&self.queue, lights);
+        if let Some(auto_exposure) = &self.tonemap.auto_exposure {
+            self.auto_exposure_pass
+                .update(&self.queue, auto_exposure, dt.as_secs_f32());
+        }
+        if let Some(bloom) = &self.bloom {
+            self.bloom_pass.update(&self.queue, bloom);
+        }
+        self.tonemap_pass.update(&self.queue, &self.tonemap);
+        self.ibl_pass.update(
+            &self.queue,
+            self.environment.is_some(),
+            self.environment_intensity,
+        );
+        if let Some(ssao) = &self.ssao {
+            self.ssao_pass.update(&self.queue, &self.camera, ssao);
+        }
+        if self.ssao.is_some() != self.ssao_bound {
+            self.rebuild_shadow_bind_group();
+        }
+    }
+
+    /// Renders a frame to the window surface, or to the offscreen target
+    /// when the engine is headless.
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        match &self.surface {
+            Some(surface) => {
+                let output = surface.get_current_frame()?.output;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                self.render_to(&view);
+            }
+            None => {
+                let target = self.offscreen_target.unwrap();
+                self.render_to(&self.targets.get(target).view);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_to(&self, view: &wgpu::TextureView) {
+        let transparent = self.transparent_queue();
+        let mut graph = RenderGraph::new(self.config.width, self.config.height);
+        let mut frame = FrameResources {
+            shadow_maps: graph.import("shadow_maps"),
+            point_shadow_maps: graph.import("point_shadow_maps"),
+            hdr: graph.import("hdr_target"),
+            velocity: graph.import("velocity_target"),
+        };
+        let output = graph.import("output");
+
+        self.add_shadow_passes(&mut graph, &mut frame);
+        if self.debug_view == DebugView::ShadowMap {
+            let mut pass = graph.add_pass("shadow map debug");
+            pass.read(frame.shadow_maps);
+            pass.write(output);
+            pass.execute(move |ctx| self.shadow_debug_pass.render(ctx.encoder, view));
+        } else {
+            self.add_camera_passes(&mut graph, &mut frame, &transparent);
+            self.add_post_processing(&mut graph, &mut frame, output, view);
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        graph.execute(
+            &self.device,
+            &mut encoder,
+            &mut self.transient_targets.borrow_mut(),
+        );
+        self.queue.submit(iter::once(encoder.finish()));
+    }
+
+    /// Renders the shadow casters into every cascade and point light face
+    /// in use.
+    fn add_shadow_passes<'a>(&'a self, graph: &mut RenderGraph<'a>, frame: &mut FrameResources) {
+        let shadows = &self.shadow_maps;
+        for (layer, cascade) in shadows
+            .cascade_bind_groups
+            .iter()
+            .enumerate()
+            .take(shadows.cascade_count() as usize)
+        {
+            let mut pass = graph.add_pass(format!("shadow cascade {}", layer));
+            frame.shadow_maps = pass.write(frame.shadow_maps);
+            pass.execute(move |ctx| {
+                let target = ShadowTarget {
+                    depth: &shadows.layer_views[layer],
+                    moments: &shadows.moment_views[layer],
+                };
+                self.render_depth(ctx.encoder, target, &self.shadow_pass, cascade);
+            });
+        }
+        let point_shadows = &self.point_shadow_maps;
+        for (layer, face) in point_shadows
+            .face_bind_groups
+            .iter()
+            .enumerate()
+            .take(point_shadows.caster_count() as usize * 6)
+        {
+            let mut pass = graph.add_pass(format!("point shadow face {}", layer));
+            frame.point_shadow_maps = pass.write(frame.point_shadow_maps);
+            pass.execute(move |ctx| {
+                let target = ShadowTarget {
+                    depth: &point_shadows.face_views[layer],
+                    moments: &point_shadows.moment_views[layer],
+                };
+                self.render_depth(ctx.encoder, target, &self.point_shadow_pass, face);
+            });
+        }
+    }
+
+    /// Lights the scene into the HDR target, and draws the sky and
+    /// `transparent` over it.
+    fn add_camera_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &mut FrameResources,
+        transparent: &'a [TransparentDraw],
+    ) {
+        let (sorted, weighted_blended): (&[_], &[_]) = match self.transparency {
+            Transparency::Sorted => (transparent, &[]),
+            Transparency::WeightedBlended => (&[], transparent),
+        };
+        let mut clusters = graph.import("clusters");
+        let mut camera_depth = graph.import("camera_depth");
+        let mut ambient_occlusion = graph.import("ambient_occlusion");
+        let mut gbuffer = graph.import("gbuffer");
+
+        // The deferred path lights by screen area instead, all but the
+        // transparent surfaces.
+        if self.deferred_pass.is_none() || !transparent.is_empty() {
+            let mut pass = graph.add_pass("light clusters");
+            clusters = pass.write(clusters);
+            pass.execute(move |ctx| self.cluster_pass.run(ctx.encoder));
+        }
+
+        if self.ssao.is_some() {
+            let mut pass = graph.add_pass("depth prepass");
+            camera_depth = pass.write(camera_depth);
+            pass.execute(move |ctx| {
+                self.render_depth_prepass(
+                    ctx.encoder,
+                    self.depth_attachment(wgpu::LoadOp::Clear(1.0)),
+                )
+            });
+
+            let mut pass = graph.add_pass("ssao");
+            pass.read(camera_depth);
+            ambient_occlusion = pass.write(ambient_occlusion);
+            pass.execute(move |ctx| self.ssao_pass.render(ctx.encoder, &self.targets));
+        }
+        // What shading with the full material reads.
+        let lighting = [
+            frame.shadow_maps,
+            frame.point_shadow_maps,
+            clusters,
+            ambient_occlusion,
+        ];
+
+        // Keep the prepass' depth.
+        let depth_load = match self.ssao {
+            Some(_) => wgpu::LoadOp::Load,
+            None => wgpu::LoadOp::Clear(1.0),
+        };
+        let mut pass = graph.add_pass("camera");
+        for id in lighting {
+            pass.read(id);
+        }
+        frame.hdr = pass.write(frame.hdr);
+        frame.velocity = pass.write(frame.velocity);
+        camera_depth = pass.write(camera_depth);
+        if self.deferred_pass.is_some() {
+            gbuffer = pass.write(gbuffer);
+        }
+        pass.execute(move |ctx| self.render_camera_pass(ctx.encoder, depth_load, sorted));
+
+        if let Some(deferred_pass) = &self.deferred_pass {
+            let mut pass = graph.add_pass("deferred lighting");
+            pass.read(gbuffer)
+                .read(camera_depth)
+                .read(frame.shadow_maps)
+                .read(frame.point_shadow_maps);
+            frame.hdr = pass.write(frame.hdr);
+            pass.execute(move |ctx| {
+                deferred_pass.render(
+                    ctx.encoder,
+                    &self.targets.get(self.hdr_target).view,
+                    &self.camera_bind_group,
+                    &self.light_bind_group,
+                    &self.shadow_bind_group,
+                    self.scene.lights.len() as u32,
+                )
+            });
+
+            // Unlit geometry, the sky and sorted transparent surfaces go on
+            // top of the lit G-buffer.
+            if self.scene.light_gizmo.is_some() || self.sky.is_some() || !sorted.is_empty() {
+                let mut pass = graph.add_pass("forward");
+                pass.read(camera_depth);
+                for id in lighting {
+                    pass.read(id);
+                }
+                frame.hdr = pass.write(frame.hdr);
+                frame.velocity = pass.write(frame.velocity);
+                pass.execute(move |ctx| self.render_forward_pass(ctx.encoder, sorted));
+            }
+        }
+
+        if !weighted_blended.is_empty() {
+            self.add_weighted_blended_passes(
+                graph,
+                frame,
+                camera_depth,
+                lighting,
+                weighted_blended,
+            );
+        }
+    }
+
+    /// Accumulates `draws` into transient targets and composites them over
+    /// the HDR target.
+    fn add_weighted_blended_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &mut FrameResources,
+        camera_depth: ResourceId,
+        lighting: [ResourceId; 4],
+        draws: &'a [TransparentDraw],
+    ) {
+        let desc = |format, sample_count| TextureDesc {
+            format,
+            sample_count,
+        };
+        let accumulation = graph.create_texture(
+            "transparency.accumulation",
+            desc(transparency::ACCUMULATION_FORMAT, 1),
+        );
+        let revealage = graph.create_texture(
+            "transparency.revealage",
+            desc(transparency::REVEALAGE_FORMAT, 1),
+        );
+        let msaa = (self.sample_count > 1).then(|| {
+            [
+                graph.create_texture(
+                    "transparency.msaa_accumulation",
+                    desc(transparency::ACCUMULATION_FORMAT, self.sample_count),
+                ),
+                graph.create_texture(
+                    "transparency.msaa_revealage",
+                    desc(transparency::REVEALAGE_FORMAT, self.sample_count),
+                ),
+            ]
+        });
+
+        let mut pass = graph.add_pass("transparency accumulate");
+        pass.read(camera_depth);
+        for id in lighting {
+            pass.read(id);
+        }
+        let accumulation = pass.write(accumulation);
+        let revealage = pass.write(revealage);
+        for id in msaa.iter().flatten() {
+            pass.write(*id);
+        }
+        pass.execute(move |ctx| {
+            let targets = AccumulationTargets {
+                accumulation: ctx.texture(accumulation),
+                revealage: ctx.texture(revealage),
+                msaa: msaa.map(|[accumulation, revealage]| {
+                    [ctx.texture(accumulation), ctx.texture(revealage)]
+                }),
+            };
+            let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transparent Pass"),
+                color_attachments: &targets.color_attachments(),
+                depth_stencil_attachment: Some(self.depth_attachment(wgpu::LoadOp::Load)),
+            });
+            self.draw_transparent(&mut render_pass, &self.transparency_pass.accumulate, draws);
+        });
+
+        let mut pass = graph.add_pass("transparency composite");
+        pass.read(accumulation).read(revealage);
+        frame.hdr = pass.write(frame.hdr);
+        pass.execute(move |ctx| {
+            self.transparency_pass.composite(
+                ctx.device,
+                ctx.encoder,
+                &ctx.texture(accumulation).view,
+                &ctx.texture(revealage).view,
+                &self.targets.get(self.hdr_target).view,
+            )
+        });
+    }
+
+    /// Resolves the HDR target into `view`, through whichever of TAA, bloom,
+    /// auto exposure and FXAA are on.
+    fn add_post_processing<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: &mut FrameResources,
+        output: ResourceId,
+        view: &'a wgpu::TextureView,
+    ) {
+        if let AntiAliasing::Taa(_) = self.anti_aliasing {
+            let mut pass = graph.add_pass("taa");
+            pass.read(frame.velocity);
+            frame.hdr = pass.write(frame.hdr);
+            pass.execute(move |ctx| self.taa_pass.render(ctx.encoder, &self.targets));
+        }
+        if self.bloom.is_some() {
+            let mut pass = graph.add_pass("bloom");
+            frame.hdr = pass.write(frame.hdr);
+            pass.execute(move |ctx| self.bloom_pass.render(ctx.encoder, &self.targets));
+        }
+        let mut luminance = graph.import("luminance");
+        if self.tonemap.auto_exposure.is_some() {
+            let mut pass = graph.add_pass("auto exposure");
+            pass.read(frame.hdr);
+            luminance = pass.write(luminance);
+            pass.execute(move |ctx| self.auto_exposure_pass.run(ctx.encoder));
+        }
+
+        if self.anti_aliasing == AntiAliasing::Fxaa {
+            let ldr = graph.import("ldr_target");
+            let mut pass = graph.add_pass("tonemap");
+            pass.read(frame.hdr).read(luminance);
+            let ldr = pass.write(ldr);
+            pass.execute(move |ctx| {
+                self.tonemap_pass
+                    .render(ctx.encoder, &self.targets.get(self.ldr_target).view)
+            });
+
+            let mut pass = graph.add_pass("fxaa");
+            pass.read(ldr);
+            pass.write(output);
+            pass.execute(move |ctx| self.fxaa_pass.render(ctx.encoder, view));
+        } else {
+            let mut pass = graph.add_pass("tonemap");
+            pass.read(frame.hdr).read(luminance);
+            pass.write(output);
+            pass.execute(move |ctx| self.tonemap_pass.render(ctx.encoder, view));
+        }
+    }
+
+    /// One of the camera pass' color targets, or the multisampled one
+    /// resolving into it.
+    fn camera_attachment(
+        &self,
+        target: usize,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        // Multisampled frames are resolved into the HDR and velocity targets.
+        let single_sampled = &self
+            .targets
+            .get([self.hdr_target, self.velocity_target][target])
+            .view;
+        wgpu::RenderPassColorAttachment {
+            view: match self.msaa_targets {
+                Some(msaa_targets) => &self.targets.get(msaa_targets[target]).view,
+                None => single_sampled,
+            },
+            resolve_target: self.msaa_targets.map(|_| single_sampled),
+            ops: wgpu::Operations { load, store: true },
+        }
+    }
+
+    fn depth_attachment(
+        &self,
+        load: wgpu::LoadOp<f32>,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.targets.get(self.camera_depth).view,
+            depth_ops: Some(wgpu::Operations { load, store: true }),
+            stencil_ops: None,
+        }
+    }
+
+    /// Draws the opaque scene, and on the forward path the sky and the
+    /// `sorted` transparent queue after it.
+    fn render_camera_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_load: wgpu::LoadOp<f32>,
+        sorted: &[TransparentDraw],
+    ) {
+        let mut color_attachments = vec![
+            self.camera_attachment(
+                0,
+                wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+            ),
+            self.camera_attachment(1, wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)),
+        ];
+        if let Some(deferred_pass) = &self.deferred_pass {
+            color_attachments.extend(deferred_pass.gbuffer.color_attachments(&self.targets));
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(self.depth_attachment(depth_load)),
+        });
+
+        if self.deferred_pass.is_none() {
+            self.draw_light_gizmos(&mut render_pass);
+        }
+
+        for ((_, model), instances) in self.scene.models().zip(&self.instance_buffers) {
+            if instances.count == 0 {
+                continue;
+            }
+            // Vertices
+            render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+
+            // Draw
+            for mesh in &model.meshes {
+                let material = &model.materials[mesh.material];
+                let pass = match material.factors.alpha_mode {
+                    model::AlphaMode::Opaque => &self.camera_pass,
+                    model::AlphaMode::Mask { .. } => &self.cutout_pass,
+                    model::AlphaMode::Blend => continue,
+                };
+                render_pass.set_pipeline(&pass.pipeline);
+                render_pass.draw_shadow_mesh_instanced(
+                    mesh,
+                    material,
+                    0..instances.count,
+                    &self.camera_bind_group,
+                    &self.light_bind_group,
+                    &self.shadow_bind_group,
+                );
+            }
+        }
+
+        if self.deferred_pass.is_none() {
+            if let Some(sky) = &self.sky {
+                self.sky_pass
+                    .render(&mut render_pass, sky, &self.camera_bind_group);
+            }
+            self.draw_transparent(&mut render_pass, &self.transparency_pass.sorted, sorted);
+        }
+    }
+
+    /// Draws the light gizmos, the sky and the `sorted` transparent queue
+    /// over the lit G-buffer.
+    fn render_forward_pass(&self, encoder: &mut wgpu::CommandEncoder, sorted: &[TransparentDraw]) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Forward Pass"),
+            color_attachments: &[
+                self.camera_attachment(0, wgpu::LoadOp::Load),
+                self.camera_attachment(1, wgpu::LoadOp::Load),
+            ],
+            depth_stencil_attachment: Some(self.depth_attachment(wgpu::LoadOp::Load)),
+        });
+        self.draw_light_gizmos(&mut render_pass);
+        if let Some(sky) = &self.sky {
+            self.sky_pass
+                .render(&mut render_pass, sky, &self.camera_bind_group);
+        }
+        self.draw_transparent(&mut render_pass, &self.transparency_pass.sorted, sorted);
+    }
+
+    /// Draws the opaque geometry into the camera pass' depth buffer. Cutouts
+    /// are left to the camera pass, and don't occlude in SSAO.
+    fn render_depth_prepass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_attachment: wgpu::RenderPassDepthStencilAttachment,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(depth_attachment),
+        });
+        render_pass.set_pipeline(&self.depth_prepass.pipeline);
+        for ((_, model), instances) in self.scene.models().zip(&self.instance_buffers) {
+            if instances.count == 0 {
+                continue;
+            }
+            render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+            for mesh in &model.meshes {
+                let material = &model.materials[mesh.material];
+                if material.factors.alpha_mode != model::AlphaMode::Opaque {
+                    continue;
+                }
+                render_pass.draw_shadow_mesh_instanced(
+                    mesh,
+                    material,
+                    0..instances.count,
+                    &self.camera_bind_group,
+                    &self.light_bind_group,
+                    &self.shadow_bind_group,
+                );
+            }
+        }
+    }
+
+    /// Every instance of every [`AlphaMode::Blend`](model::AlphaMode::Blend)
+    /// mesh, back to front when they are sorted.
+    fn transparent_queue(&self) -> Vec<TransparentDraw> {
+        use cgmath::Transform;
+
+        let mut draws = Vec::new();
+        for (model_index, ((_, model), instances)) in
+            self.scene.models().zip(&self.instance_buffers).enumerate()
+        {
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                let material = &model.materials[mesh.material];
+                if material.factors.alpha_mode != model::AlphaMode::Blend {
+                    continue;
+                }
+                for (instance, transform) in instances.transforms.iter().enumerate() {
+                    let center = transform.transform_point(mesh.center.into());
+                    draws.push(TransparentDraw {
+                        model: model_index,
+                        mesh: mesh_index,
+                        instance: instance as u32,
+                        view_depth: self.camera.view_depth(center),
+                    });
+                }
+            }
+        }
+        if self.transparency == Transparency::Sorted {
+            transparency::sort_back_to_front(&mut draws);
+        }
+        draws
+    }
+
+    /// Draws `draws` one instance at a time with `pass`.
+    fn draw_transparent<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pass: &'a renderpass::Pass,
+        draws: &[TransparentDraw],
+    ) {
+        let models = self
+            .scene
+            .models()
+            .map(|(_, model)| model)
+            .collect::<Vec<_>>();
+        render_pass.set_pipeline(&pass.pipeline);
+        for draw in draws {
+            let model = models[draw.model];
+            let mesh = &model.meshes[draw.mesh];
+            render_pass.set_vertex_buffer(1, self.instance_buffers[draw.model].buffer.slice(..));
+            render_pass.draw_shadow_mesh_instanced(
+                mesh,
+                &model.materials[mesh.material],
+                draw.instance..draw.instance + 1,
+                &self.camera_bind_group,
+                &self.light_bind_group,
+                &self.shadow_bind_group,
+            );
+        }
+    }
+
+    /// Draws the scene's light gizmo at every light.
+    fn draw_light_gizmos<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(gizmo) = self.scene.light_gizmo {
+            render_pass.set_pipeline(&self.light_render_pipeline);
+            render_pass.draw_light_model_instanced(
+                self.scene.model(gizmo),
+                0..self.scene.lights.len() as u32,
+                &self.camera_bind_group,
+                &self.light_bind_group,
+            );
+        }
+    }
+
+    /// Draws every model instance into a shadow map with `pass`, whose only
+    /// bind group is `bind_group`.
+    fn render_depth(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: ShadowTarget,
+        pass: &renderpass::Pass,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let filter = self.shadow_maps.options.filter;
+        // Clear the moments to those of the far plane.
+        let [r, g, b, a] = filter.moments(1.0);
+        let moments = [wgpu::RenderPassColorAttachment {
+            view: target.moments,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: r as f64,
+                    g: g as f64,
+                    b: b as f64,
+                    a: a as f64,
+                }),
+                store: true,
+            },
+        }];
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: if filter.uses_moments() { &moments } else { &[] },
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: target.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&pass.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        for ((_, model), instances) in self.scene.models().zip(&self.instance_buffers) {
+            if instances.count == 0 {
+                continue;
+            }
+            render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+            render_pass.draw_model_depth_instanced(model, 0..instances.count);
+        }
+    }
+
+    /// Copies the last frame rendered by a headless engine back to the CPU.
+    pub fn read_frame(&self) -> anyhow::Result<image::RgbaImage> {
+        let target = self.targets.get(
+            self.offscreen_target
+                .context("Only headless engines can read back frames")?,
+        );
+
+        let width = self.config.width;
+        let height = self.config.height;
+        // Rows in a texture to buffer copy have to be aligned to 256 bytes.
+        let unpadded_bytes_per_row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let buffer_slice = output_buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = buffer_slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        output_buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .context("Readback buffer does not match the frame size")
+    }
+
+    /// Reads back the last headless frame and writes it to `path` as a PNG.
+    pub fn save_frame<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        self.read_frame()?
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Automatic exposure from a luminance histogram of the HDR frame.
+//!
+//! A compute pass sorts every pixel into a log-luminance histogram, and a
+//! second one averages the histogram between two percentiles and moves the
+//! adapted luminance towards it. The tonemap pass reads the adapted luminance
+//! straight from the GPU, so nothing is read back.
+
+use wgpu::util::DeviceExt;
+
+use crate::rendertarget::{RenderTargets, TargetId};
+
+/// Bins in the histogram. Bin 0 holds black pixels, which are ignored.
+pub const HISTOGRAM_BINS: usize = 256;
+/// Width and height of the histogram workgroups.
+const WORKGROUP_SIZE: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AutoExposureOptions {
+    /// Darkest luminance the histogram tells apart, in stops.
+    pub min_log_luminance: f32,
+    /// Brightest luminance the histogram tells apart, in stops.
+    pub max_log_luminance: f32,
+    /// Fraction of the darkest pixels left out of the average.
+    pub low_percentile: f32,
+    /// Fraction of pixels, from the darkest, up to which the average reaches.
+    pub high_percentile: f32,
+    /// How fast exposure adapts when the scene gets brighter, per second.
+    pub speed_up: f32,
+    /// How fast exposure adapts when the scene gets darker, per second.
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposureOptions {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            low_percentile: 0.1,
+            high_percentile: 0.95,
+            speed_up: 3.0,
+            speed_down: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AutoExposureUniform {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    speed_up: f32,
+    speed_down: f32,
+    delta_time: f32,
+    _padding: f32,
+}
+
+impl AutoExposureUniform {
+    fn new(options: &AutoExposureOptions, delta_time: f32) -> Self {
+        let low_percentile = options.low_percentile.clamp(0.0, 1.0);
+        Self {
+            min_log_luminance: options.min_log_luminance,
+            log_luminance_range: (options.max_log_luminance - options.min_log_luminance)
+                .max(f32::EPSILON),
+            low_percentile,
+            high_percentile: options.high_percentile.clamp(low_percentile, 1.0),
+            speed_up: options.speed_up.max(0.0),
+            speed_down: options.speed_down.max(0.0),
+            delta_time,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Compute passes that adapt the exposure luminance to the HDR frame.
+pub struct AutoExposurePass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    /// Holds the adapted luminance as a single `f32`, padded to 16 bytes.
+    pub luminance_buffer: wgpu::Buffer,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    /// The HDR target that's measured, and its size.
+    hdr: TargetId,
+    size: (u32, u32),
+}
+
+impl AutoExposurePass {
+    /// Measures `hdr`, one of `targets`.
+    pub fn new(device: &wgpu::Device, targets: &RenderTargets, hdr: TargetId) -> Self {
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Auto Exposure Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                storage(2),
+                storage(3),
+            ],
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("auto_exposure.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[AutoExposureUniform::new(
+                &AutoExposureOptions::default(),
+                0.0,
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let histogram_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("auto_exposure.histogram_buffer"),
+            contents: bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // Zero until the first frame has been measured, which then snaps to
+        // its luminance instead of adapting from black.
+        let luminance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("auto_exposure.luminance_buffer"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &targets.get(hdr).view,
+            &uniform_buffer,
+            &histogram_buffer,
+            &luminance_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("auto_exposure.pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader =
+            device.create_shader_module(&wgpu::include_wgsl!("../shaders/auto_exposure.wgsl"));
+
+        let histogram_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("auto_exposure.histogram_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "build_histogram",
+        });
+        let average_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("auto_exposure.average_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "average_histogram",
+        });
+
+        Self {
+            layout,
+            bind_group,
+            uniform_buffer,
+            histogram_buffer,
+            luminance_buffer,
+            histogram_pipeline,
+            average_pipeline,
+            hdr,
+            size: targets.size(hdr),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr_view: &wgpu::TextureView,
+        uniform_buffer: &wgpu::Buffer,
+        histogram_buffer: &wgpu::Buffer,
+        luminance_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("auto_exposure.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: luminance_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Measures the recreated HDR target, e.g. after a resize.
+    pub fn set_input(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &targets.get(self.hdr).view,
+            &self.uniform_buffer,
+            &self.histogram_buffer,
+            &self.luminance_buffer,
+        );
+        self.size = targets.size(self.hdr);
+    }
+
+    /// Uploads `options` for a frame `delta_time` seconds after the last.
+    pub fn update(&self, queue: &wgpu::Queue, options: &AutoExposureOptions, delta_time: f32) {
+        let uniform = AutoExposureUniform::new(options, delta_time);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("auto_exposure.compute_pass"),
+        });
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_pipeline(&self.histogram_pipeline);
+        let (width, height) = self.size;
+        pass.dispatch(
+            width.div_ceil(WORKGROUP_SIZE),
+            height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+
+        pass.set_pipeline(&self.average_pipeline);
+        pass.dispatch(1, 1, 1);
+    }
+}
@@ -0,0 +1,174 @@
+//! Fast approximate anti-aliasing on the tonemapped frame.
+
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use crate::pipeline::{PipelineBuilder, PipelineCache};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FxaaUniform {
+    /// Set when the input decodes sRGB on sampling.
+    linear_source: u32,
+    _padding: [u32; 3],
+}
+
+/// Fullscreen pass that smooths edges in the tonemapped frame while
+/// writing it to the output target.
+pub struct FxaaPass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl FxaaPass {
+    /// Reads `ldr_view`, which has the format of `config`, as is the output.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        config: &wgpu::SurfaceConfiguration,
+        ldr_view: &wgpu::TextureView,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("FXAA Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("fxaa.sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fxaa.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[FxaaUniform {
+                linear_source: config.format.describe().srgb as u32,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group =
+            Self::create_bind_group(device, &layout, ldr_view, &sampler, &uniform_buffer);
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("fxaa.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/fxaa.wgsl")));
+        let render_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("fxaa.render_pipeline", &pipeline_layout, &shader)
+                .fragment("fxaa_main")
+                .color_target(config.format, Some(wgpu::BlendState::REPLACE))
+                .cull_mode(None),
+        );
+
+        Self {
+            layout,
+            bind_group,
+            sampler,
+            uniform_buffer,
+            render_pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        ldr_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fxaa.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(ldr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Reads from a new tonemapped texture, e.g. after a resize.
+    pub fn set_input(&mut self, device: &wgpu::Device, ldr_view: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            ldr_view,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("fxaa.render_pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
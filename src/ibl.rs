@@ -0,0 +1,498 @@
+//! Image based lighting.
+//!
+//! An equirectangular HDR image of the surroundings is baked, in compute
+//! passes, into a cube map and two convolutions of it: an irradiance map
+//! holding the diffuse light arriving from every direction, and a specular
+//! map whose mips are blurred for increasing roughness. Together with a
+//! lookup table of the BRDF's response to them, the split-sum approximation,
+//! they replace the constant ambient term of the camera pass.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::Context;
+use wgpu::util::DeviceExt;
+
+use crate::texture;
+
+/// Format of the baked cube maps and the BRDF lookup table.
+pub const ENVIRONMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Width of a face of the environment cube map, which has a full mip chain.
+pub const ENVIRONMENT_RESOLUTION: u32 = 512;
+/// Width of a face of the irradiance map. Diffuse light barely changes
+/// with direction, so it can be small.
+pub const IRRADIANCE_RESOLUTION: u32 = 32;
+/// Width of a face of the specular map's first mip.
+pub const PREFILTERED_RESOLUTION: u32 = 128;
+/// Mips of the specular map, from roughness 0 to 1.
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+/// Width and height of the BRDF lookup table.
+pub const BRDF_LUT_RESOLUTION: u32 = 256;
+/// Width and height of the bake workgroups.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Mips of a full chain down from `resolution`.
+pub fn mip_level_count(resolution: u32) -> u32 {
+    32 - resolution.max(1).leading_zeros()
+}
+
+/// Roughness the specular map's mip `level` is prefiltered for. The camera
+/// pass picks the mip the other way around.
+pub fn prefiltered_roughness(level: u32) -> f32 {
+    level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniform {
+    roughness: f32,
+    source_size: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    intensity: f32,
+    /// Whether an environment is loaded; the camera pass falls back to a
+    /// constant ambient term otherwise.
+    enabled: u32,
+    /// Last mip of the specular map.
+    max_lod: f32,
+    _padding: u32,
+}
+
+/// The cube maps baked from one environment image.
+pub struct Environment {
+    /// The surroundings themselves, with mips.
+    pub cubemap: texture::Texture,
+    /// Cosine weighted irradiance for diffuse lighting.
+    pub irradiance: texture::Texture,
+    /// Specular reflections, blurred further with every mip.
+    pub prefiltered: texture::Texture,
+}
+
+impl Environment {
+    fn new(device: &wgpu::Device, resolution: u32, irradiance: u32, prefiltered: u32) -> Self {
+        let cube = |resolution, mip_levels, label| {
+            texture::Texture::create_storage_texture(
+                device,
+                resolution,
+                6,
+                mip_levels,
+                ENVIRONMENT_FORMAT,
+                wgpu::TextureViewDimension::Cube,
+                label,
+            )
+        };
+        Self {
+            cubemap: cube(
+                resolution,
+                mip_level_count(resolution),
+                "environment_cubemap",
+            ),
+            irradiance: cube(irradiance, 1, "environment_irradiance"),
+            prefiltered: cube(
+                prefiltered,
+                PREFILTERED_MIP_LEVELS.min(mip_level_count(prefiltered)),
+                "environment_prefiltered",
+            ),
+        }
+    }
+}
+
+/// Bakes environments, and holds what the camera pass binds for them.
+pub struct IblPass {
+    equirect_layout: wgpu::BindGroupLayout,
+    downsample_layout: wgpu::BindGroupLayout,
+    irradiance_layout: wgpu::BindGroupLayout,
+    prefilter_layout: wgpu::BindGroupLayout,
+    equirect_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    irradiance_pipeline: wgpu::ComputePipeline,
+    prefilter_pipeline: wgpu::ComputePipeline,
+    /// Scale and bias to the Fresnel reflectance at normal incidence, by
+    /// view angle and roughness.
+    pub brdf_lut: texture::Texture,
+    /// Bound while no environment is loaded.
+    pub placeholder: Environment,
+    /// Intensity and whether an environment is loaded, read by the camera
+    /// pass.
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl IblPass {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let output_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: ENVIRONMENT_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+            },
+            count: None,
+        };
+        let texture_entry = |binding, view_dimension, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+            },
+            count: None,
+        };
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 4,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Sampler {
+                comparison: false,
+                filtering: true,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+
+        // Every entry point binds only what it reads, see `ibl.wgsl`.
+        let equirect_layout = layout(
+            "Equirect Layout",
+            &[
+                texture_entry(0, wgpu::TextureViewDimension::D2, false),
+                output_entry,
+            ],
+        );
+        let downsample_layout = layout(
+            "Environment Downsample Layout",
+            &[
+                texture_entry(2, wgpu::TextureViewDimension::D2Array, false),
+                output_entry,
+            ],
+        );
+        let irradiance_layout = layout(
+            "Irradiance Layout",
+            &[
+                texture_entry(3, wgpu::TextureViewDimension::Cube, true),
+                sampler_entry,
+                output_entry,
+            ],
+        );
+        let prefilter_layout = layout(
+            "Prefilter Layout",
+            &[
+                texture_entry(3, wgpu::TextureViewDimension::Cube, true),
+                sampler_entry,
+                output_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+        let brdf_layout = layout("BRDF LUT Layout", &[output_entry]);
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("../shaders/ibl.wgsl"));
+        let pipeline = |layout: &wgpu::BindGroupLayout, entry_point| {
+            let label = format!("ibl.{}", entry_point);
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let equirect_pipeline = pipeline(&equirect_layout, "equirect_to_cube");
+        let downsample_pipeline = pipeline(&downsample_layout, "downsample");
+        let irradiance_pipeline = pipeline(&irradiance_layout, "irradiance");
+        let prefilter_pipeline = pipeline(&prefilter_layout, "prefilter_specular");
+        let brdf_pipeline = pipeline(&brdf_layout, "brdf_lut");
+
+        // The lookup table doesn't depend on the environment, so it is baked
+        // once up front.
+        let brdf_lut = texture::Texture::create_storage_texture(
+            device,
+            BRDF_LUT_RESOLUTION,
+            1,
+            1,
+            ENVIRONMENT_FORMAT,
+            wgpu::TextureViewDimension::D2,
+            "brdf_lut",
+        );
+        let brdf_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ibl.brdf_bind_group"),
+            layout: &brdf_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&storage_view(&brdf_lut, 0)),
+            }],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("ibl.brdf_encoder"),
+        });
+        dispatch(
+            &mut encoder,
+            &brdf_pipeline,
+            &brdf_bind_group,
+            BRDF_LUT_RESOLUTION,
+            1,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ibl.uniform_buffer"),
+            size: std::mem::size_of::<EnvironmentUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            equirect_layout,
+            downsample_layout,
+            irradiance_layout,
+            prefilter_layout,
+            equirect_pipeline,
+            downsample_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            brdf_lut,
+            placeholder: Environment::new(device, 1, 1, 1),
+            uniform_buffer,
+        }
+    }
+
+    /// Loads and bakes an equirectangular Radiance HDR image.
+    pub fn load<P: AsRef<Path>>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> anyhow::Result<Environment> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let decoder = image::codecs::hdr::HdrDecoder::new(BufReader::new(file))
+            .with_context(|| format!("Failed to decode {:?}", path))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?;
+        self.bake(device, queue, metadata.width, metadata.height, &pixels)
+    }
+
+    /// Bakes an equirectangular image of `width` by `height` linear pixels,
+    /// row by row from the top.
+    pub fn bake(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[image::Rgb<f32>],
+    ) -> anyhow::Result<Environment> {
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        anyhow::ensure!(
+            width <= max_dimension && height <= max_dimension,
+            "Environment of {}x{} exceeds the texture size limit of {}",
+            width,
+            height,
+            max_dimension
+        );
+        anyhow::ensure!(
+            pixels.len() == (width * height) as usize,
+            "Expected {} pixels, got {}",
+            width * height,
+            pixels.len()
+        );
+
+        let data: Vec<[f32; 4]> = pixels.iter().map(|p| [p[0], p[1], p[2], 1.0]).collect();
+        let equirect = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("environment_equirect"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            bytemuck::cast_slice(&data),
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let environment = Environment::new(
+            device,
+            ENVIRONMENT_RESOLUTION,
+            IRRADIANCE_RESOLUTION,
+            PREFILTERED_RESOLUTION,
+        );
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("ibl.bake_encoder"),
+        });
+
+        let bind_group = |label, layout, entries: &[wgpu::BindGroupEntry]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries,
+            })
+        };
+
+        let cubemap = &environment.cubemap;
+        let output = storage_view(cubemap, 0);
+        let equirect_bind_group = bind_group(
+            "ibl.equirect_bind_group",
+            &self.equirect_layout,
+            &[texture_entry(0, &equirect_view), texture_entry(1, &output)],
+        );
+        dispatch(
+            &mut encoder,
+            &self.equirect_pipeline,
+            &equirect_bind_group,
+            ENVIRONMENT_RESOLUTION,
+            6,
+        );
+
+        // The convolutions read blurrier mips for wider lobes.
+        for level in 1..mip_level_count(ENVIRONMENT_RESOLUTION) {
+            let source = storage_view(cubemap, level - 1);
+            let output = storage_view(cubemap, level);
+            let downsample_bind_group = bind_group(
+                "ibl.downsample_bind_group",
+                &self.downsample_layout,
+                &[texture_entry(2, &source), texture_entry(1, &output)],
+            );
+            dispatch(
+                &mut encoder,
+                &self.downsample_pipeline,
+                &downsample_bind_group,
+                ENVIRONMENT_RESOLUTION >> level,
+                6,
+            );
+        }
+
+        let output = storage_view(&environment.irradiance, 0);
+        let irradiance_bind_group = bind_group(
+            "ibl.irradiance_bind_group",
+            &self.irradiance_layout,
+            &[
+                texture_entry(3, &cubemap.view),
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                },
+                texture_entry(1, &output),
+            ],
+        );
+        dispatch(
+            &mut encoder,
+            &self.irradiance_pipeline,
+            &irradiance_bind_group,
+            IRRADIANCE_RESOLUTION,
+            6,
+        );
+
+        for level in 0..PREFILTERED_MIP_LEVELS {
+            let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("ibl.prefilter_buffer"),
+                contents: bytemuck::cast_slice(&[PrefilterUniform {
+                    roughness: prefiltered_roughness(level),
+                    source_size: ENVIRONMENT_RESOLUTION as f32,
+                    _padding: [0.0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let output = storage_view(&environment.prefiltered, level);
+            let prefilter_bind_group = bind_group(
+                "ibl.prefilter_bind_group",
+                &self.prefilter_layout,
+                &[
+                    texture_entry(3, &cubemap.view),
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                    },
+                    texture_entry(1, &output),
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: uniform.as_entire_binding(),
+                    },
+                ],
+            );
+            dispatch(
+                &mut encoder,
+                &self.prefilter_pipeline,
+                &prefilter_bind_group,
+                PREFILTERED_RESOLUTION >> level,
+                6,
+            );
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+        Ok(environment)
+    }
+
+    /// Scales the environment's light by `intensity`, or turns image based
+    /// lighting off when `enabled` is false.
+    pub fn update(&self, queue: &wgpu::Queue, enabled: bool, intensity: f32) {
+        let uniform = EnvironmentUniform {
+            intensity,
+            enabled: enabled as u32,
+            max_lod: (PREFILTERED_MIP_LEVELS - 1) as f32,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+/// A view of every layer of mip `level` of `texture` that a bake pass can
+/// write or read texel by texel.
+fn storage_view(texture: &texture::Texture, level: u32) -> wgpu::TextureView {
+    texture.texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: level,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn texture_entry(binding: u32, view: &wgpu::TextureView) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: wgpu::BindingResource::TextureView(view),
+    }
+}
+
+/// Runs `pipeline` over a square of `resolution` texels in each of `layers`
+/// layers.
+fn dispatch(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    resolution: u32,
+    layers: u32,
+) {
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("ibl.compute_pass"),
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    let groups = resolution.max(1).div_ceil(WORKGROUP_SIZE);
+    pass.dispatch(groups, groups, layers);
+}
@@ -0,0 +1,29 @@
+pub mod app;
+pub mod bloom;
+pub mod camera;
+pub mod cameracontroller;
+pub mod cluster;
+pub mod deferred;
+pub mod depthpass;
+pub mod engine;
+pub mod exposure;
+pub mod fxaa;
+pub mod ibl;
+pub mod instance;
+pub mod lighting;
+pub mod model;
+pub mod pipeline;
+pub mod rendergraph;
+pub mod renderpass;
+pub mod rendertarget;
+pub mod scene;
+pub mod shadow;
+pub mod sky;
+pub mod ssao;
+pub mod taa;
+pub mod texture;
+pub mod tonemap;
+pub mod transparency;
+
+pub use app::{run, App};
+pub use engine::{AntiAliasing, DebugView, Engine, EngineOptions, ShadingPath};
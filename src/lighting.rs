@@ -9,33 +9,175 @@ use crate::{
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct LightRaw {
     pub proj: [[f32; 4]; 4],
+    /// xyz: position, w: range
     pub position: [f32; 4],
+    pub direction: [f32; 4],
+    /// rgb: color, a: intensity
     pub color: [f32; 4],
+    pub kind: u32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    /// Layer of the point shadow cube array, or -1 if the light casts none.
+    pub shadow_index: i32,
 }
 
-#[derive(Debug)]
+/// Header of the light storage buffer, followed by `count` [`LightRaw`]s.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsHeader {
+    pub count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightsHeader {
+    pub fn new(count: u32) -> Self {
+        Self {
+            count,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// Full intensity inside `inner_angle`, fading out to zero at `outer_angle`.
+    /// Both angles are measured from the light's direction.
+    Spot {
+        inner_angle: cgmath::Deg<f32>,
+        outer_angle: cgmath::Deg<f32>,
+    },
+    /// Infinitely far away, lighting everything from `direction`.
+    Directional,
+}
+
+#[derive(Debug, Clone)]
 pub struct Light {
+    pub kind: LightKind,
     pub position: cgmath::Point3<f32>,
+    /// Direction the light points in. Unused by point lights.
+    pub direction: cgmath::Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which point and spot lights stop contributing.
+    pub range: f32,
+    pub cast_shadows: bool,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Point,
+            position: cgmath::Point3::new(0.0, 0.0, 0.0),
+            direction: -cgmath::Vector3::unit_y(),
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 100.0,
+            cast_shadows: true,
+        }
+    }
 }
 
 impl Light {
+    pub fn point(position: cgmath::Point3<f32>) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn spot(
+        position: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+        inner_angle: cgmath::Deg<f32>,
+        outer_angle: cgmath::Deg<f32>,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            },
+            position,
+            direction,
+            ..Default::default()
+        }
+    }
+
+    pub fn directional(direction: cgmath::Vector3<f32>) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            direction,
+            ..Default::default()
+        }
+    }
+
     pub fn to_raw(&self) -> LightRaw {
-        use cgmath::{Deg, EuclideanSpace, Matrix4, PerspectiveFov, Point3, Vector3};
-
-        let mx_view = Matrix4::look_at_rh(self.position, Point3::origin(), Vector3::unit_z());
-        let projection = PerspectiveFov {
-            fovy: Deg(45.0).into(),
-            aspect: 1.0,
-            near: 0.1,
-            far: 100.0,
+        use cgmath::{
+            Angle, EuclideanSpace, InnerSpace, Matrix4, PerspectiveFov, Point3, SquareMatrix,
+            Vector3,
+        };
+
+        // Any up vector but one along the light's direction.
+        let up = |direction: Vector3<f32>| {
+            if direction.y.abs() > 0.99 {
+                Vector3::unit_z()
+            } else {
+                Vector3::unit_y()
+            }
+        };
+        let mx_view_proj = match self.kind {
+            LightKind::Directional => {
+                // Cover the area around the origin, looking along the light.
+                let direction = self.direction.normalize();
+                let eye = Point3::origin() - direction * 50.0;
+                let mx_view = Matrix4::look_at_rh(eye, Point3::origin(), up(direction));
+                let projection = cgmath::ortho(-30.0, 30.0, -30.0, 30.0, 0.1, 100.0);
+                OPENGL_TO_WGPU_MATRIX * projection * mx_view
+            }
+            LightKind::Spot { outer_angle, .. } => {
+                let mx_view = Matrix4::look_at_rh(
+                    self.position,
+                    self.position + self.direction,
+                    up(self.direction.normalize()),
+                );
+                // A perspective projection can't reach 180 degrees.
+                let projection = PerspectiveFov {
+                    fovy: cgmath::Deg(outer_angle.0.min(89.0) * 2.0).into(),
+                    aspect: 1.0,
+                    near: 0.1,
+                    far: self.range,
+                };
+                OPENGL_TO_WGPU_MATRIX * cgmath::Matrix4::from(projection.to_perspective()) * mx_view
+            }
+            // Point lights cast their shadows through cube maps, see
+            // `shadow::cube_face_view_projs`.
+            LightKind::Point => Matrix4::identity(),
         };
-        let mx_correction = OPENGL_TO_WGPU_MATRIX;
-        let mx_view_proj =
-            mx_correction * cgmath::Matrix4::from(projection.to_perspective()) * mx_view;
+
+        let (kind, cos_inner, cos_outer) = match self.kind {
+            LightKind::Point => (0, 1.0, 1.0),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (1, inner_angle.cos(), outer_angle.cos()),
+            LightKind::Directional => (2, 1.0, 1.0),
+        };
+        let direction = self.direction.normalize();
+
         LightRaw {
             proj: *mx_view_proj.as_ref(),
-            position: [self.position.x, self.position.y, self.position.z, 1.0],
-            color: [1.0, 1.0, 1.0, 1.0],
+            position: [
+                self.position.x,
+                self.position.y,
+                self.position.z,
+                self.range,
+            ],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [self.color[0], self.color[1], self.color[2], self.intensity],
+            kind,
+            cos_inner,
+            cos_outer,
+            shadow_index: -1,
         }
     }
 }
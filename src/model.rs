@@ -1,10 +1,10 @@
-use std::{ops::Range, path::Path};
+use std::{ops::Range, path::Path, sync::Arc};
 
 use anyhow::Context;
 use tobj::LoadOptions;
 use wgpu::util::DeviceExt;
 
-use crate::texture;
+use crate::{scene::Transform, texture};
 
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -16,6 +16,8 @@ pub struct ModelVertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
     normal: [f32; 3],
+    tangent: [f32; 3],
+    bitangent: [f32; 3],
 }
 
 impl Vertex for ModelVertex {
@@ -41,28 +43,283 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
 
+/// How a material's alpha is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Cutout: fragments with alpha below `cutoff` are dropped. With MSAA,
+    /// alpha to coverage fades the edges out over the samples instead.
+    Mask { cutoff: f32 },
+    /// Translucent: blended over what's behind it in the transparent queue,
+    /// without writing depth.
+    Blend,
+}
+
+/// Scalar material parameters. Each one multiplies the matching texture.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub occlusion_strength: f32,
+    pub normal_scale: f32,
+    pub alpha_mode: AlphaMode,
+}
+
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            occlusion_strength: 1.0,
+            normal_scale: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    // rgb: emissive, a: alpha cutoff
+    emissive: [f32; 4],
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    normal_scale: f32,
+}
+
+impl From<&MaterialFactors> for MaterialUniform {
+    fn from(factors: &MaterialFactors) -> Self {
+        let [r, g, b] = factors.emissive;
+        let alpha_cutoff = match factors.alpha_mode {
+            AlphaMode::Opaque | AlphaMode::Blend => 0.0,
+            AlphaMode::Mask { cutoff } => cutoff,
+        };
+        Self {
+            base_color: factors.base_color,
+            emissive: [r, g, b, alpha_cutoff],
+            metallic: factors.metallic,
+            roughness: factors.roughness,
+            occlusion_strength: factors.occlusion_strength,
+            normal_scale: factors.normal_scale,
+        }
+    }
+}
+
+/// The maps a material was authored with. Missing ones are replaced by 1x1
+/// textures that leave the factors unchanged.
+#[derive(Default)]
+pub struct MaterialTextures {
+    /// sRGB color, alpha in the a channel.
+    pub base_color: Option<texture::Texture>,
+    /// Linear; roughness in the g channel, metallic in b.
+    pub metallic_roughness: Option<texture::Texture>,
+    /// Linear tangent-space normal.
+    pub normal: Option<texture::Texture>,
+    /// Linear; ambient occlusion in the r channel.
+    pub occlusion: Option<texture::Texture>,
+    /// sRGB emitted color.
+    pub emissive: Option<texture::Texture>,
+}
+
+/// A metallic-roughness PBR material.
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: texture::Texture,
+    pub base_color_texture: texture::Texture,
+    pub metallic_roughness_texture: texture::Texture,
+    pub normal_texture: texture::Texture,
+    pub occlusion_texture: texture::Texture,
+    pub emissive_texture: texture::Texture,
+    pub factors: MaterialFactors,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    /// Layout of group 0: a texture and sampler pair for each map, in the order
+    /// of [`MaterialTextures`], followed by the factors uniform.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = Vec::new();
+        for map in 0..5 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: map * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: map * 2 + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            });
+        }
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 10,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        name: String,
+        textures: MaterialTextures,
+        factors: MaterialFactors,
+    ) -> anyhow::Result<Self> {
+        let fallback = |texture: Option<texture::Texture>, color, format, map: &str| match texture {
+            Some(texture) => Ok(texture),
+            None => texture::Texture::from_color(
+                device,
+                queue,
+                color,
+                format,
+                &format!("{} default {}", name, map),
+            ),
+        };
+        let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let base_color_texture = fallback(textures.base_color, [255; 4], srgb, "base color")?;
+        let metallic_roughness_texture = fallback(
+            textures.metallic_roughness,
+            [255; 4],
+            linear,
+            "metallic roughness",
+        )?;
+        let normal_texture = fallback(textures.normal, [128, 128, 255, 255], linear, "normal")?;
+        let occlusion_texture = fallback(textures.occlusion, [255; 4], linear, "occlusion")?;
+        let emissive_texture = fallback(textures.emissive, [255; 4], srgb, "emissive")?;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Material Buffer", name)),
+            contents: bytemuck::cast_slice(&[MaterialUniform::from(&factors)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let maps = [
+            &base_color_texture,
+            &metallic_roughness_texture,
+            &normal_texture,
+            &occlusion_texture,
+            &emissive_texture,
+        ];
+        let mut entries = Vec::new();
+        for (map, texture) in maps.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: map as u32 * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: map as u32 * 2 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        entries.push(wgpu::BindGroupEntry {
+            binding: 10,
+            resource: uniform_buffer.as_entire_binding(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("material_bind_group"),
+        });
+
+        Ok(Self {
+            name,
+            base_color_texture,
+            metallic_roughness_texture,
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+            factors,
+            uniform_buffer,
+            bind_group,
+        })
+    }
+
+    /// Uploads `factors` after they have been changed.
+    pub fn write_factors(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[MaterialUniform::from(&self.factors)]),
+        );
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    /// Center of the mesh's bounding box in model space, which transparent
+    /// draws are sorted by.
+    pub center: [f32; 3],
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<Material>,
+    /// Shared so that models split out of one glTF file reuse its materials.
+    pub materials: Vec<Arc<Material>>,
+}
+
+/// A node of an imported glTF scene.
+pub struct GltfNode {
+    pub name: String,
+    pub transform: Transform,
+    /// Index into [`GltfScene::models`] of the mesh this node places.
+    pub model: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// Everything read from a glTF file: one [`Model`] per glTF mesh, each with
+/// one [`Mesh`] per primitive, and the node hierarchy that places them.
+pub struct GltfScene {
+    pub models: Vec<Model>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene, or of the first scene if none is set.
+    pub roots: Vec<usize>,
 }
 
 impl Model {
@@ -87,30 +344,74 @@ impl Model {
 
         let mut materials = Vec::new();
         for mat in obj_materials {
-            let diffuse_path = mat.diffuse_texture;
-            let diffuse_texture =
-                texture::Texture::load(device, queue, container_folder.join(diffuse_path))?;
+            let open = |file: &str| -> anyhow::Result<Option<image::DynamicImage>> {
+                if file.is_empty() {
+                    return Ok(None);
+                }
+                let path = container_folder.join(file);
+                image::open(&path)
+                    .map(Some)
+                    .with_context(|| format!("Failed to open {:?}", path))
+            };
+            let upload = |img: Option<image::DynamicImage>,
+                          file: &str,
+                          srgb: bool|
+             -> anyhow::Result<Option<texture::Texture>> {
+                let img = match img {
+                    Some(img) => img,
+                    None => return Ok(None),
+                };
+                let path = container_folder.join(file);
+                let format = if srgb {
+                    wgpu::TextureFormat::Rgba8UnormSrgb
+                } else {
+                    wgpu::TextureFormat::Rgba8Unorm
+                };
+                texture::Texture::from_image_with_format(device, queue, &img, path.to_str(), format)
+                    .map(Some)
+            };
+            let load = |file: &str, srgb: bool| upload(open(file)?, file, srgb);
 
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: Some("material_bind_group"),
-            });
+            // The shaders read alpha from the base color texture, so an alpha
+            // map goes into it.
+            let (alpha_file, _) = mtl_texture_options(&mat.dissolve_texture);
+            let diffuse = open(&mat.diffuse_texture)?;
+            let base_color = match open(alpha_file)? {
+                Some(alpha) => Some(with_alpha_map(diffuse, &alpha)),
+                None => diffuse,
+            };
 
-            materials.push(Material {
-                name: mat.name,
-                diffuse_texture,
-                bind_group,
-            });
+            // `map_Bump` and `bump` end up in normal_texture, `norm` does not.
+            let normal_entry = match mtl_param(&mat, "norm") {
+                Some(norm) => norm,
+                None => &mat.normal_texture,
+            };
+            let (normal_file, normal_scale) = mtl_texture_options(normal_entry);
+
+            let textures = MaterialTextures {
+                base_color: upload(base_color, &mat.diffuse_texture, true)?,
+                normal: load(normal_file, false)?,
+                emissive: load(mtl_param(&mat, "map_Ke").unwrap_or(""), true)?,
+                ..Default::default()
+            };
+            let factors = MaterialFactors {
+                normal_scale,
+                ..MaterialFactors::from_mtl(&mat)
+            };
+            materials.push(Arc::new(Material::new(
+                device, queue, layout, mat.name, textures, factors,
+            )?));
+        }
+        // Meshes without a material use the first one, so make sure it exists.
+        if materials.is_empty() {
+            materials.push(Arc::new(Material::new(
+                device,
+                queue,
+                layout,
+                "default".to_string(),
+                MaterialTextures::default(),
+                MaterialFactors::default(),
+            )?));
         }
 
         let mut meshes = Vec::new();
@@ -129,8 +430,12 @@ impl Model {
                         m.mesh.normals[i * 3 + 1],
                         m.mesh.normals[i * 3 + 2],
                     ],
+                    // Filled in by compute_tangents below.
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
                 })
             }
+            compute_tangents(&mut vertices, &m.mesh.indices);
 
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
@@ -149,11 +454,535 @@ impl Model {
                 index_buffer,
                 num_elements: m.mesh.indices.len() as u32,
                 material: m.mesh.material_id.unwrap_or(0),
+                center: bounds_center(&vertices),
             });
         }
 
         Ok(Self { meshes, materials })
     }
+
+    /// Loads a `.gltf` or `.glb` file with embedded or external buffers and
+    /// images.
+    pub fn load_gltf<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+    ) -> anyhow::Result<GltfScene> {
+        let path = path.as_ref();
+        let (document, buffers, images) =
+            gltf::import(path).with_context(|| format!("Failed to import {:?}", path))?;
+
+        let mut materials = Vec::new();
+        for material in document.materials() {
+            materials.push(Arc::new(gltf_material(
+                device, queue, layout, &images, material,
+            )?));
+        }
+        // Primitives without a material use the glTF default material, which
+        // goes after the document's own.
+        let default_material = materials.len();
+        let unassigned = document
+            .meshes()
+            .flat_map(|mesh| mesh.primitives())
+            .find(|primitive| primitive.material().index().is_none());
+        if let Some(primitive) = unassigned {
+            materials.push(Arc::new(gltf_material(
+                device,
+                queue,
+                layout,
+                &images,
+                primitive.material(),
+            )?));
+        }
+
+        let mut models = Vec::new();
+        for mesh in document.meshes() {
+            let mut meshes = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    log::warn!(
+                        "Skipping {:?} primitive of mesh {:?} in {:?}",
+                        primitive.mode(),
+                        mesh.name(),
+                        path
+                    );
+                    continue;
+                }
+
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions = reader
+                    .read_positions()
+                    .context("glTF primitive has no positions")?
+                    .collect::<Vec<_>>();
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let normals = match reader.read_normals() {
+                    Some(normals) => normals.collect(),
+                    None => compute_normals(&positions, &indices),
+                };
+                let tex_coords = match reader.read_tex_coords(0) {
+                    Some(tex_coords) => tex_coords.into_f32().collect(),
+                    None => vec![[0.0; 2]; positions.len()],
+                };
+
+                let mut vertices = positions
+                    .iter()
+                    .zip(tex_coords)
+                    .zip(normals)
+                    .map(|((position, tex_coords), normal)| ModelVertex {
+                        position: *position,
+                        tex_coords,
+                        normal,
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
+                    })
+                    .collect::<Vec<_>>();
+                match reader.read_tangents() {
+                    // glTF stores the bitangent's handedness in w.
+                    Some(tangents) => {
+                        for (vertex, [x, y, z, w]) in vertices.iter_mut().zip(tangents) {
+                            use cgmath::Vector3;
+                            let normal = Vector3::from(vertex.normal);
+                            let tangent = Vector3::new(x, y, z);
+                            vertex.tangent = tangent.into();
+                            vertex.bitangent = (normal.cross(tangent) * w).into();
+                        }
+                    }
+                    None => compute_tangents(&mut vertices, &indices),
+                }
+
+                let name = format!(
+                    "{:?} {} {}",
+                    path,
+                    mesh.name().unwrap_or("mesh"),
+                    primitive.index()
+                );
+                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Vertex Buffer", name)),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Index Buffer", name)),
+                    contents: bytemuck::cast_slice(&indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                });
+
+                meshes.push(Mesh {
+                    name,
+                    vertex_buffer,
+                    index_buffer,
+                    num_elements: indices.len() as u32,
+                    material: primitive.material().index().unwrap_or(default_material),
+                    center: bounds_center(&vertices),
+                });
+            }
+
+            models.push(Model {
+                meshes,
+                materials: materials.clone(),
+            });
+        }
+
+        let nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                GltfNode {
+                    name: node.name().unwrap_or("node").to_string(),
+                    transform: Transform {
+                        translation: translation.into(),
+                        rotation: cgmath::Quaternion::new(
+                            rotation[3],
+                            rotation[0],
+                            rotation[1],
+                            rotation[2],
+                        ),
+                        scale: scale.into(),
+                    },
+                    model: node.mesh().map(|mesh| mesh.index()),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect();
+
+        let roots = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .map(|scene| scene.nodes().map(|node| node.index()).collect())
+            .unwrap_or_default();
+
+        Ok(GltfScene {
+            models,
+            nodes,
+            roots,
+        })
+    }
+}
+
+fn gltf_material(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    images: &[gltf::image::Data],
+    material: gltf::Material,
+) -> anyhow::Result<Material> {
+    let name = material.name().unwrap_or("material").to_string();
+    let pbr = material.pbr_metallic_roughness();
+
+    let load = |texture: gltf::Texture, srgb: bool| -> anyhow::Result<texture::Texture> {
+        let format = if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let img = gltf_image(&images[texture.source().index()])?;
+        let mut loaded = texture::Texture::from_image_with_format(
+            device,
+            queue,
+            &img,
+            Some(&format!("{} texture {}", name, texture.index())),
+            format,
+        )?;
+        loaded.sampler = gltf_sampler(device, texture.sampler());
+        Ok(loaded)
+    };
+
+    let textures = MaterialTextures {
+        base_color: pbr
+            .base_color_texture()
+            .map(|info| load(info.texture(), true))
+            .transpose()?,
+        metallic_roughness: pbr
+            .metallic_roughness_texture()
+            .map(|info| load(info.texture(), false))
+            .transpose()?,
+        normal: material
+            .normal_texture()
+            .map(|normal| load(normal.texture(), false))
+            .transpose()?,
+        occlusion: material
+            .occlusion_texture()
+            .map(|occlusion| load(occlusion.texture(), false))
+            .transpose()?,
+        emissive: material
+            .emissive_texture()
+            .map(|info| load(info.texture(), true))
+            .transpose()?,
+    };
+    let factors = MaterialFactors {
+        base_color: pbr.base_color_factor(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: material.emissive_factor(),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(1.0, |occlusion| occlusion.strength()),
+        normal_scale: material
+            .normal_texture()
+            .map_or(1.0, |normal| normal.scale()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        },
+    };
+
+    Material::new(device, queue, layout, name, textures, factors)
+}
+
+fn bounds_center(vertices: &[ModelVertex]) -> [f32; 3] {
+    if vertices.is_empty() {
+        return [0.0; 3];
+    }
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in vertices {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+    [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0)
+}
+
+/// Looks up an MTL statement that `tobj` does not parse itself.
+fn mtl_param<'a>(mat: &'a tobj::Material, key: &str) -> Option<&'a str> {
+    mat.unknown_param.get(key).map(|value| value.trim())
+}
+
+fn mtl_floats(mat: &tobj::Material, key: &str) -> Option<Vec<f32>> {
+    mtl_param(mat, key)?
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect()
+}
+
+/// `base`, or white if there's none, with its alpha replaced by the
+/// brightness of `alpha`, which is stretched over it.
+fn with_alpha_map(
+    base: Option<image::DynamicImage>,
+    alpha: &image::DynamicImage,
+) -> image::DynamicImage {
+    use image::GenericImageView;
+
+    let mut base = match base {
+        Some(base) => base.to_rgba8(),
+        None => image::RgbaImage::from_pixel(alpha.width(), alpha.height(), image::Rgba([255; 4])),
+    };
+    let alpha = image::imageops::resize(
+        &alpha.to_luma8(),
+        base.width(),
+        base.height(),
+        image::imageops::FilterType::Triangle,
+    );
+    for (pixel, coverage) in base.pixels_mut().zip(alpha.pixels()) {
+        pixel[3] = coverage[0];
+    }
+    image::DynamicImage::ImageRgba8(base)
+}
+
+/// Splits a texture statement into its file and the `-bm` bump multiplier.
+/// Other options are skipped. The file is everything after the options, so
+/// it may contain spaces.
+fn mtl_texture_options(entry: &str) -> (&str, f32) {
+    let mut scale = 1.0;
+    let mut tokens = entry.split_whitespace().peekable();
+    while let Some(token) = tokens.next() {
+        if token == "-bm" {
+            scale = tokens
+                .next_if(|value| value.parse::<f32>().is_ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(scale);
+        } else if token.starts_with('-') {
+            // Options take up to three numeric arguments or one of a few
+            // words, and a file can follow one that is missing its value.
+            let word = |value: &&str| {
+                matches!(
+                    *value,
+                    "on" | "off"
+                        | "r"
+                        | "g"
+                        | "b"
+                        | "m"
+                        | "l"
+                        | "z"
+                        | "sphere"
+                        | "cube_top"
+                        | "cube_bottom"
+                        | "cube_front"
+                        | "cube_back"
+                        | "cube_left"
+                        | "cube_right"
+                )
+            };
+            if tokens.next_if(word).is_none() {
+                while tokens.next_if(|t| t.parse::<f32>().is_ok()).is_some() {}
+            }
+        } else {
+            let start = token.as_ptr() as usize - entry.as_ptr() as usize;
+            return (entry[start..].trim_end(), scale);
+        }
+    }
+    ("", scale)
+}
+
+impl MaterialFactors {
+    /// Maps the classic MTL parameters, and the PBR extension's `Pr`, `Pm`
+    /// and `Ke` where present, onto metallic-roughness factors.
+    ///
+    /// Alpha comes from `d`, or from `Tr`, its complement, when `d` is
+    /// missing. Materials with an alpha below one are blended; an alpha map
+    /// (`map_d`) on an otherwise solid material makes it a cutout instead,
+    /// with the base color texture's alpha, which [`Model::load`] fills from
+    /// the map, tested against one half.
+    pub fn from_mtl(mat: &tobj::Material) -> Self {
+        let [r, g, b] = mat.diffuse;
+        // Blinn-Phong exponent to GGX roughness, as in Walter et al. 2007.
+        let roughness = mtl_floats(mat, "Pr")
+            .and_then(|v| v.first().copied())
+            .unwrap_or_else(|| (2.0 / (mat.shininess.max(0.0) + 2.0)).sqrt());
+        let metallic = mtl_floats(mat, "Pm")
+            .and_then(|v| v.first().copied())
+            .unwrap_or(0.0);
+        let emissive = match mtl_floats(mat, "Ke").as_deref() {
+            Some([r, g, b, ..]) => [*r, *g, *b],
+            _ => [0.0; 3],
+        };
+        // tobj defaults `d` to one and leaves `Tr` to us.
+        let transparency = mtl_floats(mat, "Tr").and_then(|v| v.first().copied());
+        let alpha = match transparency {
+            Some(tr) if mat.dissolve >= 1.0 => 1.0 - tr,
+            _ => mat.dissolve,
+        }
+        .clamp(0.0, 1.0);
+        let alpha_mode = if alpha < 1.0 {
+            AlphaMode::Blend
+        } else if !mat.dissolve_texture.is_empty() {
+            AlphaMode::Mask { cutoff: 0.5 }
+        } else {
+            AlphaMode::Opaque
+        };
+
+        Self {
+            base_color: [r, g, b, alpha],
+            metallic,
+            roughness,
+            emissive,
+            alpha_mode,
+            ..Default::default()
+        }
+    }
+}
+
+fn gltf_image(data: &gltf::image::Data) -> anyhow::Result<image::DynamicImage> {
+    use gltf::image::Format;
+
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let wide = || {
+        data.pixels
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>()
+    };
+    let img =
+        match data.format {
+            Format::R8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageLuma8),
+            Format::R8G8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageLumaA8),
+            Format::R8G8B8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageRgb8),
+            Format::R8G8B8A8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageRgba8),
+            Format::B8G8R8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageBgr8),
+            Format::B8G8R8A8 => image::ImageBuffer::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageBgra8),
+            Format::R16 => image::ImageBuffer::from_raw(width, height, wide())
+                .map(image::DynamicImage::ImageLuma16),
+            Format::R16G16 => image::ImageBuffer::from_raw(width, height, wide())
+                .map(image::DynamicImage::ImageLumaA16),
+            Format::R16G16B16 => image::ImageBuffer::from_raw(width, height, wide())
+                .map(image::DynamicImage::ImageRgb16),
+            Format::R16G16B16A16 => image::ImageBuffer::from_raw(width, height, wide())
+                .map(image::DynamicImage::ImageRgba16),
+        };
+    img.context("glTF image data does not match its size")
+}
+
+fn gltf_sampler(device: &wgpu::Device, sampler: gltf::texture::Sampler) -> wgpu::Sampler {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+        _ => wgpu::FilterMode::Linear,
+    };
+    let min_filter = match sampler.min_filter() {
+        Some(MinFilter::Nearest)
+        | Some(MinFilter::NearestMipmapNearest)
+        | Some(MinFilter::NearestMipmapLinear) => wgpu::FilterMode::Nearest,
+        _ => wgpu::FilterMode::Linear,
+    };
+
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter,
+        min_filter,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    })
+}
+
+/// Per-vertex tangents and bitangents from the UV gradients of the triangles
+/// around each vertex, orthogonalized against the normal.
+fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let p0 = Vector3::from(vertices[a].position);
+        let uv0 = Vector2::from(vertices[a].tex_coords);
+        let edge1 = Vector3::from(vertices[b].position) - p0;
+        let edge2 = Vector3::from(vertices[c].position) - p0;
+        let duv1 = Vector2::from(vertices[b].tex_coords) - uv0;
+        let duv2 = Vector2::from(vertices[c].tex_coords) - uv0;
+
+        let det = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() < f32::EPSILON {
+            // No usable UV mapping on this triangle.
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+        for &i in &[a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = Vector3::from(vertex.normal);
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < 1e-12 {
+            // Any direction perpendicular to the normal will do.
+            let axis = if normal.x.abs() < 0.9 {
+                Vector3::unit_x()
+            } else {
+                Vector3::unit_y()
+            };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent.into();
+        vertex.bitangent = (normal.cross(tangent) * handedness).into();
+    }
+}
+
+/// Smooth normals for primitives that do not provide their own.
+fn compute_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    use cgmath::{InnerSpace, Vector3};
+
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let pa = Vector3::from(positions[a]);
+        let face = (Vector3::from(positions[b]) - pa).cross(Vector3::from(positions[c]) - pa);
+        normals[a] += face;
+        normals[b] += face;
+        normals[c] += face;
+    }
+    normals
+        .into_iter()
+        .map(|n| {
+            if n.magnitude2() > 0.0 {
+                n.normalize().into()
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect()
 }
 
 pub trait DrawModel<'a> {
@@ -181,6 +1010,8 @@ pub trait DrawModel<'a> {
         light: &'a wgpu::BindGroup,
         shadow: &'a wgpu::BindGroup,
     );
+    /// Draws only the geometry, for passes that bind their own groups.
+    fn draw_model_depth_instanced(&mut self, model: &'a Model, instances: Range<u32>);
     fn draw_model(
         &mut self,
         model: &'a Model,
@@ -252,6 +1083,14 @@ where
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
+    fn draw_model_depth_instanced(&mut self, model: &'a Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            self.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+        }
+    }
+
     fn draw_model(
         &mut self,
         model: &'a Model,
@@ -295,3 +1134,200 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_options_read_the_bump_multiplier() {
+        assert_eq!(
+            mtl_texture_options("-bm 0.5 normal.png"),
+            ("normal.png", 0.5)
+        );
+        assert_eq!(mtl_texture_options("normal.png"), ("normal.png", 1.0));
+        assert_eq!(mtl_texture_options("-bm normal.png"), ("normal.png", 1.0));
+    }
+
+    #[test]
+    fn texture_options_skip_the_others() {
+        assert_eq!(
+            mtl_texture_options("-o 0.5 0.5 -s 2 2 1 -clamp on -bm 2 normal.png"),
+            ("normal.png", 2.0)
+        );
+        assert_eq!(
+            mtl_texture_options("-imfchan l -texres 512 alpha.png"),
+            ("alpha.png", 1.0)
+        );
+    }
+
+    #[test]
+    fn texture_options_without_a_value_leave_the_file() {
+        assert_eq!(
+            mtl_texture_options("-clamp normal.png"),
+            ("normal.png", 1.0)
+        );
+        assert_eq!(
+            mtl_texture_options("-o -bm 3 normal.png"),
+            ("normal.png", 3.0)
+        );
+        assert_eq!(mtl_texture_options("-bm"), ("", 1.0));
+        assert_eq!(mtl_texture_options(""), ("", 1.0));
+    }
+
+    #[test]
+    fn texture_paths_keep_their_spaces() {
+        assert_eq!(
+            mtl_texture_options("-bm 0.25 textures/brick wall normal.png "),
+            ("textures/brick wall normal.png", 0.25)
+        );
+        assert_eq!(
+            mtl_texture_options("my texture.png"),
+            ("my texture.png", 1.0)
+        );
+    }
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> ModelVertex {
+        ModelVertex {
+            position,
+            tex_coords,
+            normal: [0.0, 0.0, 1.0],
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+        }
+    }
+
+    /// A unit quad facing +z with `u` running along x, or against it if
+    /// `mirrored`, and `v` along y.
+    fn quad(mirrored: bool) -> Vec<ModelVertex> {
+        let u = |x: f32| if mirrored { 1.0 - x } else { x };
+        [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .iter()
+            .map(|&[x, y]| vertex([x, y, 0.0], [u(x), y]))
+            .collect()
+    }
+
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn assert_unit_tangent_frame(vertex: &ModelVertex) {
+        use cgmath::{InnerSpace, Vector3};
+
+        let normal = Vector3::from(vertex.normal);
+        let tangent = Vector3::from(vertex.tangent);
+        let bitangent = Vector3::from(vertex.bitangent);
+        assert!(
+            vertex
+                .tangent
+                .iter()
+                .chain(&vertex.bitangent)
+                .all(|v| v.is_finite()),
+            "{:?}",
+            vertex
+        );
+        assert!((tangent.magnitude() - 1.0).abs() < 1e-5, "{:?}", vertex);
+        assert!((bitangent.magnitude() - 1.0).abs() < 1e-5, "{:?}", vertex);
+        assert!(tangent.dot(normal).abs() < 1e-5, "{:?}", vertex);
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let mut vertices = quad(false);
+        compute_tangents(&mut vertices, &QUAD_INDICES);
+        for vertex in &vertices {
+            assert_close(vertex.tangent, [1.0, 0.0, 0.0]);
+            assert_close(vertex.bitangent, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_handedness() {
+        let mut vertices = quad(true);
+        compute_tangents(&mut vertices, &QUAD_INDICES);
+        for vertex in &vertices {
+            assert_close(vertex.tangent, [-1.0, 0.0, 0.0]);
+            // Still along v, though normal x tangent points the other way.
+            assert_close(vertex.bitangent, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_get_finite_tangents() {
+        let mut vertices = vec![
+            // Collinear positions with usable UVs.
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([2.0, 0.0, 0.0], [0.0, 1.0]),
+            // A proper triangle with all its UVs in one spot.
+            vertex([0.0, 0.0, 0.0], [0.5, 0.5]),
+            vertex([1.0, 0.0, 0.0], [0.5, 0.5]),
+            vertex([0.0, 1.0, 0.0], [0.5, 0.5]),
+            // A triangle collapsed to a point.
+            vertex([1.0, 1.0, 1.0], [0.0, 0.0]),
+            vertex([1.0, 1.0, 1.0], [0.0, 0.0]),
+            vertex([1.0, 1.0, 1.0], [0.0, 0.0]),
+        ];
+        compute_tangents(&mut vertices, &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        for vertex in &vertices {
+            assert_unit_tangent_frame(vertex);
+        }
+    }
+
+    #[test]
+    fn normals_face_out_of_counter_clockwise_triangles() {
+        let positions: Vec<_> = quad(false).iter().map(|v| v.position).collect();
+        for normal in compute_normals(&positions, &QUAD_INDICES) {
+            assert_close(normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_get_finite_normals() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            // Not part of any triangle.
+            [5.0, 5.0, 5.0],
+        ];
+        for normal in compute_normals(&positions, &[0, 1, 2, 3, 4, 5]) {
+            assert!(normal.iter().all(|v| v.is_finite()), "{:?}", normal);
+            assert_close(normal, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn alpha_map_replaces_the_base_color_alpha() {
+        let base = image::RgbaImage::from_pixel(4, 2, image::Rgba([10, 20, 30, 255]));
+        // Smaller than the base, half clear and half opaque.
+        let alpha = image::GrayImage::from_fn(2, 1, |x, _| image::Luma([x as u8 * 255]));
+        let combined = with_alpha_map(
+            Some(image::DynamicImage::ImageRgba8(base)),
+            &image::DynamicImage::ImageLuma8(alpha),
+        )
+        .to_rgba8();
+
+        assert_eq!(combined.dimensions(), (4, 2));
+        assert_eq!(combined.get_pixel(0, 0).0, [10, 20, 30, 0]);
+        assert_eq!(combined.get_pixel(3, 1).0, [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn alpha_map_without_a_base_color_is_white() {
+        let alpha = image::GrayImage::from_pixel(3, 3, image::Luma([128]));
+        let combined = with_alpha_map(None, &image::DynamicImage::ImageLuma8(alpha)).to_rgba8();
+
+        assert_eq!(combined.dimensions(), (3, 3));
+        assert!(combined
+            .pixels()
+            .all(|pixel| pixel.0 == [255, 255, 255, 128]));
+    }
+}
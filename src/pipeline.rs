@@ -1,51 +1,303 @@
-pub fn create_render_pipeline(
-    device: &wgpu::Device,
-    layout: &wgpu::PipelineLayout,
-    color_format: wgpu::TextureFormat,
-    depth_format: Option<wgpu::TextureFormat>,
-    vertex_layouts: &[wgpu::VertexBufferLayout],
-    vert_shader: wgpu::ShaderModule,
-    frag_shader: wgpu::ShaderModule,
-) -> wgpu::RenderPipeline {
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(layout),
-        vertex: wgpu::VertexState {
-            module: &vert_shader,
-            entry_point: "main",
-            buffers: vertex_layouts,
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &frag_shader,
-            entry_point: "main",
-            targets: &[wgpu::ColorTargetState {
-                format: color_format,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            }],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            clamp_depth: false,
-            conservative: false,
-        },
-        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+//! Render pipelines described one state at a time.
+//!
+//! A [`PipelineBuilder`] starts from back-face culled triangles with no
+//! color targets, no depth and one sample per pixel, and only the states a
+//! pass changes from there need spelling out. Pipelines are only built
+//! through a [`PipelineCache`], which hands out the same pipeline for
+//! builders that describe the same states.
+
+use std::{collections::HashMap, sync::Arc};
+
+/// How a pipeline tests and writes depth.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthState {
+    pub format: wgpu::TextureFormat,
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+    pub bias: wgpu::DepthBiasState,
+}
+
+/// The layout and shader are shared with the builder, so that a cache can
+/// keep them alive for as long as it tells pipelines apart by them.
+#[derive(Clone)]
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    layout: Arc<wgpu::PipelineLayout>,
+    shader: Arc<wgpu::ShaderModule>,
+    vertex_entry: &'a str,
+    fragment_entry: Option<&'a str>,
+    vertex_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    targets: Vec<wgpu::ColorTargetState>,
+    primitive: wgpu::PrimitiveState,
+    depth: Option<DepthState>,
+    multisample: wgpu::MultisampleState,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// A pipeline running `shader`'s `main` vertex entry and nothing else.
+    pub fn new(
+        label: &'a str,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Arc<wgpu::ShaderModule>,
+    ) -> Self {
+        Self {
+            label,
+            layout: layout.clone(),
+            shader: shader.clone(),
+            vertex_entry: "main",
+            fragment_entry: None,
+            vertex_buffers: Vec::new(),
+            targets: Vec::new(),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth: None,
+            multisample: wgpu::MultisampleState::default(),
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn vertex_entry(mut self, entry: &'a str) -> Self {
+        self.vertex_entry = entry;
+        self
+    }
+
+    pub fn vertex_buffers(mut self, buffers: &[wgpu::VertexBufferLayout<'a>]) -> Self {
+        self.vertex_buffers = buffers.to_vec();
+        self
+    }
+
+    /// Adds a fragment stage running `entry`. Without one, the pipeline
+    /// only writes depth.
+    pub fn fragment(mut self, entry: &'a str) -> Self {
+        self.fragment_entry = Some(entry);
+        self
+    }
+
+    /// Adds a color target, written in full and blended with `blend` if
+    /// any.
+    pub fn color_target(
+        self,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        self.color_target_state(wgpu::ColorTargetState {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        })
+    }
+
+    /// Adds a color target with a write mask of its own.
+    pub fn color_target_state(mut self, target: wgpu::ColorTargetState) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.primitive.topology = topology;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.primitive.front_face = front_face;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    /// Needs [`wgpu::Features::NON_FILL_POLYGON_MODE`] for anything but
+    /// `Fill`.
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Needs [`wgpu::Features::DEPTH_CLAMPING`] to be set.
+    pub fn clamp_depth(mut self, clamp_depth: bool) -> Self {
+        self.primitive.clamp_depth = clamp_depth;
+        self
+    }
+
+    /// Tests fragments against a `format` depth target with `compare`, and
+    /// writes their depth if `write` is set.
+    pub fn depth(
+        mut self,
+        format: wgpu::TextureFormat,
+        write: bool,
+        compare: wgpu::CompareFunction,
+    ) -> Self {
+        self.depth = Some(DepthState {
             format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
+            write,
+            compare,
             bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-    });
-
-    return render_pipeline;
+        });
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If [`PipelineBuilder::depth`] wasn't set first.
+    pub fn depth_bias(mut self, bias: wgpu::DepthBiasState) -> Self {
+        self.depth
+            .as_mut()
+            .expect("depth bias needs a depth target")
+            .bias = bias;
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.multisample.count = sample_count;
+        self
+    }
+
+    pub fn alpha_to_coverage(mut self, enabled: bool) -> Self {
+        self.multisample.alpha_to_coverage_enabled = enabled;
+        self
+    }
+
+    fn build(&self, device: &wgpu::Device) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: self.vertex_entry,
+                buffers: &self.vertex_buffers,
+            },
+            fragment: self.fragment_entry.map(|entry_point| wgpu::FragmentState {
+                module: &self.shader,
+                entry_point,
+                targets: &self.targets,
+            }),
+            primitive: self.primitive,
+            depth_stencil: self.depth.map(|depth| wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: depth.write,
+                depth_compare: depth.compare,
+                stencil: wgpu::StencilState::default(),
+                bias: depth.bias,
+            }),
+            multisample: self.multisample,
+        })
+    }
+
+    fn key(&self) -> PipelineKey {
+        PipelineKey {
+            layout: Shared(self.layout.clone()),
+            shader: Shared(self.shader.clone()),
+            vertex_entry: self.vertex_entry.to_string(),
+            fragment_entry: self.fragment_entry.map(str::to_string),
+            vertex_buffers: self
+                .vertex_buffers
+                .iter()
+                .map(|buffer| {
+                    (
+                        buffer.array_stride,
+                        buffer.step_mode,
+                        buffer.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            targets: self.targets.clone(),
+            primitive: self.primitive,
+            // The bias' floats by their bits, as they are never NaN.
+            depth: self.depth.map(|depth| {
+                (
+                    depth.format,
+                    depth.write,
+                    depth.compare,
+                    depth.bias.constant,
+                    depth.bias.slope_scale.to_bits(),
+                    depth.bias.clamp.to_bits(),
+                )
+            }),
+            multisample: self.multisample,
+        }
+    }
+}
+
+/// A shared wgpu object, told apart from others by address. Holding on to
+/// it keeps the address from being reused.
+struct Shared<T>(Arc<T>);
+
+impl<T> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Shared<T> {}
+
+impl<T> std::hash::Hash for Shared<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state);
+    }
+}
+
+/// Everything a [`PipelineBuilder`] describes but its label.
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+    layout: Shared<wgpu::PipelineLayout>,
+    shader: Shared<wgpu::ShaderModule>,
+    vertex_entry: String,
+    fragment_entry: Option<String>,
+    vertex_buffers: Vec<(
+        wgpu::BufferAddress,
+        wgpu::VertexStepMode,
+        Vec<wgpu::VertexAttribute>,
+    )>,
+    targets: Vec<wgpu::ColorTargetState>,
+    primitive: wgpu::PrimitiveState,
+    depth: Option<(
+        wgpu::TextureFormat,
+        bool,
+        wgpu::CompareFunction,
+        i32,
+        u32,
+        u32,
+    )>,
+    multisample: wgpu::MultisampleState,
+}
+
+/// Pipelines built so far, shared between the builders that describe them.
+/// Keeps the layouts and shaders they use alive for as long as it lives.
+#[derive(Default)]
+pub struct PipelineCache {
+    pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+}
+
+impl PipelineCache {
+    /// The pipeline `builder` describes, built the first time it's asked
+    /// for under the label given then.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        builder: &PipelineBuilder<'_>,
+    ) -> Arc<wgpu::RenderPipeline> {
+        self.pipelines
+            .entry(builder.key())
+            .or_insert_with(|| Arc::new(builder.build(device)))
+            .clone()
+    }
+
+    /// How many different pipelines were built.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
 }
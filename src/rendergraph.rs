@@ -0,0 +1,378 @@
+//! The frame as a graph of passes.
+//!
+//! Every pass declares the textures and buffers it reads and writes, and
+//! records its commands in a closure. Resources are versioned: writing one
+//! gives a new [`ResourceId`], so a pass reading a version runs after the
+//! pass that wrote it, and the next write to the resource waits for every
+//! reader of the version before. The graph orders the passes by those
+//! dependencies, keeping the order they were added in where it's free to
+//! choose, and culls passes whose results nothing uses.
+//!
+//! Resources are either imported, owned by something outside the graph
+//! like the engine's HDR target, or transient: textures the size of the
+//! frame that only live between their first and last use in it. Transient
+//! textures whose lifetimes don't overlap share memory, and the textures
+//! backing them persist across frames in [`TransientTextures`], which
+//! recreates them when their description or the frame size changes.
+
+use crate::texture;
+
+/// One version of a resource in a [`RenderGraph`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ResourceId {
+    resource: usize,
+    version: u32,
+}
+
+/// A texture the graph allocates at the size of the frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+enum ResourceKind {
+    /// Lives outside the graph, so writing it is a result of the frame.
+    Imported,
+    Transient(TextureDesc),
+}
+
+struct Resource {
+    name: String,
+    kind: ResourceKind,
+    /// The latest version written.
+    version: u32,
+}
+
+/// Records a pass' commands with the frame's transient textures at hand.
+pub struct PassContext<'p> {
+    pub device: &'p wgpu::Device,
+    pub encoder: &'p mut wgpu::CommandEncoder,
+    textures: &'p [Option<&'p texture::Texture>],
+}
+
+impl<'p> PassContext<'p> {
+    /// The texture behind transient resource `id`.
+    ///
+    /// # Panics
+    ///
+    /// If `id` came from [`RenderGraph::import`]: imported resources live
+    /// outside the graph, and the pass borrows them itself.
+    pub fn texture(&self, id: ResourceId) -> &'p texture::Texture {
+        self.textures[id.resource].expect("only transient resources have graph textures")
+    }
+}
+
+type Execute<'a> = Box<dyn FnOnce(&mut PassContext<'_>) + 'a>;
+
+struct PassNode<'a> {
+    name: String,
+    reads: Vec<ResourceId>,
+    /// The versions the pass produces.
+    writes: Vec<ResourceId>,
+    execute: Option<Execute<'a>>,
+}
+
+/// Declares what a pass added with [`RenderGraph::add_pass`] reads and
+/// writes.
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: usize,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    pub fn read(&mut self, id: ResourceId) -> &mut Self {
+        self.graph.passes[self.pass].reads.push(id);
+        self
+    }
+
+    /// Writes over `id`, which has to be the latest version of its
+    /// resource, and returns the version the pass leaves behind.
+    pub fn write(&mut self, id: ResourceId) -> ResourceId {
+        let resource = &mut self.graph.resources[id.resource];
+        assert_eq!(
+            id.version, resource.version,
+            "{} was already written after this version",
+            resource.name
+        );
+        resource.version += 1;
+        let written = ResourceId {
+            resource: id.resource,
+            version: resource.version,
+        };
+        self.graph.passes[self.pass].writes.push(written);
+        written
+    }
+
+    /// Sets the closure recording the pass' commands.
+    pub fn execute(self, execute: impl FnOnce(&mut PassContext<'_>) + 'a) {
+        self.graph.passes[self.pass].execute = Some(Box::new(execute));
+    }
+}
+
+/// What [`RenderGraph::compile`] decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Indices of the passes to run, in order. Culled passes are left out.
+    pub order: Vec<usize>,
+    /// Which shared texture backs each resource, for the transient ones that
+    /// are used.
+    pub slots: Vec<Option<usize>>,
+    /// The description of each shared texture.
+    pub slot_descs: Vec<TextureDesc>,
+}
+
+pub struct RenderGraph<'a> {
+    width: u32,
+    height: u32,
+    resources: Vec<Resource>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// A graph for a frame of `width` by `height` pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceId {
+        self.resources.push(Resource {
+            name: name.to_string(),
+            kind,
+            version: 0,
+        });
+        ResourceId {
+            resource: self.resources.len() - 1,
+            version: 0,
+        }
+    }
+
+    /// A texture or buffer owned outside the graph, as it is when the frame
+    /// starts.
+    pub fn import(&mut self, name: &str) -> ResourceId {
+        self.add_resource(name, ResourceKind::Imported)
+    }
+
+    /// A texture that only lives for this frame, with undefined contents
+    /// until a pass writes it.
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Transient(desc))
+    }
+
+    /// Adds a pass, labelled `name` in debuggers.
+    pub fn add_pass(&mut self, name: impl Into<String>) -> PassBuilder<'_, 'a> {
+        self.passes.push(PassNode {
+            name: name.into(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            execute: None,
+        });
+        PassBuilder {
+            pass: self.passes.len() - 1,
+            graph: self,
+        }
+    }
+
+    fn producer(&self, id: ResourceId) -> Option<usize> {
+        self.passes
+            .iter()
+            .position(|pass| pass.writes.contains(&id))
+    }
+
+    /// Orders and culls the passes and assigns the transient textures to
+    /// shared ones.
+    ///
+    /// # Panics
+    ///
+    /// If the passes depend on each other in a cycle.
+    pub fn compile(&self) -> Schedule {
+        let count = self.passes.len();
+
+        // Passes writing imported resources are the frame's results; the
+        // rest only run when a pass that does reads what they write.
+        let mut live = vec![false; count];
+        let mut pending = (0..count)
+            .filter(|&pass| {
+                self.passes[pass]
+                    .writes
+                    .iter()
+                    .any(|id| matches!(self.resources[id.resource].kind, ResourceKind::Imported))
+            })
+            .collect::<Vec<_>>();
+        while let Some(pass) = pending.pop() {
+            if std::mem::replace(&mut live[pass], true) {
+                continue;
+            }
+            let node = &self.passes[pass];
+            // A write builds on the version before it.
+            let previous = node.writes.iter().map(|id| ResourceId {
+                version: id.version - 1,
+                ..*id
+            });
+            for id in node.reads.iter().copied().chain(previous) {
+                pending.extend(self.producer(id));
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); count];
+        for (pass, node) in self.passes.iter().enumerate() {
+            if !live[pass] {
+                continue;
+            }
+            for &id in &node.reads {
+                dependencies[pass].extend(self.producer(id));
+            }
+            for id in &node.writes {
+                let previous = ResourceId {
+                    version: id.version - 1,
+                    ..*id
+                };
+                dependencies[pass].extend(self.producer(previous));
+                // Readers of the old version have to be done with it.
+                dependencies[pass].extend((0..count).filter(|&reader| {
+                    reader != pass && live[reader] && self.passes[reader].reads.contains(&previous)
+                }));
+            }
+        }
+
+        // Kahn's algorithm, taking the earliest added pass that is ready.
+        let mut order = Vec::new();
+        let mut scheduled = vec![false; count];
+        while order.len() < live.iter().filter(|&&live| live).count() {
+            let next = (0..count)
+                .find(|&pass| {
+                    live[pass]
+                        && !scheduled[pass]
+                        && dependencies[pass]
+                            .iter()
+                            .all(|&dependency| scheduled[dependency])
+                })
+                .unwrap_or_else(|| {
+                    let stuck = (0..count)
+                        .find(|&pass| live[pass] && !scheduled[pass])
+                        .unwrap();
+                    panic!(
+                        "render graph passes depend on each other in a cycle through {}",
+                        self.passes[stuck].name
+                    )
+                });
+            scheduled[next] = true;
+            order.push(next);
+        }
+
+        // Lifetimes of the transient textures as positions in the order.
+        let mut lifetimes = vec![None::<(usize, usize)>; self.resources.len()];
+        for (position, &pass) in order.iter().enumerate() {
+            let node = &self.passes[pass];
+            for id in node.reads.iter().chain(&node.writes) {
+                let lifetime = &mut lifetimes[id.resource];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+            }
+        }
+
+        // First come, first served: a transient takes the first shared
+        // texture of its description that is free by the time it's needed.
+        let mut transients = (0..self.resources.len())
+            .filter_map(|resource| match self.resources[resource].kind {
+                ResourceKind::Transient(desc) => {
+                    lifetimes[resource].map(|lifetime| (resource, desc, lifetime))
+                }
+                ResourceKind::Imported => None,
+            })
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|&(_, _, (first, _))| first);
+        let mut slots = vec![None; self.resources.len()];
+        let mut slot_descs = Vec::new();
+        let mut slot_ends = Vec::<usize>::new();
+        for (resource, desc, (first, last)) in transients {
+            let free = (0..slot_descs.len())
+                .find(|&slot| slot_descs[slot] == desc && slot_ends[slot] < first);
+            let slot = free.unwrap_or_else(|| {
+                slot_descs.push(desc);
+                slot_ends.push(last);
+                slot_descs.len() - 1
+            });
+            slot_ends[slot] = last;
+            slots[resource] = Some(slot);
+        }
+
+        Schedule {
+            order,
+            slots,
+            slot_descs,
+        }
+    }
+
+    /// Records the scheduled passes into `encoder`, each in a debug group
+    /// of its name.
+    pub fn execute(
+        mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        transients: &mut TransientTextures,
+    ) {
+        let schedule = self.compile();
+        transients.prepare(device, self.width, self.height, &schedule.slot_descs);
+        let textures = schedule
+            .slots
+            .iter()
+            .map(|slot| slot.map(|slot| &transients.textures[slot].2))
+            .collect::<Vec<_>>();
+
+        for pass in schedule.order {
+            let node = &mut self.passes[pass];
+            let execute = node.execute.take().unwrap_or_else(|| {
+                panic!("render graph pass {} has nothing to execute", node.name)
+            });
+            encoder.push_debug_group(&node.name);
+            execute(&mut PassContext {
+                device,
+                encoder,
+                textures: &textures,
+            });
+            encoder.pop_debug_group();
+        }
+    }
+}
+
+/// The textures behind a render graph's transient resources, kept from one
+/// frame to the next.
+#[derive(Default)]
+pub struct TransientTextures {
+    textures: Vec<(TextureDesc, (u32, u32), texture::Texture)>,
+}
+
+impl TransientTextures {
+    /// Makes sure there is a texture for every one of `descs`, at `width` by
+    /// `height`, recreating those that no longer match.
+    fn prepare(&mut self, device: &wgpu::Device, width: u32, height: u32, descs: &[TextureDesc]) {
+        self.textures.truncate(descs.len());
+        for (slot, &desc) in descs.iter().enumerate() {
+            let current = self.textures.get(slot);
+            if current.map(|(d, size, _)| (*d, *size)) == Some((desc, (width, height))) {
+                continue;
+            }
+            let texture = texture::Texture::create_target(
+                device,
+                width,
+                height,
+                desc.format,
+                desc.sample_count,
+                &format!("render graph transient {}", slot),
+            );
+            let entry = (desc, (width, height), texture);
+            match self.textures.get_mut(slot) {
+                Some(current) => *current = entry,
+                None => self.textures.push(entry),
+            }
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::sync::Arc;
+
 pub struct Pass {
-    pub pipeline: wgpu::RenderPipeline
-}
\ No newline at end of file
+    /// Shared with passes whose pipelines have the same states, through a
+    /// [`PipelineCache`](crate::pipeline::PipelineCache).
+    pub pipeline: Arc<wgpu::RenderPipeline>,
+}
@@ -0,0 +1,128 @@
+//! Render targets that are rebuilt when the window changes size.
+//!
+//! Every target in [`RenderTargets`] declares its [`TargetSize`]: a scale of
+//! the window's size in physical pixels, or a size of its own. The engine's
+//! targets and the passes' own, like SSAO's half resolution ones or bloom's
+//! mips, all live in one registry and are referred to by [`TargetId`].
+//! Resizing the registry, on a window resize or a change of scale factor,
+//! recreates the window relative targets whose size changed, and leaves it
+//! to their users to rebuild the bind groups that refer to them.
+
+use crate::texture;
+
+/// How big a render target is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TargetSize {
+    /// `scale` times the window's size in physical pixels, rounded and at
+    /// least a pixel across.
+    Window { scale: f32 },
+    /// The same size whatever the window's.
+    Fixed { width: u32, height: u32 },
+}
+
+impl TargetSize {
+    /// The size of the window.
+    pub const FULL: Self = Self::Window { scale: 1.0 };
+
+    /// Width and height in pixels for a `width` by `height` window.
+    pub fn resolve(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Self::Window { scale } => {
+                let scaled = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+                (scaled(width), scaled(height))
+            }
+            Self::Fixed { width, height } => (width, height),
+        }
+    }
+}
+
+/// Everything needed to create one of the targets in [`RenderTargets`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TargetDesc {
+    pub label: &'static str,
+    pub size: TargetSize,
+    /// Depth formats get a comparison sampler like
+    /// [`Texture::create_depth_target`](texture::Texture::create_depth_target)'s.
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TargetId(usize);
+
+/// The render targets an engine keeps from frame to frame.
+pub struct RenderTargets {
+    width: u32,
+    height: u32,
+    targets: Vec<(TargetDesc, texture::Texture)>,
+}
+
+impl RenderTargets {
+    /// An empty registry for a `width` by `height` window.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            targets: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, device: &wgpu::Device, desc: TargetDesc) -> TargetId {
+        let texture = Self::create(device, &desc, self.width, self.height);
+        self.targets.push((desc, texture));
+        TargetId(self.targets.len() - 1)
+    }
+
+    pub fn get(&self, id: TargetId) -> &texture::Texture {
+        &self.targets[id.0].1
+    }
+
+    /// Width and height of the target in pixels.
+    pub fn size(&self, id: TargetId) -> (u32, u32) {
+        self.targets[id.0].0.size.resolve(self.width, self.height)
+    }
+
+    /// Recreates the targets whose size depends on the window's for a
+    /// `width` by `height` window. Returns whether any were, in which case
+    /// bind groups holding their views are stale.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) -> bool {
+        let (old_width, old_height) = (self.width, self.height);
+        self.width = width;
+        self.height = height;
+        let mut recreated = false;
+        for (desc, texture) in &mut self.targets {
+            if desc.size.resolve(old_width, old_height) != desc.size.resolve(width, height) {
+                *texture = Self::create(device, desc, width, height);
+                recreated = true;
+            }
+        }
+        recreated
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        desc: &TargetDesc,
+        window_width: u32,
+        window_height: u32,
+    ) -> texture::Texture {
+        let (width, height) = desc.size.resolve(window_width, window_height);
+        match desc.format.describe().sample_type {
+            wgpu::TextureSampleType::Depth => texture::Texture::create_depth_target(
+                device,
+                width,
+                height,
+                desc.format,
+                desc.sample_count,
+                desc.label,
+            ),
+            _ => texture::Texture::create_target(
+                device,
+                width,
+                height,
+                desc.format,
+                desc.sample_count,
+                desc.label,
+            ),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, Transform as _, Vector3};
+
+use crate::{
+    instance::InstanceRaw,
+    lighting::Light,
+    model::{GltfScene, Model},
+};
+
+/// Local translation, rotation and scale of a node relative to its parent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ModelId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LightId(usize);
+
+/// What a node places in the world at its transform.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeContent {
+    Empty,
+    Model(ModelId),
+    /// The light is moved to the node and points down its -Z axis.
+    Light(LightId),
+}
+
+pub struct Node {
+    pub name: String,
+    pub content: NodeContent,
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Matrix4<f32>,
+    /// World matrix as of the update before last, for motion vectors.
+    previous_world: Matrix4<f32>,
+    /// Set once the world matrix has been computed for the first time.
+    placed: bool,
+    dirty: bool,
+}
+
+impl Node {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn local_transform(&self) -> &Transform {
+        &self.local
+    }
+
+    /// World matrix as of the last [`Scene::update_world_transforms`].
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+
+    /// World matrix as of the [`Scene::update_world_transforms`] before the
+    /// last. Nodes that were just added haven't moved from anywhere, so this
+    /// matches [`Node::world_matrix`] for them.
+    pub fn previous_world_matrix(&self) -> Matrix4<f32> {
+        self.previous_world
+    }
+}
+
+/// A hierarchy of nodes together with the models and lights they reference.
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    models: Vec<Model>,
+    pub lights: Vec<Light>,
+    /// Model drawn at the position of the light.
+    pub light_gizmo: Option<ModelId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_model(&mut self, model: Model) -> ModelId {
+        self.models.push(model);
+        ModelId(self.models.len() - 1)
+    }
+
+    pub fn model(&self, id: ModelId) -> &Model {
+        &self.models[id.0]
+    }
+
+    pub fn models(&self) -> impl Iterator<Item = (ModelId, &Model)> {
+        self.models
+            .iter()
+            .enumerate()
+            .map(|(i, model)| (ModelId(i), model))
+    }
+
+    pub fn add_light(&mut self, light: Light) -> LightId {
+        self.lights.push(light);
+        LightId(self.lights.len() - 1)
+    }
+
+    pub fn light(&self, id: LightId) -> &Light {
+        &self.lights[id.0]
+    }
+
+    pub fn light_mut(&mut self, id: LightId) -> &mut Light {
+        &mut self.lights[id.0]
+    }
+
+    /// Like [`Scene::light_mut`], but `None` once the light was removed
+    /// from [`Scene::lights`].
+    pub fn get_light_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.lights.get_mut(id.0)
+    }
+
+    pub fn add_node(
+        &mut self,
+        parent: Option<NodeId>,
+        name: &str,
+        transform: Transform,
+        content: NodeContent,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: name.to_string(),
+            content,
+            local: transform,
+            parent,
+            children: Vec::new(),
+            world: Matrix4::one(),
+            previous_world: Matrix4::one(),
+            placed: false,
+            dirty: true,
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    /// Adds the models of a glTF file and recreates its node hierarchy under
+    /// `parent`. Returns the nodes created for the glTF scene's roots.
+    pub fn add_gltf(&mut self, gltf: GltfScene, parent: Option<NodeId>) -> Vec<NodeId> {
+        let models = gltf
+            .models
+            .into_iter()
+            .map(|model| self.add_model(model))
+            .collect::<Vec<_>>();
+
+        let mut roots = Vec::new();
+        let mut stack = gltf
+            .roots
+            .iter()
+            .rev()
+            .map(|root| (*root, parent, true))
+            .collect::<Vec<_>>();
+        while let Some((index, parent, is_root)) = stack.pop() {
+            let node = &gltf.nodes[index];
+            let content = match node.model {
+                Some(model) => NodeContent::Model(models[model]),
+                None => NodeContent::Empty,
+            };
+            let id = self.add_node(parent, &node.name, node.transform, content);
+            if is_root {
+                roots.push(id);
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|child| (*child, Some(id), false)),
+            );
+        }
+        roots
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (NodeId(i), node))
+    }
+
+    /// Moves `id` and its subtree under `parent`, or to the root when `None`.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            assert!(a != id, "a node cannot be parented to its own subtree");
+            ancestor = self.nodes[a.0].parent;
+        }
+
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|child| *child != id),
+            None => self.roots.retain(|root| *root != id),
+        }
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes[id.0].parent = parent;
+        self.nodes[id.0].dirty = true;
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+        let node = &mut self.nodes[id.0];
+        node.local = transform;
+        node.dirty = true;
+    }
+
+    /// Mutable access to a node's local transform. Marks the node dirty.
+    pub fn transform_mut(&mut self, id: NodeId) -> &mut Transform {
+        let node = &mut self.nodes[id.0];
+        node.dirty = true;
+        &mut node.local
+    }
+
+    /// Recomputes the world matrices of dirty nodes and their descendants, and
+    /// moves and turns lights to the nodes that reference them. Returns whether anything
+    /// changed. The matrices from before become the previous world matrices,
+    /// so call this once per frame.
+    pub fn update_world_transforms(&mut self) -> bool {
+        for node in &mut self.nodes {
+            node.previous_world = node.world;
+        }
+
+        let mut changed = false;
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|root| (*root, Matrix4::one(), false))
+            .collect::<Vec<_>>();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let recompute = node.dirty || parent_changed;
+            if recompute {
+                node.world = parent_world * node.local.to_matrix();
+                if !node.placed {
+                    node.previous_world = node.world;
+                    node.placed = true;
+                }
+                node.dirty = false;
+                changed = true;
+
+                // The light may have been dropped from `lights` since.
+                if let NodeContent::Light(light) = node.content {
+                    if let Some(light) = self.lights.get_mut(light.0) {
+                        let w = node.world.w;
+                        light.position = cgmath::Point3::new(w.x, w.y, w.z);
+                        light.direction =
+                            node.world.transform_vector(-Vector3::unit_z()).normalize();
+                    }
+                }
+            }
+
+            let node = &self.nodes[id.0];
+            stack.extend(
+                node.children
+                    .iter()
+                    .map(|child| (*child, node.world, recompute)),
+            );
+        }
+
+        changed
+    }
+
+    /// Instance data for every node that references `model`.
+    pub fn instances(&self, model: ModelId) -> Vec<InstanceRaw> {
+        self.nodes
+            .iter()
+            .filter(|node| node.content == NodeContent::Model(model))
+            .map(|node| InstanceRaw::from_matrices(node.world, node.previous_world))
+            .collect()
+    }
+}
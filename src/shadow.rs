@@ -0,0 +1,733 @@
+//! Shadow maps.
+//!
+//! The first directional or spot light that casts shadows renders into
+//! [`ShadowMaps`]. Directional lights get up to [`MAX_CASCADES`] orthographic
+//! cascades fit to slices of the camera frustum, spot lights a single layer
+//! with the projection from [`Light::to_raw`].
+//!
+//! Up to [`MAX_POINT_SHADOWS`] point lights render the distance to the light
+//! into the six faces of a cube in [`PointShadowMaps`].
+//!
+//! The lit pass filters both kinds of map as chosen by [`ShadowFilter`]. The
+//! variance filters additionally render depth moments into a color texture
+//! next to each depth map.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3};
+
+use crate::{
+    camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+    lighting::{Light, LightKind},
+    texture,
+};
+
+pub const MAX_CASCADES: usize = 4;
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+/// Largest [`ShadowFilter::Pcf`] kernel width.
+pub const MAX_PCF_KERNEL: u32 = 7;
+/// Size of the Poisson disk that [`ShadowFilter::PoissonPcf`] and
+/// [`ShadowFilter::Pcss`] take samples from.
+pub const MAX_POISSON_SAMPLES: u32 = 16;
+/// Largest [`ShadowFilter::Evsm`] exponent whose squared warp still fits in
+/// [`MOMENTS_FORMAT`].
+pub const EVSM_MAX_EXPONENT: f32 = 5.54;
+/// Format of the moment textures rendered for the variance filters.
+pub const MOMENTS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Near plane of the cube face projections.
+const POINT_SHADOW_NEAR: f32 = 0.05;
+
+/// Depth covered behind each cascade's bounding sphere, towards the light, so
+/// that casters outside the camera frustum still cast into it.
+const CASTER_MARGIN: f32 = 100.0;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowOptions {
+    /// Width and height of each cascade in texels.
+    pub resolution: u32,
+    /// Number of cascades for directional lights, from 1 to [`MAX_CASCADES`].
+    pub cascades: u32,
+    /// Distance from the camera after which directional shadows fade out.
+    pub max_distance: f32,
+    /// Mix between uniform (0.0) and logarithmic (1.0) split distances.
+    pub split_lambda: f32,
+    /// Fraction at the far end of each cascade over which it blends into the
+    /// next one.
+    pub blend: f32,
+    /// Width and height of each point light cube face in texels.
+    pub point_resolution: u32,
+    /// How the lit pass filters shadow lookups. Choosing between a variance
+    /// and a depth filter decides which maps are rendered, so it can only be
+    /// set when creating the engine.
+    pub filter: ShadowFilter,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascades: 4,
+            max_distance: 60.0,
+            split_lambda: 0.75,
+            blend: 0.1,
+            point_resolution: 512,
+            filter: ShadowFilter::default(),
+        }
+    }
+}
+
+/// How shadow lookups are filtered. Biases are in units of the shadow map's
+/// normalized depth and add to the fixed bias each map is rendered with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShadowFilter {
+    /// A single hardware comparison, filtered bilinearly.
+    Hardware { bias: f32 },
+    /// Percentage-closer filtering over a `kernel` by `kernel` grid of texels,
+    /// up to [`MAX_PCF_KERNEL`].
+    Pcf { kernel: u32, bias: f32 },
+    /// Percentage-closer filtering of up to [`MAX_POISSON_SAMPLES`] samples
+    /// from a Poisson disk `radius` texels wide, rotated per pixel to trade
+    /// banding for noise.
+    PoissonPcf {
+        samples: u32,
+        radius: f32,
+        bias: f32,
+    },
+    /// Percentage-closer soft shadows. A blocker search over `light_size`
+    /// texels finds the average occluder depth, which scales the Poisson
+    /// filter so shadows harden near contact points.
+    Pcss {
+        light_size: f32,
+        samples: u32,
+        bias: f32,
+    },
+    /// Variance shadow maps, averaging the moments over a `kernel` by
+    /// `kernel` grid. `min_variance` hides acne, and `light_bleed` cuts off
+    /// that fraction of the Chebyshev bound to hide light bleeding between
+    /// overlapping occluders.
+    Vsm {
+        kernel: u32,
+        min_variance: f32,
+        light_bleed: f32,
+    },
+    /// Exponential variance shadow maps, which warp depth with a positive and
+    /// a negative exponent (at most [`EVSM_MAX_EXPONENT`]) to reduce light
+    /// bleeding further than [`ShadowFilter::Vsm`].
+    Evsm {
+        kernel: u32,
+        exponents: [f32; 2],
+        min_variance: f32,
+        light_bleed: f32,
+    },
+}
+
+impl Default for ShadowFilter {
+    fn default() -> Self {
+        Self::Pcf {
+            kernel: 3,
+            bias: 0.0,
+        }
+    }
+}
+
+impl ShadowFilter {
+    /// Whether the filter reads moment textures rather than depth.
+    pub fn uses_moments(&self) -> bool {
+        matches!(self, Self::Vsm { .. } | Self::Evsm { .. })
+    }
+
+    /// Exponents depth is warped with, clamped so the moments stay finite.
+    /// Zero for filters that don't warp.
+    fn exponents(&self) -> [f32; 2] {
+        match *self {
+            Self::Evsm { exponents, .. } => [
+                exponents[0].clamp(0.0, EVSM_MAX_EXPONENT),
+                exponents[1].clamp(0.0, EVSM_MAX_EXPONENT),
+            ],
+            _ => [0.0; 2],
+        }
+    }
+
+    /// The moments a caster at normalized `depth` writes: depth and its
+    /// square, or for [`ShadowFilter::Evsm`] the positive and negative warps
+    /// and their squares.
+    pub fn moments(&self, depth: f32) -> [f32; 4] {
+        match self.exponents() {
+            [positive, negative] if positive > 0.0 || negative > 0.0 => {
+                let depth = depth * 2.0 - 1.0;
+                let positive = (positive * depth).exp();
+                let negative = -(-negative * depth).exp();
+                [positive, positive * positive, negative, negative * negative]
+            }
+            _ => [depth, depth * depth, 0.0, 0.0],
+        }
+    }
+
+    /// Parameters for the lit pass, clamped to what the shader supports.
+    pub fn uniform(&self) -> ShadowFilterUniform {
+        let mut uniform = ShadowFilterUniform {
+            exponents: self.exponents(),
+            ..bytemuck::Zeroable::zeroed()
+        };
+        match *self {
+            Self::Hardware { bias } => {
+                uniform.mode = 0;
+                uniform.samples = 1;
+                uniform.bias = bias;
+            }
+            Self::Pcf { kernel, bias } => {
+                uniform.mode = 1;
+                uniform.samples = kernel.clamp(1, MAX_PCF_KERNEL);
+                uniform.bias = bias;
+            }
+            Self::PoissonPcf {
+                samples,
+                radius,
+                bias,
+            } => {
+                uniform.mode = 2;
+                uniform.samples = samples.clamp(1, MAX_POISSON_SAMPLES);
+                uniform.radius = radius.max(0.0);
+                uniform.bias = bias;
+            }
+            Self::Pcss {
+                light_size,
+                samples,
+                bias,
+            } => {
+                uniform.mode = 3;
+                uniform.samples = samples.clamp(1, MAX_POISSON_SAMPLES);
+                uniform.radius = light_size.max(0.0);
+                uniform.bias = bias;
+            }
+            Self::Vsm {
+                kernel,
+                min_variance,
+                light_bleed,
+            }
+            | Self::Evsm {
+                kernel,
+                min_variance,
+                light_bleed,
+                ..
+            } => {
+                uniform.mode = 4;
+                uniform.samples = kernel.clamp(1, MAX_PCF_KERNEL);
+                uniform.min_variance = min_variance.max(0.0);
+                uniform.light_bleed = light_bleed.clamp(0.0, 0.99);
+            }
+        }
+        uniform
+    }
+}
+
+/// [`ShadowFilter`] as read by the lit pass.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowFilterUniform {
+    /// Hardware, PCF, Poisson PCF, PCSS or variance, from 0 to 4.
+    pub mode: u32,
+    /// Kernel width or number of Poisson samples.
+    pub samples: u32,
+    pub bias: f32,
+    /// Poisson disk radius or light size, in texels.
+    pub radius: f32,
+    /// Warp exponents, both zero for plain variance shadow maps.
+    pub exponents: [f32; 2],
+    pub min_variance: f32,
+    pub light_bleed: f32,
+}
+
+/// Shadow parameters read by the lit pass.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    pub cascades: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// View depth at which each cascade ends.
+    pub splits: [f32; MAX_CASCADES],
+    pub cascade_count: u32,
+    pub blend: f32,
+    /// Index of the light that casts the cascades, or `u32::MAX` if none does.
+    pub caster: u32,
+    _padding: u32,
+    pub filter: ShadowFilterUniform,
+}
+
+impl ShadowUniform {
+    /// A single shadow map rendered with `view_proj`, covering any depth.
+    pub fn single(view_proj: [[f32; 4]; 4]) -> Self {
+        let mut uniform: Self = bytemuck::Zeroable::zeroed();
+        uniform.cascades[0] = view_proj;
+        uniform.splits[0] = f32::MAX;
+        uniform.cascade_count = 1;
+        uniform
+    }
+}
+
+/// Far distance of each cascade, using the practical split scheme that mixes
+/// uniform and logarithmic splits of `near..far`.
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let log = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Light view projection covering the slice `near..far` of the camera frustum.
+///
+/// The slice is bounded by a sphere, so the projection's size does not change
+/// as the camera rotates, and its center is snapped to whole shadow map texels
+/// in light space, so static geometry does not shimmer as the camera moves.
+pub fn cascade_view_proj(
+    camera: &Camera,
+    near: f32,
+    far: f32,
+    direction: Vector3<f32>,
+    resolution: u32,
+) -> Matrix4<f32> {
+    let corners = camera.frustum_corners(near, far);
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+    // Rounding keeps float noise from changing the size frame to frame.
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let view = Matrix4::look_at_rh(Point3::origin(), Point3::origin() + direction, up);
+
+    let texel = 2.0 * radius / resolution as f32;
+    let center = view.transform_point(center);
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+
+    // The view looks down -z, so distances in front of the light are -z.
+    let projection = cgmath::ortho(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -center.z - radius - CASTER_MARGIN,
+        -center.z + radius,
+    );
+    OPENGL_TO_WGPU_MATRIX * projection * view
+}
+
+/// Computes the shadow matrices for `light` seen from `camera`.
+pub fn shadow_uniform(camera: &Camera, light: &Light, options: &ShadowOptions) -> ShadowUniform {
+    if light.kind != LightKind::Directional {
+        return ShadowUniform::single(light.to_raw().proj);
+    }
+
+    let mut uniform: ShadowUniform = bytemuck::Zeroable::zeroed();
+    let count = options.cascades.clamp(1, MAX_CASCADES as u32);
+    let far = options.max_distance.min(camera.zfar);
+    let splits = cascade_splits(camera.znear, far, count, options.split_lambda);
+    let mut near = camera.znear;
+    for (i, split) in splits.iter().enumerate() {
+        uniform.cascades[i] =
+            cascade_view_proj(camera, near, *split, light.direction, options.resolution).into();
+        uniform.splits[i] = *split;
+        near = *split;
+    }
+    uniform.cascade_count = count;
+    uniform.blend = options.blend;
+    uniform
+}
+
+/// Index of the light that renders into [`ShadowMaps`]: the first
+/// directional or spot light that casts shadows.
+pub fn shadow_caster(lights: &[Light]) -> Option<usize> {
+    lights
+        .iter()
+        .position(|light| light.cast_shadows && light.kind != LightKind::Point)
+}
+
+/// Indices of the point lights that get a cube in [`PointShadowMaps`], in
+/// order of their cube.
+pub fn point_shadow_casters(lights: &[Light]) -> Vec<usize> {
+    lights
+        .iter()
+        .enumerate()
+        .filter(|(_, light)| light.cast_shadows && light.kind == LightKind::Point)
+        .map(|(i, _)| i)
+        .take(MAX_POINT_SHADOWS)
+        .collect()
+}
+
+/// View projections of the six cube faces around `position`, in the order
+/// +X, -X, +Y, -Y, +Z, -Z.
+///
+/// Cube maps are addressed with the texture origin at the top, while the
+/// projections put +Y up, so each face is flipped vertically. That mirrors
+/// the geometry, so render with clockwise front faces.
+pub fn cube_face_view_projs(position: Point3<f32>, range: f32) -> [Matrix4<f32>; 6] {
+    let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, POINT_SHADOW_NEAR, range);
+    let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+    let face = |direction: Vector3<f32>, up: Vector3<f32>| {
+        let view = Matrix4::look_at_rh(position, position + direction, up);
+        OPENGL_TO_WGPU_MATRIX * flip * projection * view
+    };
+    [
+        face(Vector3::unit_x(), -Vector3::unit_y()),
+        face(-Vector3::unit_x(), -Vector3::unit_y()),
+        face(Vector3::unit_y(), Vector3::unit_z()),
+        face(-Vector3::unit_y(), -Vector3::unit_z()),
+        face(Vector3::unit_z(), -Vector3::unit_y()),
+        face(-Vector3::unit_z(), -Vector3::unit_y()),
+    ]
+}
+
+/// What the shadow pass needs to render one cascade.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    view_proj: [[f32; 4]; 4],
+    /// xy: exponents for the moments, see [`ShadowFilter::moments`]
+    warp: [f32; 4],
+}
+
+/// Creates a moment texture matching a depth map of `layers` layers, and a
+/// view of each layer. Filters that don't use moments get a 1x1 placeholder.
+fn create_moments(
+    device: &wgpu::Device,
+    filter: &ShadowFilter,
+    resolution: u32,
+    layers: u32,
+    dimension: wgpu::TextureViewDimension,
+    label: &str,
+) -> (texture::Texture, Vec<wgpu::TextureView>) {
+    let resolution = if filter.uses_moments() { resolution } else { 1 };
+    let moments = texture::Texture::create_color_array(
+        device,
+        resolution,
+        layers,
+        MOMENTS_FORMAT,
+        dimension,
+        label,
+    );
+    let views = (0..layers)
+        .map(|layer| layer_view(&moments.texture, layer, "shadow moments view"))
+        .collect();
+    (moments, views)
+}
+
+/// A 2D view of a single `layer` of `texture`.
+fn layer_view(texture: &wgpu::Texture, layer: u32, label: &str) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(label),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_array_layer: layer,
+        array_layer_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+/// Depth texture array holding one layer per cascade, with the buffers the
+/// shadow and lit passes read the cascade matrices from.
+pub struct ShadowMaps {
+    pub options: ShadowOptions,
+    pub texture: texture::Texture,
+    /// A view of each layer, to render the cascades into.
+    pub layer_views: Vec<wgpu::TextureView>,
+    /// Moments of each layer, for the variance filters.
+    pub moments: texture::Texture,
+    pub moment_views: Vec<wgpu::TextureView>,
+    /// Reads depth without comparing, for the PCSS blocker search.
+    pub depth_sampler: wgpu::Sampler,
+    /// Holds a [`ShadowUniform`].
+    pub uniform_buffer: wgpu::Buffer,
+    /// The view projection of each cascade, bound while rendering into it.
+    pub cascade_bind_groups: Vec<wgpu::BindGroup>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_count: u32,
+}
+
+impl ShadowMaps {
+    /// Layout of the per-cascade view projection used by the shadow pass.
+    pub fn cascade_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("cascade_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        cascade_layout: &wgpu::BindGroupLayout,
+        options: ShadowOptions,
+    ) -> Self {
+        let options = ShadowOptions {
+            resolution: options
+                .resolution
+                .clamp(1, device.limits().max_texture_dimension_2d),
+            cascades: options.cascades.clamp(1, MAX_CASCADES as u32),
+            ..options
+        };
+
+        let texture = texture::Texture::create_depth_array(
+            device,
+            options.resolution,
+            MAX_CASCADES as u32,
+            wgpu::TextureViewDimension::D2Array,
+            "shadow_texture",
+        );
+        let layer_views = (0..MAX_CASCADES as u32)
+            .map(|layer| layer_view(&texture.texture, layer, "shadow cascade view"))
+            .collect();
+        let (moments, moment_views) = create_moments(
+            device,
+            &options.filter,
+            options.resolution,
+            MAX_CASCADES as u32,
+            wgpu::TextureViewDimension::D2Array,
+            "shadow_moments_texture",
+        );
+        let depth_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow depth sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cascade_buffers = (0..MAX_CASCADES)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Cascade Buffer"),
+                    size: std::mem::size_of::<CascadeUniform>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: cascade_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("cascade_bind_group"),
+                })
+            })
+            .collect();
+
+        Self {
+            options,
+            texture,
+            layer_views,
+            moments,
+            moment_views,
+            depth_sampler,
+            uniform_buffer,
+            cascade_bind_groups,
+            cascade_buffers,
+            cascade_count: 0,
+        }
+    }
+
+    /// Number of layers rendered this frame.
+    pub fn cascade_count(&self) -> u32 {
+        self.cascade_count
+    }
+
+    /// Fits the cascades to `camera` for the [`shadow_caster`] of `lights`.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, lights: &[Light]) {
+        let filter = self.options.filter;
+        let uniform = match shadow_caster(lights) {
+            Some(caster) => ShadowUniform {
+                caster: caster as u32,
+                filter: filter.uniform(),
+                ..shadow_uniform(camera, &lights[caster], &self.options)
+            },
+            None => ShadowUniform {
+                caster: u32::MAX,
+                filter: filter.uniform(),
+                ..bytemuck::Zeroable::zeroed()
+            },
+        };
+        self.cascade_count = uniform.cascade_count;
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        let [positive, negative] = filter.exponents();
+        for (buffer, view_proj) in self
+            .cascade_buffers
+            .iter()
+            .zip(&uniform.cascades)
+            .take(self.cascade_count as usize)
+        {
+            let cascade = CascadeUniform {
+                view_proj: *view_proj,
+                warp: [positive, negative, 0.0, 0.0],
+            };
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[cascade]));
+        }
+    }
+}
+
+/// What the point shadow pass needs to render one cube face.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointShadowFace {
+    view_proj: [[f32; 4]; 4],
+    /// xyz: light position, w: range
+    light: [f32; 4],
+    /// xy: exponents for the moments, see [`ShadowFilter::moments`]
+    warp: [f32; 4],
+}
+
+/// Cube array with one cube per shadow-casting point light. Each texel holds
+/// the distance to the light divided by its range.
+pub struct PointShadowMaps {
+    pub texture: texture::Texture,
+    /// A view of each face of each cube, to render into.
+    pub face_views: Vec<wgpu::TextureView>,
+    /// Moments of each face, for the variance filters.
+    pub moments: texture::Texture,
+    pub moment_views: Vec<wgpu::TextureView>,
+    /// Bound while rendering the face with the same index.
+    pub face_bind_groups: Vec<wgpu::BindGroup>,
+    face_buffers: Vec<wgpu::Buffer>,
+    caster_count: u32,
+    filter: ShadowFilter,
+}
+
+impl PointShadowMaps {
+    pub fn face_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("point_shadow_face_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        face_layout: &wgpu::BindGroupLayout,
+        options: ShadowOptions,
+    ) -> Self {
+        let resolution = options
+            .point_resolution
+            .clamp(1, device.limits().max_texture_dimension_2d);
+        let layers = MAX_POINT_SHADOWS as u32 * 6;
+        let texture = texture::Texture::create_depth_array(
+            device,
+            resolution,
+            layers,
+            wgpu::TextureViewDimension::CubeArray,
+            "point_shadow_texture",
+        );
+        let face_views = (0..layers)
+            .map(|layer| layer_view(&texture.texture, layer, "point shadow face view"))
+            .collect();
+        let (moments, moment_views) = create_moments(
+            device,
+            &options.filter,
+            resolution,
+            layers,
+            wgpu::TextureViewDimension::CubeArray,
+            "point_shadow_moments_texture",
+        );
+
+        let face_buffers = (0..layers)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Point Shadow Face Buffer"),
+                    size: std::mem::size_of::<PointShadowFace>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let face_bind_groups = face_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: face_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("point_shadow_face_bind_group"),
+                })
+            })
+            .collect();
+
+        Self {
+            texture,
+            face_views,
+            moments,
+            moment_views,
+            face_bind_groups,
+            face_buffers,
+            caster_count: 0,
+            filter: options.filter,
+        }
+    }
+
+    /// Number of cubes rendered this frame.
+    pub fn caster_count(&self) -> u32 {
+        self.caster_count
+    }
+
+    /// Places a cube around each of the [`point_shadow_casters`].
+    pub fn update(&mut self, queue: &wgpu::Queue, lights: &[Light]) {
+        let casters = point_shadow_casters(lights);
+        self.caster_count = casters.len() as u32;
+        let [positive, negative] = self.filter.exponents();
+
+        for (cube, light) in casters.iter().map(|i| &lights[*i]).enumerate() {
+            let position = light.position;
+            let faces = cube_face_view_projs(position, light.range);
+            for (face, view_proj) in faces.iter().enumerate() {
+                let data = PointShadowFace {
+                    view_proj: (*view_proj).into(),
+                    light: [position.x, position.y, position.z, light.range],
+                    warp: [positive, negative, 0.0, 0.0],
+                };
+                queue.write_buffer(
+                    &self.face_buffers[cube * 6 + face],
+                    0,
+                    bytemuck::cast_slice(&[data]),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//! The sky behind the scene.
+//!
+//! A fullscreen pass drawn after the opaque geometry at the far plane fills
+//! the pixels nothing else covered, either with the environment's cube map or
+//! with a physically based atmosphere: single Rayleigh and Mie scattering of
+//! sunlight, marched through a spherical shell of air around the planet. The
+//! atmosphere's sun can steer a directional light, so the scene is lit from
+//! where the sun is drawn, by the light that makes it through the air.
+
+use cgmath::InnerSpace;
+
+use std::sync::Arc;
+
+use crate::{
+    ibl::Environment,
+    pipeline::{PipelineBuilder, PipelineCache},
+    scene::{LightId, Scene},
+    texture,
+};
+
+/// What fills the pixels the scene's geometry doesn't cover.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sky {
+    /// The cube map of the environment loaded with
+    /// [`Engine::load_environment`](crate::Engine::load_environment), as
+    /// bright as it lights the scene. Black until one is loaded.
+    Environment,
+    Atmosphere(AtmosphereOptions),
+}
+
+/// A planet's atmosphere. Distances are in meters, independent of the
+/// scene's units.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtmosphereOptions {
+    /// Direction towards the sun.
+    pub sun_direction: cgmath::Vector3<f32>,
+    /// Radiance of the sun before it enters the atmosphere.
+    pub sun_intensity: f32,
+    /// Directional light whose direction follows the sun and whose color is
+    /// tinted by the sunlight left after crossing the atmosphere. Left alone
+    /// once it's removed from the scene.
+    pub sun_light: Option<LightId>,
+    /// Rayleigh scattering coefficients at sea level, per meter.
+    pub rayleigh_scattering: [f32; 3],
+    /// Height over which the density of Rayleigh scatterers falls by `e`.
+    pub rayleigh_scale_height: f32,
+    /// Mie scattering coefficient at sea level, per meter.
+    pub mie_scattering: f32,
+    /// Height over which the density of Mie scatterers falls by `e`.
+    pub mie_scale_height: f32,
+    /// How much Mie scattering favors the forward direction, from -1 to 1.
+    pub mie_anisotropy: f32,
+    pub planet_radius: f32,
+    pub atmosphere_radius: f32,
+    /// Height of the camera above the ground.
+    pub altitude: f32,
+}
+
+impl Default for AtmosphereOptions {
+    /// Earth's atmosphere at midmorning.
+    fn default() -> Self {
+        Self {
+            sun_direction: cgmath::Vector3::new(0.3, 0.5, 0.8),
+            sun_intensity: 20.0,
+            sun_light: None,
+            rayleigh_scattering: [5.8e-6, 13.5e-6, 33.1e-6],
+            rayleigh_scale_height: 8000.0,
+            mie_scattering: 21e-6,
+            mie_scale_height: 1200.0,
+            mie_anisotropy: 0.76,
+            planet_radius: 6_371_000.0,
+            atmosphere_radius: 6_471_000.0,
+            altitude: 1.0,
+        }
+    }
+}
+
+/// Steps the sun's transmittance is integrated over. Matches
+/// `LIGHT_STEPS` in `sky.wgsl`.
+const LIGHT_STEPS: u32 = 8;
+/// Matches `MIE_EXTINCTION` in `sky.wgsl`.
+const MIE_EXTINCTION: f32 = 1.1;
+
+impl AtmosphereOptions {
+    /// Fraction of each of red, green and blue sunlight that reaches the
+    /// camera, zero once the sun is below the horizon. Integrated the way
+    /// `sky.wgsl` does.
+    pub fn sun_transmittance(&self) -> [f32; 3] {
+        let sun = self.sun_direction.normalize();
+        let origin = cgmath::Vector3::new(0.0, self.planet_radius + self.altitude, 0.0);
+        let b = origin.dot(sun);
+        let distance = origin.magnitude();
+        let c = (distance - self.atmosphere_radius) * (distance + self.atmosphere_radius);
+        let exit = -b + (b * b - c).max(0.0).sqrt();
+
+        let step = exit / LIGHT_STEPS as f32;
+        let mut rayleigh_depth = 0.0;
+        let mut mie_depth = 0.0;
+        for i in 0..LIGHT_STEPS {
+            let height = (origin + sun * (i as f32 + 0.5) * step).magnitude() - self.planet_radius;
+            if height < 0.0 {
+                return [0.0; 3];
+            }
+            rayleigh_depth += (-height / self.rayleigh_scale_height).exp() * step;
+            mie_depth += (-height / self.mie_scale_height).exp() * step;
+        }
+
+        let mie = self.mie_scattering * MIE_EXTINCTION * mie_depth;
+        self.rayleigh_scattering
+            .map(|rayleigh| (-(rayleigh * rayleigh_depth + mie)).exp())
+    }
+}
+
+/// The color the application gave the sun's light, kept apart from the
+/// tinted one the atmosphere leaves it with so the tint doesn't compound.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct SunTint {
+    light: LightId,
+    color: [f32; 3],
+    tinted: [f32; 3],
+}
+
+impl SunTint {
+    /// Points `atmosphere`'s sun light, if it still exists, at the sun and
+    /// multiplies its own color by the sun's transmittance. A light the sun
+    /// no longer steers gets its own color back, unless it was changed since.
+    pub(crate) fn apply(
+        previous: Option<Self>,
+        atmosphere: Option<&AtmosphereOptions>,
+        scene: &mut Scene,
+    ) -> Option<Self> {
+        let sun_light = atmosphere
+            .and_then(|atmosphere| atmosphere.sun_light)
+            .filter(|&id| scene.get_light_mut(id).is_some());
+        if let Some(previous) = previous.filter(|previous| Some(previous.light) != sun_light) {
+            if let Some(light) = scene.get_light_mut(previous.light) {
+                if light.color == previous.tinted {
+                    light.color = previous.color;
+                }
+            }
+        }
+
+        let (atmosphere, id) = atmosphere.zip(sun_light)?;
+        let light = scene.get_light_mut(id)?;
+        // Whatever else is in the light's color was put there since.
+        let color = match previous {
+            Some(previous) if previous.light == id && light.color == previous.tinted => {
+                previous.color
+            }
+            _ => light.color,
+        };
+        let transmittance = atmosphere.sun_transmittance();
+        let tinted = [0, 1, 2].map(|i| color[i] * transmittance[i]);
+        light.direction = -atmosphere.sun_direction;
+        light.color = tinted;
+        Some(Self {
+            light: id,
+            color,
+            tinted,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AtmosphereUniform {
+    sun: [f32; 4],
+    rayleigh: [f32; 4],
+    mie_scattering: f32,
+    mie_scale_height: f32,
+    mie_anisotropy: f32,
+    planet_radius: f32,
+    atmosphere_radius: f32,
+    altitude: f32,
+    _padding: [f32; 2],
+}
+
+impl AtmosphereUniform {
+    fn new(options: &AtmosphereOptions) -> Self {
+        let sun = options.sun_direction.normalize();
+        let [r, g, b] = options.rayleigh_scattering;
+        Self {
+            sun: [sun.x, sun.y, sun.z, options.sun_intensity],
+            rayleigh: [r, g, b, options.rayleigh_scale_height],
+            mie_scattering: options.mie_scattering,
+            mie_scale_height: options.mie_scale_height,
+            mie_anisotropy: options.mie_anisotropy,
+            planet_radius: options.planet_radius,
+            atmosphere_radius: options.atmosphere_radius,
+            altitude: options.altitude,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+pub struct SkyPass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    environment_pipeline: Arc<wgpu::RenderPipeline>,
+    atmosphere_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl SkyPass {
+    /// Draws into the camera pass' color and velocity targets with
+    /// `sample_count` samples. `environment_buffer` is the image based
+    /// lighting uniform, whose intensity the environment sky shares.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        camera_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+        environment: &Environment,
+        environment_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Layout"),
+            entries: &[
+                uniform_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                uniform_entry(3),
+            ],
+        });
+
+        // Written by `update` before the atmosphere is drawn.
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sky.uniform_buffer"),
+            size: std::mem::size_of::<AtmosphereUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &uniform_buffer,
+            environment,
+            environment_buffer,
+        );
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("sky.pipeline_layout"),
+                bind_group_layouts: &[&layout, camera_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/sky.wgsl")));
+        let mut pipeline = |entry_point| {
+            pipelines.get(
+                device,
+                &PipelineBuilder::new(entry_point, &pipeline_layout, &shader)
+                    .fragment(entry_point)
+                    .color_target(texture::Texture::HDR_FORMAT, None)
+                    .color_target(texture::Texture::VELOCITY_FORMAT, None)
+                    .cull_mode(None)
+                    // Only where the depth buffer is still clear.
+                    .depth(
+                        texture::Texture::DEPTH_FORMAT,
+                        false,
+                        wgpu::CompareFunction::LessEqual,
+                    )
+                    .sample_count(sample_count),
+            )
+        };
+        let environment_pipeline = pipeline("environment_sky");
+        let atmosphere_pipeline = pipeline("atmosphere_sky");
+
+        Self {
+            layout,
+            bind_group,
+            uniform_buffer,
+            environment_pipeline,
+            atmosphere_pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        environment: &Environment,
+        environment_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sky.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&environment.cubemap.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: environment_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Shows a new environment, e.g. after one was loaded.
+    pub fn set_environment(
+        &mut self,
+        device: &wgpu::Device,
+        environment: &Environment,
+        environment_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.uniform_buffer,
+            environment,
+            environment_buffer,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, options: &AtmosphereOptions) {
+        let uniform = AtmosphereUniform::new(options);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws `sky` into a camera pass whose depth buffer holds the opaque
+    /// geometry.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        sky: &Sky,
+        camera: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(match sky {
+            Sky::Environment => &self.environment_pipeline,
+            Sky::Atmosphere(_) => &self.atmosphere_pipeline,
+        });
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::Light;
+
+    fn tinted(color: [f32; 3], atmosphere: &AtmosphereOptions) -> [f32; 3] {
+        let transmittance = atmosphere.sun_transmittance();
+        [0, 1, 2].map(|i| color[i] * transmittance[i])
+    }
+
+    #[test]
+    fn sun_tints_the_lights_own_color_once() {
+        let mut scene = Scene::new();
+        let sun = scene.add_light(Light {
+            color: [1.0, 0.5, 0.25],
+            ..Light::directional(-cgmath::Vector3::unit_y())
+        });
+        let atmosphere = AtmosphereOptions {
+            sun_light: Some(sun),
+            ..Default::default()
+        };
+
+        let mut tint = None;
+        for _ in 0..3 {
+            tint = SunTint::apply(tint, Some(&atmosphere), &mut scene);
+        }
+        assert_eq!(
+            scene.light(sun).color,
+            tinted([1.0, 0.5, 0.25], &atmosphere)
+        );
+        assert_eq!(scene.light(sun).direction, -atmosphere.sun_direction);
+
+        // A color set in between is tinted in turn.
+        scene.light_mut(sun).color = [0.0, 1.0, 0.0];
+        SunTint::apply(tint, Some(&atmosphere), &mut scene);
+        assert_eq!(scene.light(sun).color, tinted([0.0, 1.0, 0.0], &atmosphere));
+    }
+
+    #[test]
+    fn light_gets_its_color_back_without_the_sky() {
+        let mut scene = Scene::new();
+        let sun = scene.add_light(Light::directional(-cgmath::Vector3::unit_y()));
+        let atmosphere = AtmosphereOptions {
+            sun_light: Some(sun),
+            ..Default::default()
+        };
+
+        let tint = SunTint::apply(None, Some(&atmosphere), &mut scene);
+        assert_eq!(SunTint::apply(tint, None, &mut scene), None);
+        assert_eq!(scene.light(sun).color, [1.0; 3]);
+    }
+
+    #[test]
+    fn removed_sun_light_is_skipped() {
+        let mut scene = Scene::new();
+        let sun = scene.add_light(Light::directional(-cgmath::Vector3::unit_y()));
+        let atmosphere = AtmosphereOptions {
+            sun_light: Some(sun),
+            ..Default::default()
+        };
+
+        let tint = SunTint::apply(None, Some(&atmosphere), &mut scene);
+        scene.lights.clear();
+        assert_eq!(SunTint::apply(tint, Some(&atmosphere), &mut scene), None);
+    }
+}
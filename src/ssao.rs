@@ -0,0 +1,470 @@
+//! Screen space ambient occlusion.
+//!
+//! With SSAO on, the opaque geometry is drawn into the camera pass' depth
+//! buffer ahead of the camera pass itself. The depth is linearized, and for
+//! every pixel a hemisphere of samples around its reconstructed position and
+//! normal is tested against it: the more samples end up behind other
+//! geometry, the less ambient light reaches the pixel. The noisy result is
+//! blurred twice, across and down, keeping to surfaces at the same depth, and
+//! the camera pass scales its ambient term by it. All but the last blur run at
+//! half the window's resolution; the last one writes the full size result.
+
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use crate::{
+    camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+    pipeline::{PipelineBuilder, PipelineCache},
+    rendertarget::{RenderTargets, TargetDesc, TargetId, TargetSize},
+};
+
+/// Most samples in the hemisphere kernel. Matches the length of
+/// `Ssao::kernel` in `ssao.wgsl`.
+pub const MAX_SSAO_SAMPLES: u32 = 64;
+/// Width and height of the tile of random rotations.
+pub const NOISE_SIZE: u32 = 4;
+/// Linear view depth, 0 where nothing was drawn.
+pub const VIEW_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+/// Fraction of ambient light that reaches each pixel.
+pub const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+/// Size of the targets occlusion is computed and first blurred in.
+const RESOLUTION: TargetSize = TargetSize::Window { scale: 0.5 };
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SsaoOptions {
+    /// Radius of the sampled hemisphere, in world units.
+    pub radius: f32,
+    /// Exponent applied to the unoccluded fraction. Higher darkens more.
+    pub intensity: f32,
+    /// Samples per pixel, up to [`MAX_SSAO_SAMPLES`].
+    pub sample_count: u32,
+    /// Depth a sample has to be behind the depth buffer to count, against
+    /// surfaces occluding themselves.
+    pub bias: f32,
+}
+
+impl Default for SsaoOptions {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            intensity: 1.5,
+            sample_count: 16,
+            bias: 0.025,
+        }
+    }
+}
+
+/// Low discrepancy value in [0, 1) for `index`, the digits of `index` in
+/// `base` mirrored around the decimal point.
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0 / base as f32;
+    while index > 0 {
+        result += (index % base) as f32 * fraction;
+        index /= base;
+        fraction /= base as f32;
+    }
+    result
+}
+
+/// Offsets within the unit hemisphere around +z, spread evenly over it and
+/// clustered towards its center, so nearby geometry weighs most. The first
+/// `sample_count` of them reach from near the center out to the radius; the
+/// rest are left zero.
+pub fn hemisphere_kernel(sample_count: u32) -> [[f32; 4]; MAX_SSAO_SAMPLES as usize] {
+    let sample_count = sample_count.clamp(1, MAX_SSAO_SAMPLES);
+    let mut kernel = [[0.0; 4]; MAX_SSAO_SAMPLES as usize];
+    for (i, sample) in kernel.iter_mut().take(sample_count as usize).enumerate() {
+        let index = i as u32 + 1;
+        let phi = 2.0 * std::f32::consts::PI * radical_inverse(index, 2);
+        // Uniform over the hemisphere, but off the tangent plane, where
+        // samples would hit the surface itself.
+        let cos_theta = 0.1 + 0.9 * radical_inverse(index, 3);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let t = (i + 1) as f32 / sample_count as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        *sample = [
+            phi.cos() * sin_theta * scale,
+            phi.sin() * sin_theta * scale,
+            cos_theta * scale,
+            0.0,
+        ];
+    }
+    kernel
+}
+
+/// Unit vectors in the xy plane that rotate the kernel around the normal,
+/// one per texel of the noise tile.
+pub fn noise_rotations() -> [[f32; 4]; (NOISE_SIZE * NOISE_SIZE) as usize] {
+    let mut noise = [[0.0; 4]; (NOISE_SIZE * NOISE_SIZE) as usize];
+    for (i, rotation) in noise.iter_mut().enumerate() {
+        let angle = 2.0 * std::f32::consts::PI * radical_inverse(i as u32 + 1, 5);
+        *rotation = [angle.cos(), angle.sin(), 0.0, 0.0];
+    }
+    noise
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    kernel: [[f32; 4]; MAX_SSAO_SAMPLES as usize],
+    radius: f32,
+    intensity: f32,
+    bias: f32,
+    sample_count: u32,
+}
+
+impl SsaoUniform {
+    fn new(camera: &Camera, options: &SsaoOptions) -> Self {
+        use cgmath::SquareMatrix;
+
+        let projection = OPENGL_TO_WGPU_MATRIX * camera.build_projection_matrix();
+        let sample_count = options.sample_count.clamp(1, MAX_SSAO_SAMPLES);
+        Self {
+            projection: projection.into(),
+            inverse_projection: projection
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity)
+                .into(),
+            kernel: hemisphere_kernel(sample_count),
+            radius: options.radius.max(0.0),
+            intensity: options.intensity.max(0.0),
+            bias: options.bias,
+            sample_count,
+        }
+    }
+}
+
+/// The linear depth and occlusion targets, and the pipelines filling them.
+pub struct SsaoPass {
+    linearize_layout: wgpu::BindGroupLayout,
+    occlusion_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    noise: wgpu::TextureView,
+    linearize_pipeline: Arc<wgpu::RenderPipeline>,
+    occlusion_pipeline: Arc<wgpu::RenderPipeline>,
+    blur_horizontal_pipeline: Arc<wgpu::RenderPipeline>,
+    blur_vertical_pipeline: Arc<wgpu::RenderPipeline>,
+    /// The camera pass' depth buffer.
+    depth: TargetId,
+    multisampled: bool,
+    view_depth: TargetId,
+    /// The occlusion before it's blurred.
+    raw_occlusion: TargetId,
+    /// Holds the occlusion between the two blurs.
+    blurred: TargetId,
+    /// The blurred result at the window's size.
+    pub occlusion: TargetId,
+    linearize_bind_group: wgpu::BindGroup,
+    occlusion_bind_group: wgpu::BindGroup,
+    blur_horizontal_bind_group: wgpu::BindGroup,
+    blur_vertical_bind_group: wgpu::BindGroup,
+}
+
+impl SsaoPass {
+    /// Reads `depth`, the camera pass' depth buffer with `sample_count`
+    /// samples, and adds its own targets to `targets`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        targets: &mut RenderTargets,
+        depth: TargetId,
+        sample_count: u32,
+    ) -> Self {
+        let texture_entry = |binding, sample_type, multisampled| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 5,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+
+        // Every entry point binds only what it reads, see `ssao.wgsl`.
+        let multisampled = sample_count > 1;
+        let linearize_layout = layout(
+            "SSAO Linearize Layout",
+            &[
+                texture_entry(
+                    if multisampled { 1 } else { 0 },
+                    wgpu::TextureSampleType::Depth,
+                    multisampled,
+                ),
+                uniform_entry,
+            ],
+        );
+        let occlusion_layout = layout(
+            "SSAO Layout",
+            &[
+                texture_entry(2, unfilterable, false),
+                texture_entry(3, unfilterable, false),
+                uniform_entry,
+            ],
+        );
+        let blur_layout = layout(
+            "SSAO Blur Layout",
+            &[
+                texture_entry(2, unfilterable, false),
+                texture_entry(4, unfilterable, false),
+                uniform_entry,
+            ],
+        );
+
+        // Written by `update` before the first frame.
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ssao.uniform_buffer"),
+            size: std::mem::size_of::<SsaoUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let noise = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("ssao_noise"),
+                    size: wgpu::Extent3d {
+                        width: NOISE_SIZE,
+                        height: NOISE_SIZE,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                },
+                bytemuck::cast_slice(&noise_rotations()),
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/ssao.wgsl")));
+        let mut pipeline = |layout: &wgpu::BindGroupLayout, entry_point, format| {
+            let pipeline_layout = Arc::new(device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some(entry_point),
+                    bind_group_layouts: &[layout],
+                    push_constant_ranges: &[],
+                },
+            ));
+            pipelines.get(
+                device,
+                &PipelineBuilder::new(entry_point, &pipeline_layout, &shader)
+                    .fragment(entry_point)
+                    .color_target(format, None)
+                    .cull_mode(None),
+            )
+        };
+        let linearize_pipeline = pipeline(
+            &linearize_layout,
+            if multisampled {
+                "linearize_multisampled"
+            } else {
+                "linearize"
+            },
+            VIEW_DEPTH_FORMAT,
+        );
+        let occlusion_pipeline = pipeline(&occlusion_layout, "occlusion", OCCLUSION_FORMAT);
+        let blur_horizontal_pipeline = pipeline(&blur_layout, "blur_horizontal", OCCLUSION_FORMAT);
+        let blur_vertical_pipeline = pipeline(&blur_layout, "blur_vertical", OCCLUSION_FORMAT);
+
+        let mut target = |label, size, format| {
+            targets.add(
+                device,
+                TargetDesc {
+                    label,
+                    size,
+                    format,
+                    sample_count: 1,
+                },
+            )
+        };
+        let view_depth = target("ssao_view_depth", RESOLUTION, VIEW_DEPTH_FORMAT);
+        let raw_occlusion = target("ssao_raw_occlusion", RESOLUTION, OCCLUSION_FORMAT);
+        let blurred = target("ssao_blurred", RESOLUTION, OCCLUSION_FORMAT);
+        let occlusion = target("ssao_occlusion", TargetSize::FULL, OCCLUSION_FORMAT);
+        let bind_groups = BindGroups::new(
+            device,
+            [&linearize_layout, &occlusion_layout, &blur_layout],
+            targets,
+            [depth, view_depth, raw_occlusion, blurred],
+            &noise,
+            &uniform_buffer,
+            multisampled,
+        );
+
+        Self {
+            linearize_layout,
+            occlusion_layout,
+            blur_layout,
+            uniform_buffer,
+            noise,
+            linearize_pipeline,
+            occlusion_pipeline,
+            blur_horizontal_pipeline,
+            blur_vertical_pipeline,
+            depth,
+            multisampled,
+            view_depth,
+            raw_occlusion,
+            blurred,
+            occlusion,
+            linearize_bind_group: bind_groups.linearize,
+            occlusion_bind_group: bind_groups.occlusion,
+            blur_horizontal_bind_group: bind_groups.blur_horizontal,
+            blur_vertical_bind_group: bind_groups.blur_vertical,
+        }
+    }
+
+    /// Points the bind groups at the recreated targets, e.g. after a resize.
+    pub fn set_input(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        let bind_groups = BindGroups::new(
+            device,
+            [
+                &self.linearize_layout,
+                &self.occlusion_layout,
+                &self.blur_layout,
+            ],
+            targets,
+            [
+                self.depth,
+                self.view_depth,
+                self.raw_occlusion,
+                self.blurred,
+            ],
+            &self.noise,
+            &self.uniform_buffer,
+            self.multisampled,
+        );
+        self.linearize_bind_group = bind_groups.linearize;
+        self.occlusion_bind_group = bind_groups.occlusion;
+        self.blur_horizontal_bind_group = bind_groups.blur_horizontal;
+        self.blur_vertical_bind_group = bind_groups.blur_vertical;
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, options: &SsaoOptions) {
+        let uniform = SsaoUniform::new(camera, options);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Fills [`SsaoPass::occlusion`] from the depth buffer.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, targets: &RenderTargets) {
+        let passes = [
+            (
+                "ssao.linearize",
+                self.view_depth,
+                &self.linearize_pipeline,
+                &self.linearize_bind_group,
+            ),
+            (
+                "ssao.occlusion",
+                self.raw_occlusion,
+                &self.occlusion_pipeline,
+                &self.occlusion_bind_group,
+            ),
+            (
+                "ssao.blur_horizontal",
+                self.blurred,
+                &self.blur_horizontal_pipeline,
+                &self.blur_horizontal_bind_group,
+            ),
+            (
+                "ssao.blur_vertical",
+                self.occlusion,
+                &self.blur_vertical_pipeline,
+                &self.blur_vertical_bind_group,
+            ),
+        ];
+        for (label, target, pipeline, bind_group) in passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &targets.get(target).view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+struct BindGroups {
+    linearize: wgpu::BindGroup,
+    occlusion: wgpu::BindGroup,
+    blur_horizontal: wgpu::BindGroup,
+    blur_vertical: wgpu::BindGroup,
+}
+
+impl BindGroups {
+    fn new(
+        device: &wgpu::Device,
+        [linearize_layout, occlusion_layout, blur_layout]: [&wgpu::BindGroupLayout; 3],
+        targets: &RenderTargets,
+        [depth, view_depth, raw_occlusion, blurred]: [TargetId; 4],
+        noise: &wgpu::TextureView,
+        uniform_buffer: &wgpu::Buffer,
+        multisampled: bool,
+    ) -> Self {
+        let bind_group = |layout, textures: &[(u32, &wgpu::TextureView)]| {
+            let mut entries = textures
+                .iter()
+                .map(|&(binding, view)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                })
+                .collect::<Vec<_>>();
+            entries.push(wgpu::BindGroupEntry {
+                binding: 5,
+                resource: uniform_buffer.as_entire_binding(),
+            });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("ssao.bind_group"),
+                layout,
+                entries: &entries,
+            })
+        };
+        let view = |id| &targets.get(id).view;
+        let view_depth = view(view_depth);
+        Self {
+            linearize: bind_group(
+                linearize_layout,
+                &[(if multisampled { 1 } else { 0 }, view(depth))],
+            ),
+            occlusion: bind_group(occlusion_layout, &[(2, view_depth), (3, noise)]),
+            blur_horizontal: bind_group(blur_layout, &[(2, view_depth), (4, view(raw_occlusion))]),
+            blur_vertical: bind_group(blur_layout, &[(2, view_depth), (4, view(blurred))]),
+        }
+    }
+}
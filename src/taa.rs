@@ -0,0 +1,324 @@
+//! Temporal anti-aliasing.
+//!
+//! The camera projection is jittered by a different sub-pixel offset every
+//! frame, and each frame is blended into a history of the previous ones,
+//! reprojected along the camera pass' motion vectors. Over a few frames this
+//! gathers several samples per pixel for the cost of one.
+
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use crate::{
+    pipeline::{PipelineBuilder, PipelineCache},
+    rendertarget::{RenderTargets, TargetDesc, TargetId, TargetSize},
+    texture,
+};
+
+/// Length of the jitter sequence before it repeats.
+pub const JITTER_SAMPLES: u32 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TaaOptions {
+    /// Weight of the current frame when it is blended into the history.
+    /// Lower values smooth more but take longer to catch up with changes.
+    pub blend: f32,
+}
+
+impl Default for TaaOptions {
+    fn default() -> Self {
+        Self { blend: 0.1 }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    blend: f32,
+    history_valid: u32,
+    _padding: [u32; 2],
+}
+
+/// Element `index` of the Halton sequence in `base`, in [0, 1).
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Projection offset for `frame` on a `width` by `height` target, in
+/// normalized device coordinates. Follows the Halton (2, 3) sequence, which
+/// covers the pixel evenly, and stays within half a pixel of its center.
+pub fn jitter(frame: u32, width: u32, height: u32) -> cgmath::Vector2<f32> {
+    // Skip the first element, which sits exactly in the corner.
+    let index = frame % JITTER_SAMPLES + 1;
+    cgmath::Vector2::new(
+        (halton(index, 2) - 0.5) * 2.0 / width.max(1) as f32,
+        (halton(index, 3) - 0.5) * 2.0 / height.max(1) as f32,
+    )
+}
+
+/// The history buffers and pipeline for TAA.
+pub struct TaaPass {
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+    /// Resolved against the history, and overwritten with the result.
+    hdr: TargetId,
+    velocity: TargetId,
+    /// Each frame resolves into one of these, reading the other.
+    histories: [TargetId; 2],
+    /// Reads the history with the other index.
+    bind_groups: [wgpu::BindGroup; 2],
+    size: (u32, u32),
+    frame: u32,
+    history_valid: bool,
+}
+
+impl TaaPass {
+    /// Resolves `hdr` along `velocity`, and adds the history to `targets`.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        targets: &mut RenderTargets,
+        hdr: TargetId,
+        velocity: TargetId,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("taa.sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("taa.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[TaaUniform {
+                blend: 1.0,
+                history_valid: 0,
+                _padding: [0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("taa.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/taa.wgsl")));
+        let render_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("taa.render_pipeline", &pipeline_layout, &shader)
+                .fragment("resolve")
+                .color_target(
+                    texture::Texture::HDR_FORMAT,
+                    Some(wgpu::BlendState::REPLACE),
+                )
+                .cull_mode(None),
+        );
+
+        let mut history = |label| {
+            targets.add(
+                device,
+                TargetDesc {
+                    label,
+                    size: TargetSize::FULL,
+                    format: texture::Texture::HDR_FORMAT,
+                    sample_count: 1,
+                },
+            )
+        };
+        let histories = [history("taa_history_0"), history("taa_history_1")];
+        let bind_groups = Self::create_bind_groups(
+            device,
+            targets,
+            [hdr, velocity],
+            histories,
+            &layout,
+            &sampler,
+            &uniform_buffer,
+        );
+
+        Self {
+            layout,
+            sampler,
+            uniform_buffer,
+            render_pipeline,
+            hdr,
+            velocity,
+            histories,
+            bind_groups,
+            size: targets.size(hdr),
+            frame: 0,
+            history_valid: false,
+        }
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        targets: &RenderTargets,
+        [hdr, velocity]: [TargetId; 2],
+        histories: [TargetId; 2],
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> [wgpu::BindGroup; 2] {
+        let hdr_view = &targets.get(hdr).view;
+        let velocity_view = &targets.get(velocity).view;
+        let bind_group = |history: TargetId| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("taa.bind_group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(hdr_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&targets.get(history).view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(velocity_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        [bind_group(histories[1]), bind_group(histories[0])]
+    }
+
+    /// Points the bind groups at the recreated targets, e.g. after a resize.
+    /// The old history is dropped.
+    pub fn set_input(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            targets,
+            [self.hdr, self.velocity],
+            self.histories,
+            &self.layout,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+        self.size = targets.size(self.hdr);
+        self.invalidate();
+    }
+
+    /// Drops the history, so the next frame starts over from itself, e.g.
+    /// after a camera cut.
+    pub fn invalidate(&mut self) {
+        self.history_valid = false;
+    }
+
+    /// Moves on to the next frame and uploads `options` for it.
+    pub fn update(&mut self, queue: &wgpu::Queue, options: &TaaOptions) {
+        self.frame = self.frame.wrapping_add(1);
+        let uniform = TaaUniform {
+            blend: options.blend.clamp(0.0, 1.0),
+            history_valid: self.history_valid as u32,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.history_valid = true;
+    }
+
+    /// Projection offset for the current frame.
+    pub fn jitter(&self) -> cgmath::Vector2<f32> {
+        let (width, height) = self.size;
+        jitter(self.frame, width, height)
+    }
+
+    /// Resolves the HDR target against the history, and copies the result
+    /// back into the HDR target.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, targets: &RenderTargets) {
+        let write = (self.frame % 2) as usize;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("taa.render_pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &targets.get(self.histories[write]).view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.bind_groups[write], &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        let (width, height) = self.size;
+        encoder.copy_texture_to_texture(
+            targets.get(self.histories[write]).texture.as_image_copy(),
+            targets.get(self.hdr).texture.as_image_copy(),
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
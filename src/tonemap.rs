@@ -0,0 +1,260 @@
+//! Maps the HDR scene color to the output format.
+
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use crate::{
+    exposure::AutoExposureOptions,
+    pipeline::{PipelineBuilder, PipelineCache},
+};
+
+/// Curve that compresses HDR color into the displayable range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// `c / (1 + c)` per channel. Cheap, but desaturates highlights.
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference and output transforms.
+    Aces,
+    /// Troy Sobotka's AgX, which keeps saturated highlights from skewing hue.
+    Agx,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TonemapOptions {
+    pub operator: TonemapOperator,
+    /// Exposure compensation in stops. The scene color is scaled by
+    /// `2^exposure` before the operator.
+    pub exposure: f32,
+    /// Scales the scene so its average luminance lands on middle gray, on
+    /// top of `exposure`. `None` leaves exposure to the manual value alone.
+    pub auto_exposure: Option<AutoExposureOptions>,
+}
+
+impl Default for TonemapOptions {
+    fn default() -> Self {
+        Self {
+            operator: TonemapOperator::Aces,
+            exposure: 0.0,
+            auto_exposure: None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    operator: u32,
+    /// Linear scale applied before the operator.
+    exposure: f32,
+    /// Set when the output format doesn't encode to sRGB by itself.
+    encode_srgb: u32,
+    auto_exposure: u32,
+}
+
+impl TonemapUniform {
+    fn new(options: &TonemapOptions, output_format: wgpu::TextureFormat) -> Self {
+        Self {
+            operator: match options.operator {
+                TonemapOperator::Reinhard => 0,
+                TonemapOperator::Aces => 1,
+                TonemapOperator::Agx => 2,
+            },
+            exposure: options.exposure.exp2(),
+            encode_srgb: !output_format.describe().srgb as u32,
+            auto_exposure: options.auto_exposure.is_some() as u32,
+        }
+    }
+}
+
+/// Fullscreen pass that tonemaps an HDR texture into the output target.
+pub struct TonemapPass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+    output_format: wgpu::TextureFormat,
+}
+
+impl TonemapPass {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        config: &wgpu::SurfaceConfiguration,
+        hdr_view: &wgpu::TextureView,
+        luminance_buffer: &wgpu::Buffer,
+        options: &TonemapOptions,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tonemap Pass Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("tonemap_pass.sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tonemap_pass.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[TonemapUniform::new(options, config.format)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            hdr_view,
+            &sampler,
+            &uniform_buffer,
+            luminance_buffer,
+        );
+
+        let pipeline_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("tonemap_pass.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
+
+        let shader =
+            Arc::new(device.create_shader_module(&wgpu::include_wgsl!("../shaders/tonemap.wgsl")));
+
+        let render_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new("tonemap_pass.render_pipeline", &pipeline_layout, &shader)
+                .fragment("main")
+                .color_target(config.format, Some(wgpu::BlendState::REPLACE))
+                .cull_mode(None),
+        );
+
+        Self {
+            layout,
+            bind_group,
+            sampler,
+            uniform_buffer,
+            render_pipeline,
+            output_format: config.format,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+        luminance_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_pass.bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: luminance_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Reads from a new HDR texture, e.g. after a resize.
+    pub fn set_input(
+        &mut self,
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        luminance_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            hdr_view,
+            &self.sampler,
+            &self.uniform_buffer,
+            luminance_buffer,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, options: &TonemapOptions) {
+        let uniform = TonemapUniform::new(options, self.output_format);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tonemap_pass.render_pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
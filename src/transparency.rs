@@ -0,0 +1,248 @@
+//! Translucent materials, drawn over the opaque scene without writing depth.
+//!
+//! [`AlphaMode::Blend`](crate::model::AlphaMode::Blend) meshes go into a
+//! transparent queue with one draw per instance. Sorted back to front and
+//! alpha blended one over the other, they are exact as long as no two of
+//! them intersect. Weighted blended order independent transparency instead
+//! sums them in any order into an accumulation and a revealage target, and
+//! composites the weighted average over the scene: an approximation of how
+//! they cover each other, but one that never pops as they move past each
+//! other.
+
+use std::sync::Arc;
+
+use crate::{
+    pipeline::{PipelineBuilder, PipelineCache},
+    renderpass, texture,
+};
+
+/// Premultiplied, weighted color and alpha of the transparent fragments.
+pub const ACCUMULATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Product of one minus the transparent fragments' alphas.
+pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// How the transparent queue is drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// Back to front by view depth, each draw blended over the last.
+    Sorted,
+    /// Weighted blended order independent transparency.
+    WeightedBlended,
+}
+
+/// One instance of one mesh in the transparent queue.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransparentDraw {
+    /// Index of the model in the scene.
+    pub model: usize,
+    /// Index of the mesh in the model.
+    pub mesh: usize,
+    pub instance: u32,
+    /// Distance of the mesh's center from the camera along its view
+    /// direction.
+    pub view_depth: f32,
+}
+
+/// Orders `draws` farthest first. Draws at the same depth keep their order.
+pub fn sort_back_to_front(draws: &mut [TransparentDraw]) {
+    draws.sort_by(|a, b| b.view_depth.total_cmp(&a.view_depth));
+}
+
+/// The weighted blended targets, which the render graph allocates for the
+/// frame. When multisampling, the accumulation goes into the multisampled
+/// pair and is resolved into the single sampled one the composite reads.
+pub struct AccumulationTargets<'t> {
+    pub accumulation: &'t texture::Texture,
+    pub revealage: &'t texture::Texture,
+    pub msaa: Option<[&'t texture::Texture; 2]>,
+}
+
+impl<'t> AccumulationTargets<'t> {
+    /// The targets, cleared, for a pass drawing with
+    /// [`TransparencyPass::accumulate`].
+    pub fn color_attachments(&self) -> [wgpu::RenderPassColorAttachment<'t>; 2] {
+        let single_sampled = [self.accumulation, self.revealage];
+        let clear = [wgpu::Color::TRANSPARENT, wgpu::Color::WHITE];
+        let attachment = |target: usize| wgpu::RenderPassColorAttachment {
+            view: match self.msaa {
+                Some(msaa) => &msaa[target].view,
+                None => &single_sampled[target].view,
+            },
+            resolve_target: self.msaa.map(|_| &single_sampled[target].view),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear[target]),
+                store: true,
+            },
+        };
+        [attachment(0), attachment(1)]
+    }
+}
+
+pub struct TransparencyPass {
+    /// Blends sorted draws into the camera pass' color target.
+    pub sorted: renderpass::Pass,
+    /// Adds draws in any order into the weighted blended targets.
+    pub accumulate: renderpass::Pass,
+    layout: wgpu::BindGroupLayout,
+    composite_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl TransparencyPass {
+    /// `mesh` describes the camera pass' pipeline before its fragment
+    /// stage, whose `transparent` and `accumulate` entries shade the
+    /// transparent queue.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        mesh: &PipelineBuilder,
+    ) -> Self {
+        // Hidden by opaque geometry, but not by each other.
+        let mesh = mesh.clone().depth(
+            texture::Texture::DEPTH_FORMAT,
+            false,
+            wgpu::CompareFunction::LessEqual,
+        );
+
+        let sorted = renderpass::Pass {
+            pipeline: pipelines.get(
+                device,
+                &mesh
+                    .clone()
+                    .label("transparency.sorted_pipeline")
+                    .fragment("transparent")
+                    .color_target(
+                        texture::Texture::HDR_FORMAT,
+                        Some(wgpu::BlendState::ALPHA_BLENDING),
+                    )
+                    // Motion stays that of the opaque surface behind.
+                    .color_target_state(wgpu::ColorTargetState {
+                        format: texture::Texture::VELOCITY_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }),
+            ),
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let revealing = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrc,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let accumulate = renderpass::Pass {
+            pipeline: pipelines.get(
+                device,
+                &mesh
+                    .label("transparency.accumulate_pipeline")
+                    .fragment("accumulate")
+                    .color_target(
+                        ACCUMULATION_FORMAT,
+                        Some(wgpu::BlendState {
+                            color: additive,
+                            alpha: additive,
+                        }),
+                    )
+                    .color_target(
+                        REVEALAGE_FORMAT,
+                        Some(wgpu::BlendState {
+                            color: revealing,
+                            alpha: revealing,
+                        }),
+                    ),
+            ),
+        };
+
+        // Matches the bindings in `transparency.wgsl`.
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Transparency Layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+
+        let composite_layout = Arc::new(device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("transparency.pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            },
+        ));
+        let composite_shader = Arc::new(
+            device.create_shader_module(&wgpu::include_wgsl!("../shaders/transparency.wgsl")),
+        );
+        let composite_pipeline = pipelines.get(
+            device,
+            &PipelineBuilder::new(
+                "transparency.composite_pipeline",
+                &composite_layout,
+                &composite_shader,
+            )
+            .fragment("composite")
+            .color_target(
+                texture::Texture::HDR_FORMAT,
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+            )
+            .cull_mode(None),
+        );
+
+        Self {
+            sorted,
+            accumulate,
+            layout,
+            composite_pipeline,
+        }
+    }
+
+    /// Blends the transparent fragments accumulated into the single sampled
+    /// `accumulation` and `revealage` targets over `hdr_view`.
+    pub fn composite(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        accumulation: &wgpu::TextureView,
+        revealage: &wgpu::TextureView,
+        hdr_view: &wgpu::TextureView,
+    ) {
+        // The targets can move between frames, so this is made fresh.
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("transparency.bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(accumulation),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(revealage),
+                },
+            ],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Transparency Composite Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: hdr_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
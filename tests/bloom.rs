@@ -0,0 +1,10 @@
+use bitter_engine::bloom::{bloom_mip_count, MAX_BLOOM_MIPS};
+
+#[test]
+fn mip_chain_stops_at_one_texel() {
+    // The chain starts at half resolution.
+    assert_eq!(bloom_mip_count(2, 2), 1);
+    assert_eq!(bloom_mip_count(16, 64), 4);
+    assert_eq!(bloom_mip_count(1920, 1080), MAX_BLOOM_MIPS);
+    assert_eq!(bloom_mip_count(0, 0), 1);
+}
@@ -0,0 +1,37 @@
+use bitter_engine::cluster::{depth_slice, slice_depth, CLUSTER_GRID};
+
+const ZNEAR: f32 = 0.1;
+const ZFAR: f32 = 100.0;
+
+#[test]
+fn slices_span_the_depth_range() {
+    let slices = CLUSTER_GRID[2];
+    assert!((slice_depth(0, ZNEAR, ZFAR) - ZNEAR).abs() < 1e-6);
+    assert!((slice_depth(slices, ZNEAR, ZFAR) - ZFAR).abs() < 1e-3);
+
+    assert_eq!(depth_slice(ZNEAR, ZNEAR, ZFAR), 0);
+    assert_eq!(depth_slice(ZFAR, ZNEAR, ZFAR), slices - 1);
+    // Depths outside the range go to the first and last slice.
+    assert_eq!(depth_slice(0.01, ZNEAR, ZFAR), 0);
+    assert_eq!(depth_slice(1000.0, ZNEAR, ZFAR), slices - 1);
+}
+
+#[test]
+fn depth_falls_into_the_slice_that_contains_it() {
+    for slice in 0..CLUSTER_GRID[2] {
+        let near = slice_depth(slice, ZNEAR, ZFAR);
+        let far = slice_depth(slice + 1, ZNEAR, ZFAR);
+        assert_eq!(depth_slice((near + far) / 2.0, ZNEAR, ZFAR), slice);
+    }
+}
+
+#[test]
+fn slices_grow_with_depth() {
+    let depths = (0..=CLUSTER_GRID[2])
+        .map(|slice| slice_depth(slice, ZNEAR, ZFAR))
+        .collect::<Vec<_>>();
+    for window in depths.windows(3) {
+        let ratio = (window[2] - window[1]) / (window[1] - window[0]);
+        assert!(ratio > 1.0, "{:?}", window);
+    }
+}
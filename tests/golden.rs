@@ -0,0 +1,764 @@
+//! Golden-image tests for the render passes.
+//!
+//! Each test renders a fixed scene with a headless engine and compares the
+//! frame against `tests/golden/<name>.png`. On a mismatch the actual frame and
+//! a diff image are written to `target/golden/`. Run with `BLESS_GOLDEN=1` to
+//! (re)write the references; a missing reference fails the test otherwise.
+//!
+//! The tests need an adapter, falling back to a software one, so they are
+//! ignored by default: run them with `cargo test --test golden -- --ignored`.
+//! The references are blessed on lavapipe, Mesa's software Vulkan driver, and
+//! CI compares against them there. Set `GOLDEN_FALLBACK_ADAPTER=1` to go
+//! straight to the software adapter like CI does.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bitter_engine::{
+    bloom::BloomOptions,
+    exposure::AutoExposureOptions,
+    lighting::Light,
+    model::AlphaMode,
+    scene::{ModelId, NodeContent, Transform},
+    shadow::{ShadowFilter, ShadowOptions},
+    sky::{AtmosphereOptions, Sky},
+    ssao::SsaoOptions,
+    taa::TaaOptions,
+    tonemap::{TonemapOperator, TonemapOptions},
+    transparency::Transparency,
+    AntiAliasing, DebugView, Engine, EngineOptions, ShadingPath,
+};
+use cgmath::{InnerSpace, Rotation3, Zero};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+/// Largest per-channel difference for two pixels to count as equal.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to exceed the tolerance, to absorb
+/// rasterization differences between adapters.
+const MAX_DIFFERING_FRACTION: f64 = 0.001;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden")
+}
+
+fn res_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res")
+}
+
+fn headless_engine() -> Engine {
+    headless_engine_with(&EngineOptions::default())
+}
+
+fn headless_engine_with(options: &EngineOptions) -> Engine {
+    headless_engine_sized(WIDTH, HEIGHT, options)
+}
+
+/// Creates the engine on the adapter `options` ask for, or on a software
+/// one if there is none or `GOLDEN_FALLBACK_ADAPTER` is set.
+fn headless_engine_sized(width: u32, height: u32, options: &EngineOptions) -> Engine {
+    let options = EngineOptions {
+        force_fallback_adapter: options.force_fallback_adapter
+            || std::env::var_os("GOLDEN_FALLBACK_ADAPTER").is_some(),
+        ..*options
+    };
+    pollster::block_on(Engine::new_headless(width, height, &options))
+        .or_else(|e| {
+            eprintln!("{}, trying a fallback adapter", e);
+            let fallback = EngineOptions {
+                force_fallback_adapter: true,
+                ..options
+            };
+            pollster::block_on(Engine::new_headless(width, height, &fallback))
+        })
+        .expect("golden tests need an adapter")
+}
+
+/// Loads the cube model and a light at its default position into the scene.
+fn load_cube(engine: &mut Engine) -> ModelId {
+    let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+    let cube = engine.scene.add_model(model);
+    engine.scene.light_gizmo = Some(cube);
+    engine
+        .scene
+        .add_light(Light::point(cgmath::Point3::new(2.0, 5.0, 2.0)));
+    cube
+}
+
+fn add_cube_grid(engine: &mut Engine, cube: ModelId) {
+    const NUM_INSTANCES_PER_ROW: u32 = 10;
+    const SPACE_BETWEEN: f32 = 3.0;
+    let displacement = cgmath::Vector3::new(
+        NUM_INSTANCES_PER_ROW as f32 * 0.5,
+        0.0,
+        NUM_INSTANCES_PER_ROW as f32 * 0.5,
+    );
+
+    for z in 0..NUM_INSTANCES_PER_ROW {
+        for x in 0..NUM_INSTANCES_PER_ROW {
+            let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+            let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+
+            let position = cgmath::Vector3 { x, y: 0.0, z } - displacement;
+            let rotation = if position.is_zero() {
+                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+            } else {
+                cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+            };
+
+            engine.scene.add_node(
+                None,
+                "cube",
+                Transform {
+                    translation: position,
+                    rotation,
+                    ..Default::default()
+                },
+                NodeContent::Model(cube),
+            );
+        }
+    }
+}
+
+/// Adds a 16 by 16 grid of small colored lights just above the cube grid.
+fn add_light_grid(engine: &mut Engine) {
+    for z in 0..16 {
+        for x in 0..16 {
+            engine.scene.add_light(Light {
+                color: [x as f32 / 15.0, 0.5, z as f32 / 15.0],
+                intensity: 2.0,
+                range: 4.0,
+                cast_shadows: false,
+                ..Light::point(cgmath::Point3::new(
+                    x as f32 * 2.0 - 20.0,
+                    1.5,
+                    z as f32 * 2.0 - 20.0,
+                ))
+            });
+        }
+    }
+}
+
+/// Time between the frames the tests render, so that adaptation over time
+/// doesn't depend on how fast the adapter is.
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+fn render(engine: &mut Engine) -> image::RgbaImage {
+    engine.update(FRAME_TIME);
+    engine.render().unwrap();
+    engine.read_frame().unwrap()
+}
+
+/// Compares `actual` against the stored reference called `name`.
+fn assert_golden(name: &str, actual: &image::RgbaImage) {
+    let reference_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("BLESS_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        eprintln!("wrote reference {}", reference_path.display());
+        return;
+    }
+
+    assert!(
+        reference_path.exists(),
+        "{}: no reference at {}, run with BLESS_GOLDEN=1 to write it",
+        name,
+        reference_path.display()
+    );
+    let reference = image::open(&reference_path).unwrap().to_rgba8();
+    assert_eq!(
+        reference.dimensions(),
+        actual.dimensions(),
+        "{}: frame size does not match the reference",
+        name
+    );
+
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+    let mut differing = 0;
+    for ((expected_pixel, actual_pixel), diff_pixel) in reference
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let max_delta = expected_pixel
+            .0
+            .iter()
+            .zip(actual_pixel.0.iter())
+            .map(|(a, b)| (*a as i16 - *b as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap();
+        *diff_pixel = if max_delta > CHANNEL_TOLERANCE {
+            differing += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            // Dim matching pixels so the differences stand out.
+            let [r, g, b, _] = actual_pixel.0;
+            image::Rgba([r / 4, g / 4, b / 4, 255])
+        };
+    }
+
+    let total = (actual.width() * actual.height()) as f64;
+    if differing as f64 / total > MAX_DIFFERING_FRACTION {
+        std::fs::create_dir_all(output_dir()).unwrap();
+        let actual_path = output_dir().join(format!("{}.actual.png", name));
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{}: {} of {} pixels differ from the reference, see {} and {}",
+            name,
+            differing,
+            total,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn cube_grid_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+
+    assert_golden("cube_grid", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn shadow_map_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    // Point lights render into cubes, so the 2D map needs a spot light.
+    engine.scene.add_light(Light::spot(
+        cgmath::Point3::new(2.0, 5.0, 2.0),
+        cgmath::Vector3::new(-0.2, -1.0, -0.2),
+        cgmath::Deg(30.0),
+        cgmath::Deg(45.0),
+    ));
+    engine.debug_view = DebugView::ShadowMap;
+
+    assert_golden("shadow_map", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn light_gizmo_matches_reference() {
+    let mut engine = headless_engine();
+    load_cube(&mut engine);
+
+    assert_golden("light_gizmo", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn msaa_matches_reference() {
+    let options = EngineOptions {
+        sample_count: 4,
+        ..Default::default()
+    };
+    let mut engine = headless_engine_with(&options);
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+
+    assert_golden("msaa", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn fxaa_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.anti_aliasing = AntiAliasing::Fxaa;
+
+    assert_golden("fxaa", &render(&mut engine));
+}
+
+/// TAA accumulates over frames, so the reference is the image it settles on
+/// once the jitter sequence has gone around twice.
+#[test]
+#[ignore = "needs an adapter"]
+fn taa_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.anti_aliasing = AntiAliasing::Taa(TaaOptions::default());
+
+    for _ in 0..16 {
+        engine.update(FRAME_TIME);
+        engine.render().unwrap();
+    }
+    assert_golden("taa", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn bloom_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.scene.lights[0].intensity = 8.0;
+    engine.bloom = Some(BloomOptions {
+        intensity: 0.2,
+        ..Default::default()
+    });
+
+    assert_golden("bloom", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn mixed_lights_match_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.scene.add_light(Light {
+        color: [1.0, 0.2, 0.2],
+        ..Light::spot(
+            cgmath::Point3::new(-6.0, 4.0, -6.0),
+            cgmath::Vector3::new(0.0, -1.0, 0.0),
+            cgmath::Deg(15.0),
+            cgmath::Deg(30.0),
+        )
+    });
+    engine.scene.add_light(Light {
+        color: [0.2, 0.2, 1.0],
+        intensity: 0.5,
+        ..Light::directional(cgmath::Vector3::new(1.0, -1.0, 0.0))
+    });
+
+    assert_golden("mixed_lights", &render(&mut engine));
+}
+
+/// The deferred path lights the same scene as the forward one, so it shares
+/// its reference.
+#[test]
+#[ignore = "needs an adapter"]
+fn deferred_matches_forward_reference() {
+    let mut engine = headless_engine_with(&EngineOptions {
+        shading_path: ShadingPath::Deferred,
+        ..Default::default()
+    });
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.scene.add_light(Light {
+        color: [1.0, 0.2, 0.2],
+        ..Light::spot(
+            cgmath::Point3::new(-6.0, 4.0, -6.0),
+            cgmath::Vector3::new(0.0, -1.0, 0.0),
+            cgmath::Deg(15.0),
+            cgmath::Deg(30.0),
+        )
+    });
+    engine.scene.add_light(Light {
+        color: [0.2, 0.2, 1.0],
+        intensity: 0.5,
+        ..Light::directional(cgmath::Vector3::new(1.0, -1.0, 0.0))
+    });
+
+    assert_golden("mixed_lights", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn deferred_many_lights_match_reference() {
+    let mut engine = headless_engine_with(&EngineOptions {
+        shading_path: ShadingPath::Deferred,
+        ..Default::default()
+    });
+    let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+    let cube = engine.scene.add_model(model);
+    add_cube_grid(&mut engine, cube);
+    add_light_grid(&mut engine);
+
+    assert_golden("deferred_many_lights", &render(&mut engine));
+}
+
+/// The clustered forward path culls lights instead, but has to light the
+/// scene just like the deferred path.
+#[test]
+#[ignore = "needs an adapter"]
+fn clustered_many_lights_match_deferred_reference() {
+    let mut engine = headless_engine();
+    let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+    let cube = engine.scene.add_model(model);
+    add_cube_grid(&mut engine, cube);
+    add_light_grid(&mut engine);
+
+    assert_golden("deferred_many_lights", &render(&mut engine));
+}
+
+/// Writes an equirectangular sky to `target/golden/<name>.hdr`: a bright
+/// warm horizon fading into a blue zenith, over a dark ground.
+fn write_sky(name: &str) -> PathBuf {
+    const SKY_WIDTH: u32 = 128;
+    const SKY_HEIGHT: u32 = 64;
+    let pixels = (0..SKY_HEIGHT)
+        .flat_map(|y| {
+            let elevation = 1.0 - 2.0 * (y as f32 + 0.5) / SKY_HEIGHT as f32;
+            let pixel = if elevation > 0.0 {
+                let t = elevation.sqrt();
+                image::Rgb([3.0 - 2.6 * t, 2.6 - 2.0 * t, 2.0 - 0.8 * t])
+            } else {
+                image::Rgb([0.15, 0.12, 0.1])
+            };
+            std::iter::repeat_n(pixel, SKY_WIDTH as usize)
+        })
+        .collect::<Vec<_>>();
+
+    std::fs::create_dir_all(output_dir()).unwrap();
+    let path = output_dir().join(format!("{}.hdr", name));
+    let file = std::fs::File::create(&path).unwrap();
+    image::codecs::hdr::HdrEncoder::new(std::io::BufWriter::new(file))
+        .encode(&pixels, SKY_WIDTH as usize, SKY_HEIGHT as usize)
+        .unwrap();
+    path
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn environment_lighting_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.load_environment(write_sky("sky")).unwrap();
+
+    assert_golden("environment_lighting", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn environment_sky_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine
+        .load_environment(write_sky("environment_sky"))
+        .unwrap();
+    engine.sky = Some(Sky::Environment);
+
+    assert_golden("environment_sky", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn atmosphere_matches_reference() {
+    let mut engine = headless_engine();
+    let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+    let cube = engine.scene.add_model(model);
+    add_cube_grid(&mut engine, cube);
+    let sun = engine
+        .scene
+        .add_light(Light::directional(cgmath::Vector3::new(0.0, -1.0, 0.0)));
+    // A low sun, so the horizon and the lit cubes turn orange.
+    engine.sky = Some(Sky::Atmosphere(AtmosphereOptions {
+        sun_direction: cgmath::Vector3::new(-0.2, 0.08, 1.0),
+        sun_light: Some(sun),
+        ..Default::default()
+    }));
+
+    let frame = render(&mut engine);
+    let [r, g, b] = engine.scene.light(sun).color;
+    assert!(r > g && g > b, "{:?}", [r, g, b]);
+    assert_golden("atmosphere", &frame);
+}
+
+/// Adds a ground slab whose top the cube grid sinks into.
+fn add_ground(engine: &mut Engine) {
+    let model = engine.load_model(res_dir().join("plane.obj")).unwrap();
+    let plane = engine.scene.add_model(model);
+    engine.scene.add_node(
+        None,
+        "ground",
+        Transform::from_translation(cgmath::Vector3::new(0.0, -1.5, 0.0)),
+        NodeContent::Model(plane),
+    );
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn ssao_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_ground(&mut engine);
+    engine.ssao = Some(SsaoOptions::default());
+
+    assert_golden("ssao", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn multisampled_ssao_matches_reference() {
+    let mut engine = headless_engine_with(&EngineOptions {
+        sample_count: 4,
+        ..Default::default()
+    });
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_ground(&mut engine);
+    engine.ssao = Some(SsaoOptions::default());
+
+    assert_golden("ssao_msaa", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn resized_engine_matches_reference() {
+    // Created at another size and aspect, so every target and the camera
+    // have to follow the resize.
+    let options = EngineOptions {
+        sample_count: 4,
+        ..Default::default()
+    };
+    let mut engine = headless_engine_sized(WIDTH / 2, HEIGHT * 2, &options);
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_ground(&mut engine);
+    engine.ssao = Some(SsaoOptions::default());
+    render(&mut engine);
+    engine.resize(winit::dpi::PhysicalSize::new(WIDTH, HEIGHT));
+
+    assert_golden("ssao_msaa", &render(&mut engine));
+}
+
+/// Adds a row of overlapping translucent cubes between the camera and the
+/// cube grid, tinted differently so their order shows.
+fn add_glass(engine: &mut Engine) {
+    for (i, tint) in [[0.9, 0.2, 0.2], [0.2, 0.9, 0.2], [0.2, 0.2, 0.9]]
+        .iter()
+        .enumerate()
+    {
+        let mut model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+        for material in &mut model.materials {
+            let material = std::sync::Arc::get_mut(material).unwrap();
+            let [r, g, b] = *tint;
+            material.factors.base_color = [r, g, b, 0.5];
+            material.factors.alpha_mode = AlphaMode::Blend;
+            material.write_factors(&engine.queue);
+        }
+        let glass = engine.scene.add_model(model);
+        engine.scene.add_node(
+            None,
+            "glass",
+            Transform::from_translation(cgmath::Vector3::new(
+                i as f32 * 1.2 - 1.2,
+                1.0,
+                i as f32 * 1.5 + 2.0,
+            )),
+            NodeContent::Model(glass),
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn sorted_transparency_matches_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_glass(&mut engine);
+
+    assert_golden("transparency_sorted", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn weighted_blended_transparency_matches_reference() {
+    let mut engine = headless_engine_with(&EngineOptions {
+        sample_count: 4,
+        ..Default::default()
+    });
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_glass(&mut engine);
+    engine.transparency = Transparency::WeightedBlended;
+
+    assert_golden("transparency_weighted_blended", &render(&mut engine));
+}
+
+/// Transparent surfaces are shaded forward on both paths.
+#[test]
+#[ignore = "needs an adapter"]
+fn deferred_transparency_matches_forward_reference() {
+    let mut engine = headless_engine_with(&EngineOptions {
+        shading_path: ShadingPath::Deferred,
+        ..Default::default()
+    });
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    add_glass(&mut engine);
+
+    assert_golden("transparency_sorted", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn cascaded_shadows_match_reference() {
+    let mut engine = headless_engine();
+    let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+    let cube = engine.scene.add_model(model);
+    add_cube_grid(&mut engine, cube);
+    // The first directional or spot light gets the cascades.
+    engine
+        .scene
+        .add_light(Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0)));
+
+    assert_golden("cascaded_shadows", &render(&mut engine));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn point_shadows_match_reference() {
+    let mut engine = headless_engine();
+    let cube = load_cube(&mut engine);
+    add_cube_grid(&mut engine, cube);
+    engine.scene.add_light(Light {
+        color: [0.2, 0.2, 1.0],
+        ..Light::point(cgmath::Point3::new(-4.0, 1.5, -4.0))
+    });
+
+    assert_golden("point_shadows", &render(&mut engine));
+}
+
+/// Renders the cascaded shadow scene with each shadow filter.
+#[test]
+#[ignore = "needs an adapter"]
+fn shadow_filters_match_reference() {
+    let filters = [
+        ("hardware", ShadowFilter::Hardware { bias: 0.0 }),
+        (
+            "poisson",
+            ShadowFilter::PoissonPcf {
+                samples: 16,
+                radius: 3.0,
+                bias: 0.0,
+            },
+        ),
+        (
+            "pcss",
+            ShadowFilter::Pcss {
+                light_size: 12.0,
+                samples: 16,
+                bias: 0.0,
+            },
+        ),
+        (
+            "vsm",
+            ShadowFilter::Vsm {
+                kernel: 3,
+                min_variance: 0.00002,
+                light_bleed: 0.2,
+            },
+        ),
+        (
+            "evsm",
+            ShadowFilter::Evsm {
+                kernel: 3,
+                exponents: [5.0, 5.0],
+                min_variance: 0.00002,
+                light_bleed: 0.1,
+            },
+        ),
+    ];
+
+    for (name, filter) in filters.iter() {
+        let options = EngineOptions {
+            shadows: ShadowOptions {
+                filter: *filter,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = headless_engine_with(&options);
+        let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+        let cube = engine.scene.add_model(model);
+        add_cube_grid(&mut engine, cube);
+        engine
+            .scene
+            .add_light(Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0)));
+        engine.scene.add_light(Light {
+            color: [0.2, 0.2, 1.0],
+            ..Light::point(cgmath::Point3::new(-4.0, 1.5, -4.0))
+        });
+
+        assert_golden(&format!("shadow_filter_{}", name), &render(&mut engine));
+    }
+}
+
+/// Lights the cube grid well past 1.0 and tonemaps it with each operator.
+#[test]
+#[ignore = "needs an adapter"]
+fn tonemap_operators_match_reference() {
+    let operators = [
+        ("reinhard", TonemapOperator::Reinhard),
+        ("aces", TonemapOperator::Aces),
+        ("agx", TonemapOperator::Agx),
+    ];
+
+    for (name, operator) in operators.iter() {
+        let mut engine = headless_engine();
+        let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+        let cube = engine.scene.add_model(model);
+        add_cube_grid(&mut engine, cube);
+        engine.scene.add_light(Light {
+            color: [1.0, 0.6, 0.3],
+            intensity: 8.0,
+            ..Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0))
+        });
+        engine.tonemap = TonemapOptions {
+            operator: *operator,
+            exposure: -1.0,
+            ..Default::default()
+        };
+
+        assert_golden(&format!("tonemap_{}", name), &render(&mut engine));
+    }
+}
+
+/// Auto exposure should bring a dim and a bright version of the same scene
+/// to about the same brightness.
+#[test]
+#[ignore = "needs an adapter"]
+fn auto_exposure_evens_out_brightness() {
+    let mean_brightness = |intensity: f32| {
+        let mut engine = headless_engine();
+        let model = engine.load_model(res_dir().join("cube.obj")).unwrap();
+        let cube = engine.scene.add_model(model);
+        add_cube_grid(&mut engine, cube);
+        engine.scene.add_light(Light {
+            intensity,
+            ..Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0))
+        });
+        engine.tonemap = TonemapOptions {
+            auto_exposure: Some(AutoExposureOptions::default()),
+            ..Default::default()
+        };
+
+        let frame = render(&mut engine);
+        let sum: u64 = frame.pixels().map(|pixel| pixel[1] as u64).sum();
+        sum as f64 / frame.pixels().len() as f64
+    };
+
+    let (dim, bright) = (mean_brightness(0.25), mean_brightness(16.0));
+    assert!(
+        (dim - bright).abs() < 0.15 * dim.max(bright),
+        "dim {} vs bright {}",
+        dim,
+        bright
+    );
+}
@@ -0,0 +1,27 @@
+use bitter_engine::ibl::{
+    mip_level_count, prefiltered_roughness, ENVIRONMENT_RESOLUTION, PREFILTERED_MIP_LEVELS,
+    PREFILTERED_RESOLUTION,
+};
+
+#[test]
+fn mip_chains_end_at_one_texel() {
+    assert_eq!(mip_level_count(1), 1);
+    assert_eq!(mip_level_count(2), 2);
+    assert_eq!(mip_level_count(ENVIRONMENT_RESOLUTION), 10);
+    assert_eq!(
+        ENVIRONMENT_RESOLUTION >> (mip_level_count(ENVIRONMENT_RESOLUTION) - 1),
+        1
+    );
+    // Non power of two sizes round down.
+    assert_eq!(mip_level_count(600), 10);
+}
+
+#[test]
+fn prefiltered_mips_span_every_roughness() {
+    assert!(PREFILTERED_MIP_LEVELS <= mip_level_count(PREFILTERED_RESOLUTION));
+    assert_eq!(prefiltered_roughness(0), 0.0);
+    assert_eq!(prefiltered_roughness(PREFILTERED_MIP_LEVELS - 1), 1.0);
+    for level in 1..PREFILTERED_MIP_LEVELS {
+        assert!(prefiltered_roughness(level) > prefiltered_roughness(level - 1));
+    }
+}
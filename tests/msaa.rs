@@ -0,0 +1,36 @@
+use bitter_engine::{engine::SUPPORTED_SAMPLE_COUNTS, EngineOptions, ShadingPath};
+
+fn with_samples(sample_count: u32) -> EngineOptions {
+    EngineOptions {
+        sample_count,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn supported_sample_counts_are_accepted() {
+    for &sample_count in SUPPORTED_SAMPLE_COUNTS {
+        with_samples(sample_count).validate().unwrap();
+    }
+}
+
+#[test]
+fn other_sample_counts_are_rejected() {
+    for sample_count in [0, 2, 3, 8, 16] {
+        let message = with_samples(sample_count)
+            .validate()
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("MSAA"), "{}", message);
+    }
+}
+
+#[test]
+fn deferred_path_rejects_msaa() {
+    let options = EngineOptions {
+        shading_path: ShadingPath::Deferred,
+        ..with_samples(4)
+    };
+    let message = options.validate().unwrap_err().to_string();
+    assert!(message.contains("deferred"), "{}", message);
+}
@@ -0,0 +1,112 @@
+//! Tests for sharing pipelines through a `PipelineCache`.
+//!
+//! The tests need an adapter, falling back to a software one, so they are
+//! ignored by default: run them with `cargo test --test pipeline -- --ignored`.
+
+use std::sync::Arc;
+
+use bitter_engine::{
+    pipeline::{PipelineBuilder, PipelineCache},
+    Engine, EngineOptions,
+};
+
+const SHADER: &str = "
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn color() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
+";
+
+/// Creates a device on the default adapter, or on a software one if there is
+/// none.
+fn device() -> wgpu::Device {
+    pollster::block_on(Engine::new_headless(64, 64, &EngineOptions::default()))
+        .or_else(|e| {
+            eprintln!("{}, trying a fallback adapter", e);
+            let fallback = EngineOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            };
+            pollster::block_on(Engine::new_headless(64, 64, &fallback))
+        })
+        .expect("pipeline tests need an adapter")
+        .device
+}
+
+fn shader_and_layout(
+    device: &wgpu::Device,
+) -> (Arc<wgpu::ShaderModule>, Arc<wgpu::PipelineLayout>) {
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("pipeline test shader"),
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("pipeline test layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    (Arc::new(shader), Arc::new(layout))
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn identical_states_share_a_pipeline() {
+    let device = device();
+    let (shader, layout) = shader_and_layout(&device);
+    let opaque = PipelineBuilder::new("opaque", &layout, &shader)
+        .fragment("color")
+        .color_target(wgpu::TextureFormat::Rgba8Unorm, None);
+
+    let mut cache = PipelineCache::default();
+    let first = cache.get(&device, &opaque);
+    // Only the label differs.
+    let second = cache.get(&device, &opaque.clone().label("cutout"));
+
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn different_states_get_pipelines_of_their_own() {
+    let device = device();
+    let (shader, layout) = shader_and_layout(&device);
+    let opaque = PipelineBuilder::new("opaque", &layout, &shader)
+        .fragment("color")
+        .color_target(wgpu::TextureFormat::Rgba8Unorm, None);
+    let depth_tested = opaque.clone().depth(
+        wgpu::TextureFormat::Depth32Float,
+        true,
+        wgpu::CompareFunction::Less,
+    );
+
+    let mut cache = PipelineCache::default();
+    let builders = [
+        opaque.clone(),
+        opaque.clone().cull_mode(None),
+        opaque.clone().topology(wgpu::PrimitiveTopology::LineList),
+        PipelineBuilder::new("blended", &layout, &shader)
+            .fragment("color")
+            .color_target(
+                wgpu::TextureFormat::Rgba8Unorm,
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+            ),
+        depth_tested.clone(),
+        depth_tested.clone().depth_bias(wgpu::DepthBiasState {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0,
+        }),
+        depth_tested.sample_count(4),
+    ];
+    for builder in &builders {
+        cache.get(&device, builder);
+    }
+
+    assert_eq!(cache.len(), builders.len());
+}
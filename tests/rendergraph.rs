@@ -0,0 +1,125 @@
+use bitter_engine::rendergraph::{RenderGraph, TextureDesc};
+
+const HDR: TextureDesc = TextureDesc {
+    format: wgpu::TextureFormat::Rgba16Float,
+    sample_count: 1,
+};
+const MASK: TextureDesc = TextureDesc {
+    format: wgpu::TextureFormat::R8Unorm,
+    sample_count: 1,
+};
+
+#[test]
+fn passes_run_after_what_they_read() {
+    let mut graph = RenderGraph::new(64, 64);
+    let output = graph.import("output");
+    let scene = graph.create_texture("scene", HDR);
+    let shadows = graph.import("shadows");
+
+    let mut pass = graph.add_pass("shadows");
+    let shadows = pass.write(shadows);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("scene");
+    pass.read(shadows);
+    let scene = pass.write(scene);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("composite");
+    pass.read(scene);
+    pass.write(output);
+    pass.execute(|_| {});
+
+    assert_eq!(graph.compile().order, [0, 1, 2]);
+}
+
+#[test]
+fn the_next_write_waits_for_readers_of_the_last() {
+    let mut graph = RenderGraph::new(64, 64);
+    let output = graph.import("output");
+    let hdr = graph.import("hdr");
+    let luminance = graph.import("luminance");
+
+    let mut pass = graph.add_pass("lighting");
+    let lit = pass.write(hdr);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("bloom");
+    let bloomed = pass.write(lit);
+    pass.execute(|_| {});
+    // Reads the version from before bloom, so has to come first.
+    let mut pass = graph.add_pass("exposure");
+    pass.read(lit);
+    let luminance = pass.write(luminance);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("tonemap");
+    pass.read(bloomed).read(luminance);
+    pass.write(output);
+    pass.execute(|_| {});
+
+    assert_eq!(graph.compile().order, [0, 2, 1, 3]);
+}
+
+#[test]
+fn unused_passes_are_culled() {
+    let mut graph = RenderGraph::new(64, 64);
+    let output = graph.import("output");
+    let unused = graph.create_texture("unused", HDR);
+    let used = graph.create_texture("used", HDR);
+
+    let mut pass = graph.add_pass("unused");
+    pass.write(unused);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("used");
+    let used = pass.write(used);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("output");
+    pass.read(used);
+    pass.write(output);
+    pass.execute(|_| {});
+
+    let schedule = graph.compile();
+    assert_eq!(schedule.order, [1, 2]);
+    // Nothing is allocated for `unused`, the second resource.
+    assert_eq!(schedule.slots[1], None);
+}
+
+#[test]
+fn transients_share_textures_when_their_lifetimes_allow() {
+    let mut graph = RenderGraph::new(64, 64);
+    let output = graph.import("output");
+    let first = graph.create_texture("first", HDR);
+    let second = graph.create_texture("second", HDR);
+    let third = graph.create_texture("third", HDR);
+    let mask = graph.create_texture("mask", MASK);
+
+    let mut pass = graph.add_pass("a");
+    let first = pass.write(first);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("b");
+    pass.read(first);
+    let second = pass.write(second);
+    pass.execute(|_| {});
+    // `first` is done, so `third` can take its place, but `mask` has a
+    // different format.
+    let mut pass = graph.add_pass("c");
+    pass.read(second);
+    let third = pass.write(third);
+    let mask = pass.write(mask);
+    pass.execute(|_| {});
+    let mut pass = graph.add_pass("d");
+    pass.read(third).read(mask);
+    pass.write(output);
+    pass.execute(|_| {});
+
+    let schedule = graph.compile();
+    assert_eq!(schedule.order, [0, 1, 2, 3]);
+    assert_eq!(schedule.slots, [None, Some(0), Some(1), Some(0), Some(2)]);
+    assert_eq!(schedule.slot_descs, [HDR, HDR, MASK]);
+}
+
+#[test]
+#[should_panic(expected = "already written")]
+fn stale_versions_cannot_be_written() {
+    let mut graph = RenderGraph::new(64, 64);
+    let hdr = graph.import("hdr");
+    graph.add_pass("a").write(hdr);
+    graph.add_pass("b").write(hdr);
+}
@@ -0,0 +1,25 @@
+use bitter_engine::rendertarget::TargetSize;
+
+#[test]
+fn window_targets_follow_the_window() {
+    assert_eq!(TargetSize::FULL.resolve(1280, 720), (1280, 720));
+    assert_eq!(TargetSize::FULL.resolve(640, 480), (640, 480));
+}
+
+#[test]
+fn scaled_targets_round_and_keep_a_pixel() {
+    let half = TargetSize::Window { scale: 0.5 };
+    assert_eq!(half.resolve(1280, 720), (640, 360));
+    assert_eq!(half.resolve(1279, 719), (640, 360));
+    assert_eq!(half.resolve(1, 1), (1, 1));
+}
+
+#[test]
+fn fixed_targets_ignore_the_window() {
+    let fixed = TargetSize::Fixed {
+        width: 2048,
+        height: 1024,
+    };
+    assert_eq!(fixed.resolve(1280, 720), (2048, 1024));
+    assert_eq!(fixed.resolve(1, 1), (2048, 1024));
+}
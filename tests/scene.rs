@@ -0,0 +1,208 @@
+use bitter_engine::{
+    lighting::Light,
+    model::{GltfNode, GltfScene},
+    scene::{NodeContent, Scene, Transform},
+};
+use cgmath::{EuclideanSpace, Rotation3, Transform as _};
+
+fn assert_near(a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) {
+    assert!(
+        (a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5 && (a.z - b.z).abs() < 1e-5,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn world_transforms_follow_parents() {
+    let mut scene = Scene::new();
+    let parent = scene.add_node(
+        None,
+        "parent",
+        Transform {
+            translation: cgmath::Vector3::new(1.0, 0.0, 0.0),
+            scale: cgmath::Vector3::new(2.0, 2.0, 2.0),
+            ..Default::default()
+        },
+        NodeContent::Empty,
+    );
+    let child = scene.add_node(
+        Some(parent),
+        "child",
+        Transform::from_translation(cgmath::Vector3::new(0.0, 1.0, 0.0)),
+        NodeContent::Empty,
+    );
+
+    assert!(scene.update_world_transforms());
+    let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+    assert_near(
+        scene.node(child).world_matrix().transform_point(origin),
+        cgmath::Point3::new(1.0, 2.0, 0.0),
+    );
+
+    // Nothing is dirty, so nothing is recomputed.
+    assert!(!scene.update_world_transforms());
+
+    // Changing the parent moves the child.
+    scene.transform_mut(parent).rotation = cgmath::Quaternion::from_angle_z(cgmath::Deg(90.0));
+    assert!(scene.update_world_transforms());
+    assert_near(
+        scene.node(child).world_matrix().transform_point(origin),
+        cgmath::Point3::new(-1.0, 0.0, 0.0),
+    );
+}
+
+#[test]
+fn previous_world_matrices_lag_one_update() {
+    let mut scene = Scene::new();
+    let node = scene.add_node(
+        None,
+        "node",
+        Transform::from_translation(cgmath::Vector3::new(1.0, 0.0, 0.0)),
+        NodeContent::Empty,
+    );
+    let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+    let previous = |scene: &Scene| {
+        scene
+            .node(node)
+            .previous_world_matrix()
+            .transform_point(origin)
+    };
+
+    // A new node hasn't moved from anywhere.
+    scene.update_world_transforms();
+    assert_near(previous(&scene), cgmath::Point3::new(1.0, 0.0, 0.0));
+
+    scene.transform_mut(node).translation = cgmath::Vector3::new(3.0, 0.0, 0.0);
+    scene.update_world_transforms();
+    assert_near(previous(&scene), cgmath::Point3::new(1.0, 0.0, 0.0));
+
+    // Once it stops, the previous matrix catches up.
+    scene.update_world_transforms();
+    assert_near(previous(&scene), cgmath::Point3::new(3.0, 0.0, 0.0));
+}
+
+#[test]
+fn reparenting_and_lights() {
+    let mut scene = Scene::new();
+    let light = scene.add_light(Light::point(cgmath::Point3::new(0.0, 0.0, 0.0)));
+    let pivot = scene.add_node(
+        None,
+        "pivot",
+        Transform::from_translation(cgmath::Vector3::new(0.0, 5.0, 0.0)),
+        NodeContent::Empty,
+    );
+    let light_node = scene.add_node(
+        None,
+        "light",
+        Transform::from_translation(cgmath::Vector3::new(2.0, 0.0, 0.0)),
+        NodeContent::Light(light),
+    );
+
+    scene.update_world_transforms();
+    assert_near(
+        scene.light(light).position,
+        cgmath::Point3::new(2.0, 0.0, 0.0),
+    );
+
+    scene.set_parent(light_node, Some(pivot));
+    assert_eq!(scene.node(pivot).children(), &[light_node]);
+    scene.update_world_transforms();
+    assert_near(
+        scene.light(light).position,
+        cgmath::Point3::new(2.0, 5.0, 0.0),
+    );
+}
+
+#[test]
+fn rotated_parents_turn_spot_lights() {
+    let mut scene = Scene::new();
+    let light = scene.add_light(Light::spot(
+        cgmath::Point3::new(0.0, 0.0, 0.0),
+        cgmath::Vector3::new(1.0, 0.0, 0.0),
+        cgmath::Deg(20.0),
+        cgmath::Deg(30.0),
+    ));
+    let parent = scene.add_node(
+        None,
+        "parent",
+        Transform::from_translation(cgmath::Vector3::new(0.0, 4.0, 0.0)),
+        NodeContent::Empty,
+    );
+    scene.add_node(
+        Some(parent),
+        "spot",
+        Transform::from_translation(cgmath::Vector3::new(0.0, 0.0, 1.0)),
+        NodeContent::Light(light),
+    );
+
+    // Unrotated, the light points down its node's -Z axis.
+    scene.update_world_transforms();
+    assert_near(
+        cgmath::Point3::from_vec(scene.light(light).direction),
+        cgmath::Point3::new(0.0, 0.0, -1.0),
+    );
+
+    // Tilting the parent forward points the light at the ground.
+    scene.transform_mut(parent).rotation = cgmath::Quaternion::from_angle_x(cgmath::Deg(-90.0));
+    scene.update_world_transforms();
+    assert_near(
+        scene.light(light).position,
+        cgmath::Point3::new(0.0, 5.0, 0.0),
+    );
+    assert_near(
+        cgmath::Point3::from_vec(scene.light(light).direction),
+        cgmath::Point3::new(0.0, -1.0, 0.0),
+    );
+
+    // Nodes still move once their light is dropped from the scene.
+    scene.lights.clear();
+    scene.transform_mut(parent).translation = cgmath::Vector3::new(1.0, 0.0, 0.0);
+    assert!(scene.update_world_transforms());
+}
+
+#[test]
+fn gltf_hierarchy_is_recreated_under_parent() {
+    let mut scene = Scene::new();
+    let parent = scene.add_node(
+        None,
+        "import",
+        Transform::from_translation(cgmath::Vector3::new(0.0, 0.0, 1.0)),
+        NodeContent::Empty,
+    );
+    let gltf = GltfScene {
+        models: Vec::new(),
+        nodes: vec![
+            GltfNode {
+                name: "leaf".to_string(),
+                transform: Transform::from_translation(cgmath::Vector3::new(0.0, 3.0, 0.0)),
+                model: None,
+                children: Vec::new(),
+            },
+            GltfNode {
+                name: "root".to_string(),
+                transform: Transform::from_translation(cgmath::Vector3::new(1.0, 0.0, 0.0)),
+                model: None,
+                children: vec![0],
+            },
+        ],
+        roots: vec![1],
+    };
+
+    let roots = scene.add_gltf(gltf, Some(parent));
+    assert_eq!(roots.len(), 1);
+    assert_eq!(scene.node(roots[0]).name, "root");
+    assert_eq!(scene.node(roots[0]).parent(), Some(parent));
+
+    let leaf = scene.node(roots[0]).children()[0];
+    assert_eq!(scene.node(leaf).name, "leaf");
+    scene.update_world_transforms();
+    assert_near(
+        scene
+            .node(leaf)
+            .world_matrix()
+            .transform_point(cgmath::Point3::new(0.0, 0.0, 0.0)),
+        cgmath::Point3::new(1.0, 3.0, 1.0),
+    );
+}
@@ -0,0 +1,263 @@
+use bitter_engine::{
+    camera::Camera,
+    lighting::Light,
+    shadow::{self, ShadowFilter, ShadowOptions},
+};
+use cgmath::{Matrix4, Transform};
+
+fn camera(eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>) -> Camera {
+    Camera {
+        eye,
+        target,
+        up: cgmath::Vector3::unit_y(),
+        aspect: 4.0 / 3.0,
+        fovy: 45.0,
+        znear: 0.1,
+        zfar: 100.0,
+        jitter: cgmath::Vector2::new(0.0, 0.0),
+    }
+}
+
+/// Where `point` lands in the shadow map, in texels.
+fn texel_position(
+    view_proj: Matrix4<f32>,
+    point: cgmath::Point3<f32>,
+    resolution: u32,
+) -> [f32; 2] {
+    let ndc = view_proj.transform_point(point);
+    [
+        ndc.x * resolution as f32 / 2.0,
+        ndc.y * resolution as f32 / 2.0,
+    ]
+}
+
+#[test]
+fn cascade_splits_cover_the_range() {
+    let splits = shadow::cascade_splits(0.1, 60.0, 4, 0.75);
+    assert_eq!(splits.len(), 4);
+    assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!((splits[3] - 60.0).abs() < 1e-3);
+
+    let uniform = shadow::cascade_splits(1.0, 41.0, 4, 0.0);
+    for (split, expected) in uniform.iter().zip([11.0, 21.0, 31.0, 41.0].iter()) {
+        assert!((split - expected).abs() < 1e-4, "{} != {}", split, expected);
+    }
+}
+
+#[test]
+fn cascades_move_in_whole_texels() {
+    let direction = cgmath::Vector3::new(-1.0, -2.0, 1.0);
+    let resolution = 1024;
+    let point = cgmath::Point3::new(3.0, 0.5, -2.0);
+
+    let before = camera(
+        cgmath::Point3::new(0.0, 5.0, -10.0),
+        cgmath::Point3::new(0.0, 0.0, 0.0),
+    );
+    let offset = cgmath::Vector3::new(0.0123, 0.0, 0.0371);
+    let after = camera(before.eye + offset, before.target + offset);
+
+    let a = shadow::cascade_view_proj(&before, 0.1, 10.0, direction, resolution);
+    let b = shadow::cascade_view_proj(&after, 0.1, 10.0, direction, resolution);
+    let [ax, ay] = texel_position(a, point, resolution);
+    let [bx, by] = texel_position(b, point, resolution);
+    for delta in [ax - bx, ay - by].iter() {
+        assert!(
+            (delta - delta.round()).abs() < 1e-2,
+            "moved {} texels",
+            delta
+        );
+    }
+}
+
+#[test]
+fn cascade_size_ignores_camera_rotation() {
+    let direction = cgmath::Vector3::new(0.0, -1.0, 0.5);
+    let eye = cgmath::Point3::new(0.0, 5.0, -10.0);
+    let a = shadow::cascade_view_proj(
+        &camera(eye, cgmath::Point3::new(0.0, 0.0, 0.0)),
+        1.0,
+        20.0,
+        direction,
+        2048,
+    );
+    let b = shadow::cascade_view_proj(
+        &camera(eye, cgmath::Point3::new(8.0, 2.0, 0.0)),
+        1.0,
+        20.0,
+        direction,
+        2048,
+    );
+
+    assert!((a.x.x - b.x.x).abs() < 1e-6);
+    assert!((a.y.y - b.y.y).abs() < 1e-6);
+}
+
+#[test]
+fn only_directional_lights_are_cascaded() {
+    let camera = camera(
+        cgmath::Point3::new(0.0, 5.0, -10.0),
+        cgmath::Point3::new(0.0, 0.0, 0.0),
+    );
+    let options = ShadowOptions::default();
+
+    let spot = Light::spot(
+        cgmath::Point3::new(2.0, 5.0, 2.0),
+        cgmath::Vector3::new(0.0, -1.0, 0.0),
+        cgmath::Deg(20.0),
+        cgmath::Deg(30.0),
+    );
+    let uniform = shadow::shadow_uniform(&camera, &spot, &options);
+    assert_eq!(uniform.cascade_count, 1);
+    assert_eq!(uniform.cascades[0], spot.to_raw().proj);
+
+    let sun = Light::directional(cgmath::Vector3::new(-1.0, -2.0, 1.0));
+    let uniform = shadow::shadow_uniform(&camera, &sun, &options);
+    assert_eq!(uniform.cascade_count, options.cascades);
+    assert!((uniform.splits[options.cascades as usize - 1] - options.max_distance).abs() < 1e-3);
+}
+
+#[test]
+fn spot_light_matrices_stay_finite() {
+    let position = cgmath::Point3::new(0.0, 2.0, 0.0);
+    let spots = [
+        // Along the axis the up vector used to be.
+        Light::spot(
+            position,
+            cgmath::Vector3::unit_z(),
+            cgmath::Deg(20.0),
+            cgmath::Deg(30.0),
+        ),
+        Light::spot(
+            position,
+            -cgmath::Vector3::unit_z(),
+            cgmath::Deg(20.0),
+            cgmath::Deg(30.0),
+        ),
+        // Wide enough to need 180 degrees or more of field of view.
+        Light::spot(
+            position,
+            -cgmath::Vector3::unit_y(),
+            cgmath::Deg(60.0),
+            cgmath::Deg(90.0),
+        ),
+        Light::spot(
+            position,
+            cgmath::Vector3::unit_x(),
+            cgmath::Deg(90.0),
+            cgmath::Deg(120.0),
+        ),
+    ];
+    for spot in &spots {
+        let proj = spot.to_raw().proj;
+        assert!(
+            proj.iter().flatten().all(|value| value.is_finite()),
+            "{:?}",
+            spot
+        );
+    }
+}
+
+#[test]
+fn point_lights_cast_into_cubes() {
+    let point = |x| Light::point(cgmath::Point3::new(x, 1.0, 0.0));
+    let lights = vec![
+        Light::directional(cgmath::Vector3::new(0.0, -1.0, 0.0)),
+        point(0.0),
+        Light {
+            cast_shadows: false,
+            ..point(1.0)
+        },
+        point(2.0),
+        point(3.0),
+        point(4.0),
+        point(5.0),
+    ];
+
+    assert_eq!(shadow::shadow_caster(&lights), Some(0));
+    assert_eq!(
+        shadow::point_shadow_casters(&lights),
+        vec![1, 3, 4, 5, 6][..shadow::MAX_POINT_SHADOWS].to_vec()
+    );
+    assert_eq!(shadow::shadow_caster(&lights[1..]), None);
+}
+
+#[test]
+fn cube_faces_look_along_the_axes() {
+    let position = cgmath::Point3::new(1.0, 2.0, 3.0);
+    let range = 10.0;
+    let faces = shadow::cube_face_view_projs(position, range);
+    let axes = [
+        cgmath::Vector3::unit_x(),
+        -cgmath::Vector3::unit_x(),
+        cgmath::Vector3::unit_y(),
+        -cgmath::Vector3::unit_y(),
+        cgmath::Vector3::unit_z(),
+        -cgmath::Vector3::unit_z(),
+    ];
+
+    for (face, axis) in faces.iter().zip(axes.iter()) {
+        let ndc = face.transform_point(position + axis * 5.0);
+        assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5, "{:?}", ndc);
+        assert!(ndc.z > 0.0 && ndc.z < 1.0, "{:?}", ndc);
+    }
+
+    // On the +X face, cube map coordinates put +Y at the top and +Z on the
+    // left.
+    let ndc = faces[0].transform_point(position + cgmath::Vector3::new(5.0, 1.0, 1.0));
+    assert!(ndc.y > 0.0 && ndc.x < 0.0, "{:?}", ndc);
+}
+
+#[test]
+fn filter_settings_are_clamped() {
+    let pcf = ShadowFilter::Pcf {
+        kernel: 100,
+        bias: 0.001,
+    }
+    .uniform();
+    assert_eq!(pcf.samples, shadow::MAX_PCF_KERNEL);
+    assert_eq!(pcf.bias, 0.001);
+
+    let pcss = ShadowFilter::Pcss {
+        light_size: 8.0,
+        samples: 0,
+        bias: 0.0,
+    }
+    .uniform();
+    assert_eq!(pcss.samples, 1);
+    assert_eq!(pcss.radius, 8.0);
+
+    let evsm = ShadowFilter::Evsm {
+        kernel: 3,
+        exponents: [40.0, 2.0],
+        min_variance: 0.0001,
+        light_bleed: 1.5,
+    }
+    .uniform();
+    assert_eq!(evsm.exponents, [shadow::EVSM_MAX_EXPONENT, 2.0]);
+    assert!(evsm.light_bleed < 1.0);
+}
+
+#[test]
+fn moments_grow_with_depth() {
+    let vsm = ShadowFilter::Vsm {
+        kernel: 1,
+        min_variance: 0.0,
+        light_bleed: 0.0,
+    };
+    assert_eq!(vsm.moments(0.5), [0.5, 0.25, 0.0, 0.0]);
+
+    let evsm = ShadowFilter::Evsm {
+        kernel: 1,
+        exponents: [shadow::EVSM_MAX_EXPONENT; 2],
+        min_variance: 0.0,
+        light_bleed: 0.0,
+    };
+    let near = evsm.moments(0.25);
+    let far = evsm.moments(1.0);
+    assert!(near[0] < far[0] && near[2] < far[2]);
+    // The far plane's squared warp must still fit in a half float.
+    assert!(far[1] <= 65504.0);
+    assert!(!ShadowFilter::default().uses_moments());
+    assert!(evsm.uses_moments());
+}
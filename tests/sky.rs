@@ -0,0 +1,31 @@
+use bitter_engine::sky::AtmosphereOptions;
+
+fn transmittance_towards(x: f32, y: f32, z: f32) -> [f32; 3] {
+    AtmosphereOptions {
+        sun_direction: cgmath::Vector3::new(x, y, z),
+        ..Default::default()
+    }
+    .sun_transmittance()
+}
+
+#[test]
+fn overhead_sun_is_barely_dimmed() {
+    let [r, g, b] = transmittance_towards(0.0, 1.0, 0.0);
+    assert!(r > 0.9 && r <= 1.0, "{}", r);
+    assert!(g > 0.85 && g < r, "{}", g);
+    assert!(b > 0.7 && b < g, "{}", b);
+}
+
+#[test]
+fn low_sun_turns_red() {
+    let high = transmittance_towards(0.0, 1.0, 0.0);
+    let [r, g, b] = transmittance_towards(1.0, 0.02, 0.0);
+    assert!(r < high[0] && g < high[1] && b < high[2]);
+    // Blue is scattered away first, so what is left is mostly red.
+    assert!(r > 2.0 * g && g > 2.0 * b, "{:?}", [r, g, b]);
+}
+
+#[test]
+fn sun_below_the_horizon_gives_no_light() {
+    assert_eq!(transmittance_towards(1.0, -0.1, 0.0), [0.0; 3]);
+}
@@ -0,0 +1,47 @@
+use bitter_engine::ssao::{hemisphere_kernel, noise_rotations, MAX_SSAO_SAMPLES};
+
+#[test]
+fn kernel_stays_in_the_hemisphere() {
+    for sample in hemisphere_kernel(MAX_SSAO_SAMPLES) {
+        let [x, y, z, _] = sample;
+        let length = (x * x + y * y + z * z).sqrt();
+        assert!(z > 0.0, "{:?}", sample);
+        assert!(length > 0.0 && length <= 1.0, "{:?}", sample);
+    }
+}
+
+#[test]
+fn kernel_grows_outwards() {
+    for sample_count in [1, 4, 16, MAX_SSAO_SAMPLES] {
+        let kernel = hemisphere_kernel(sample_count);
+        let length = |i: usize| {
+            let [x, y, z, _] = kernel[i];
+            (x * x + y * y + z * z).sqrt()
+        };
+        let last = sample_count as usize - 1;
+        for i in 1..=last {
+            assert!(length(i) >= length(i - 1) - 1e-6);
+        }
+        // Whatever the count, the samples reach the radius, and the unused
+        // ones stay out of the way.
+        assert!(length(last) > 0.9, "{} samples", sample_count);
+        assert!(kernel[last + 1..].iter().all(|&sample| sample == [0.0; 4]));
+    }
+    // With enough of them, the first samples hug the center.
+    assert!(hemisphere_kernel(MAX_SSAO_SAMPLES)[0][2] < 0.2);
+}
+
+#[test]
+fn noise_rotates_in_the_tangent_plane() {
+    let noise = noise_rotations();
+    for [x, y, z, _] in noise {
+        assert!(((x * x + y * y).sqrt() - 1.0).abs() < 1e-5);
+        assert_eq!(z, 0.0);
+    }
+    // No two texels share a rotation.
+    for (i, a) in noise.iter().enumerate() {
+        for b in &noise[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
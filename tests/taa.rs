@@ -0,0 +1,30 @@
+use bitter_engine::taa::{halton, jitter, JITTER_SAMPLES};
+
+#[test]
+fn halton_sequence_radical_inverse() {
+    assert_eq!(halton(1, 2), 0.5);
+    assert_eq!(halton(2, 2), 0.25);
+    assert_eq!(halton(3, 2), 0.75);
+    assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+    assert!((halton(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+}
+
+#[test]
+fn jitter_stays_within_half_a_pixel() {
+    let (width, height) = (640, 480);
+    let mut sum = cgmath::Vector2::new(0.0, 0.0);
+    for frame in 0..JITTER_SAMPLES {
+        let offset = jitter(frame, width, height);
+        // One pixel is 2 / size in normalized device coordinates.
+        assert!(offset.x.abs() < 1.0 / width as f32, "{:?}", offset);
+        assert!(offset.y.abs() < 1.0 / height as f32, "{:?}", offset);
+        sum += offset;
+    }
+    // The sequence is centered on the pixel.
+    assert!(sum.x.abs() < 1.0 / width as f32);
+    assert!(sum.y.abs() < 1.0 / height as f32);
+    assert_eq!(
+        jitter(0, width, height),
+        jitter(JITTER_SAMPLES, width, height)
+    );
+}
@@ -0,0 +1,95 @@
+use bitter_engine::{
+    camera::Camera,
+    model::{AlphaMode, MaterialFactors},
+    transparency::{sort_back_to_front, TransparentDraw},
+};
+
+fn draw(instance: u32, view_depth: f32) -> TransparentDraw {
+    TransparentDraw {
+        model: 0,
+        mesh: 0,
+        instance,
+        view_depth,
+    }
+}
+
+#[test]
+fn farthest_draws_come_first() {
+    let mut draws = vec![draw(0, 2.0), draw(1, 8.0), draw(2, -1.0), draw(3, 5.0)];
+    sort_back_to_front(&mut draws);
+    let order = draws.iter().map(|draw| draw.instance).collect::<Vec<_>>();
+    assert_eq!(order, [1, 3, 0, 2]);
+}
+
+#[test]
+fn draws_at_the_same_depth_keep_their_order() {
+    let mut draws = vec![draw(0, 3.0), draw(1, 3.0), draw(2, 4.0), draw(3, 3.0)];
+    sort_back_to_front(&mut draws);
+    let order = draws.iter().map(|draw| draw.instance).collect::<Vec<_>>();
+    assert_eq!(order, [2, 0, 1, 3]);
+}
+
+#[test]
+fn view_depth_is_measured_along_the_view_direction() {
+    let camera = Camera {
+        eye: (0.0, 0.0, 0.0).into(),
+        target: (0.0, 0.0, -1.0).into(),
+        up: cgmath::Vector3::unit_y(),
+        aspect: 4.0 / 3.0,
+        fovy: 45.0,
+        znear: 0.1,
+        zfar: 100.0,
+        jitter: cgmath::Vector2::new(0.0, 0.0),
+    };
+    assert_eq!(camera.view_depth((0.0, 0.0, -4.0).into()), 4.0);
+    // Off to the side at the same depth.
+    assert_eq!(camera.view_depth((3.0, 2.0, -4.0).into()), 4.0);
+    assert_eq!(camera.view_depth((0.0, 0.0, 1.0).into()), -1.0);
+}
+
+fn mtl(statements: &[(&str, &str)], dissolve: f32) -> tobj::Material {
+    let mut material = tobj::Material {
+        dissolve,
+        ..Default::default()
+    };
+    for (key, value) in statements {
+        material
+            .unknown_param
+            .insert(key.to_string(), value.to_string());
+    }
+    material
+}
+
+#[test]
+fn solid_mtl_materials_are_opaque() {
+    let factors = MaterialFactors::from_mtl(&mtl(&[], 1.0));
+    assert_eq!(factors.base_color[3], 1.0);
+    assert_eq!(factors.alpha_mode, AlphaMode::Opaque);
+}
+
+#[test]
+fn mtl_dissolve_blends() {
+    let factors = MaterialFactors::from_mtl(&mtl(&[], 0.25));
+    assert_eq!(factors.base_color[3], 0.25);
+    assert_eq!(factors.alpha_mode, AlphaMode::Blend);
+}
+
+#[test]
+fn mtl_transparency_is_the_complement_of_dissolve() {
+    let factors = MaterialFactors::from_mtl(&mtl(&[("Tr", "0.75")], 1.0));
+    assert_eq!(factors.base_color[3], 0.25);
+    assert_eq!(factors.alpha_mode, AlphaMode::Blend);
+    // `d` wins when both are given.
+    let factors = MaterialFactors::from_mtl(&mtl(&[("Tr", "0.75")], 0.5));
+    assert_eq!(factors.base_color[3], 0.5);
+}
+
+#[test]
+fn mtl_alpha_map_makes_a_cutout() {
+    let material = tobj::Material {
+        dissolve_texture: "leaves.png".to_string(),
+        ..mtl(&[], 1.0)
+    };
+    let factors = MaterialFactors::from_mtl(&material);
+    assert_eq!(factors.alpha_mode, AlphaMode::Mask { cutoff: 0.5 });
+}